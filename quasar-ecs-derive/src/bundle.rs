@@ -1,5 +1,6 @@
 use darling::{
     ast::Data,
+    util::Flag,
     FromDeriveInput,
     FromField,
 };
@@ -9,10 +10,10 @@ use quote::{
     quote_spanned,
 };
 use syn::{
+    parse_quote_spanned,
     Generics,
     Ident,
     Type,
-    Visibility,
 };
 
 use crate::util::{
@@ -25,21 +26,21 @@ use crate::util::{
 #[darling(attributes(quasar), forward_attrs(allow, doc, cfg))]
 pub struct DeriveBundle {
     ident: Ident,
-    vis: Visibility,
     generics: Generics,
     data: Data<(), BundleField>,
 }
 
 #[derive(Clone, Debug, FromField)]
+#[darling(attributes(quasar))]
 struct BundleField {
     ident: Option<Ident>,
     ty: Type,
-    bundle: bool,
+    /// The field is itself a bundle, rather than a component.
+    bundle: Flag,
 }
 
 impl Deriver for DeriveBundle {
     fn generate_code(self) -> Result<TokenStream, Error> {
-        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
         let ident = &self.ident;
 
         let fields = self
@@ -49,12 +50,17 @@ impl Deriver for DeriveBundle {
 
         let (_style, fields) = fields.split();
 
-        let mut num_components_from_components = 0;
-        let mut num_components_from_bundles = Vec::with_capacity(fields.len());
+        // bound each field, so that generic fields work, and a field that isn't a
+        // component (or bundle) is reported once, at the field.
+        let mut generics = self.generics.clone();
+        let where_clause = generics.make_where_clause();
+
+        let mut num_components = Vec::with_capacity(fields.len());
         let mut component_types = Vec::with_capacity(fields.len());
         let mut from_components = Vec::with_capacity(fields.len());
         let mut from_components_constructor = Vec::with_capacity(fields.len());
         let mut into_components = Vec::with_capacity(fields.len());
+        let mut component_refs = Vec::with_capacity(fields.len());
 
         for (index, field) in fields.iter().enumerate() {
             let field_name = FieldName::new(index, field.ident.as_ref(), &field.ty);
@@ -62,36 +68,54 @@ impl Deriver for DeriveBundle {
             let var_name = &field_name.var;
             let field_ty = &field_name.ty;
 
-            if field.bundle {
-                num_components_from_bundles.push(quote_spanned! {
+            if field.bundle.is_present() {
+                where_clause.predicates.push(parse_quote_spanned! {
+                    field_name.span => #field_ty: ::quasar_ecs::Bundle
+                });
+
+                num_components.push(quote_spanned! {
                     field_name.span => <#field_ty as ::quasar_ecs::Bundle>::NUM_COMPONENTS
                 });
 
-                component_types.push(quote_spanned!{
+                component_types.push(quote_spanned! {
                     field_name.span => <#field_ty as ::quasar_ecs::Bundle>::component_types(&mut callback);
                 });
 
-                from_components.push(quote_spanned!{
+                from_components.push(quote_spanned! {
                     field_name.span => let #var_name = <#field_ty as ::quasar_ecs::Bundle>::from_components(&mut callback);
                 });
 
-                into_components.push(quote_spanned!{
-                    field_name.span => ::quasar_ecs::Bundle::into_each_component(self.#member, &mut callback);
+                into_components.push(quote_spanned! {
+                    field_name.span => <#field_ty as ::quasar_ecs::Bundle>::into_components(self.#member, &mut callback);
+                });
+
+                component_refs.push(quote_spanned! {
+                    field_name.span => <#field_ty as ::quasar_ecs::Bundle>::component_refs(&self.#member, &mut callback);
                 });
             }
             else {
-                num_components_from_components += 1;
+                where_clause.predicates.push(parse_quote_spanned! {
+                    field_name.span => #field_ty: ::quasar_ecs::Component
+                });
 
-                component_types.push(quote_spanned!{
-                    field_name.span => ::quasar_ecs::bundle_impl::ComponentTypesCallback::call(&mut callback, &self.#member);
+                num_components.push(quote_spanned! {
+                    field_name.span => 1
                 });
 
-                from_components.push(quote_spanned!{
-                    field_name.span => let #var_name = ::quasar_ecs::bundle_impl::FromComponentsCallback::call(&mut callback);
+                component_types.push(quote_spanned! {
+                    field_name.span => ::quasar_ecs::bundle_impl::ComponentTypesCallback::call::<#field_ty>(&mut callback);
                 });
 
-                into_components.push(quote_spanned!{
-                    field_name.span => ::quasar_ecs::bundle_impl::IntoComponentsCallback::call(&mut callback, self.#member);
+                from_components.push(quote_spanned! {
+                    field_name.span => let #var_name = ::quasar_ecs::bundle_impl::FromComponentsCallback::call::<#field_ty>(&mut callback);
+                });
+
+                into_components.push(quote_spanned! {
+                    field_name.span => ::quasar_ecs::bundle_impl::IntoComponentsCallback::call::<#field_ty>(&mut callback, self.#member);
+                });
+
+                component_refs.push(quote_spanned! {
+                    field_name.span => ::quasar_ecs::bundle_impl::ComponentRefsCallback::call::<#field_ty>(&mut callback, &self.#member);
                 });
             }
 
@@ -100,16 +124,18 @@ impl Deriver for DeriveBundle {
             });
         }
 
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
         Ok(quote! {
             #[automatically_derived]
-            impl #impl_generics ::quasar_ecs::Bundle for #ident #ty_generics #where_clause {
-                const NUM_COMPONENTS: usize = #num_components_from_components #(+ #num_components_from_bundles)*;
+            unsafe impl #impl_generics ::quasar_ecs::Bundle for #ident #ty_generics #where_clause {
+                const NUM_COMPONENTS: usize = 0 #(+ #num_components)*;
 
-                fn component_types<C: ::quasar_ecs::bundle_impl::ComponentTypesCallback>(&self, mut callback: C) {
+                fn component_types<C: ::quasar_ecs::bundle_impl::ComponentTypesCallback>(mut callback: C) {
                     #(#component_types)*
                 }
 
-                fn from_components<C: ::quasar_ecs::bundle_impl::FromComponentsCallback>(self, mut callback: C) {
+                fn from_components<C: ::quasar_ecs::bundle_impl::FromComponentsCallback>(mut callback: C) -> Self {
                     #(#from_components)*
                     Self {
                         #(#from_components_constructor)*
@@ -119,6 +145,10 @@ impl Deriver for DeriveBundle {
                 fn into_components<C: ::quasar_ecs::bundle_impl::IntoComponentsCallback>(self, mut callback: C) {
                     #(#into_components)*
                 }
+
+                fn component_refs<C: ::quasar_ecs::bundle_impl::ComponentRefsCallback>(&self, mut callback: C) {
+                    #(#component_refs)*
+                }
             }
         })
     }
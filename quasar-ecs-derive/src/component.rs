@@ -1,5 +1,8 @@
 use darling::{
-    util::SpannedValue,
+    util::{
+        Flag,
+        SpannedValue,
+    },
     FromDeriveInput,
     FromMeta,
 };
@@ -7,10 +10,22 @@ use proc_macro2::TokenStream;
 use quote::{
     quote,
     quote_spanned,
+    ToTokens,
 };
 use syn::{
+    parse::{
+        Parse,
+        ParseStream,
+    },
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Expr,
     Generics,
     Ident,
+    Meta,
+    Path,
+    Token,
+    Type,
 };
 
 use crate::util::{
@@ -25,6 +40,54 @@ pub struct DeriveComponent {
     generics: Generics,
     #[darling(default)]
     storage: SpannedValue<StorageType>,
+    clone: Flag,
+    immutable: Flag,
+    on_add: Option<Path>,
+    on_replace: Option<Path>,
+    on_remove: Option<Path>,
+    #[darling(default, multiple)]
+    require: Vec<Require>,
+}
+
+/// `require(Transform, Visibility = default_visibility)`
+#[derive(Clone, Debug)]
+struct Require {
+    components: Vec<RequiredComponent>,
+}
+
+impl FromMeta for Require {
+    fn from_meta(item: &Meta) -> darling::Result<Self> {
+        let Meta::List(list) = item
+        else {
+            return Err(darling::Error::unsupported_format("non-list").with_span(item));
+        };
+        let components = list
+            .parse_args_with(Punctuated::<RequiredComponent, Token![,]>::parse_terminated)?
+            .into_iter()
+            .collect();
+        Ok(Self { components })
+    }
+}
+
+/// `Visibility` or `Visibility = default_visibility`
+#[derive(Clone, Debug)]
+struct RequiredComponent {
+    ty: Type,
+    constructor: Option<Expr>,
+}
+
+impl Parse for RequiredComponent {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        let constructor = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        }
+        else {
+            None
+        };
+        Ok(Self { ty, constructor })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, FromMeta)]
@@ -33,6 +96,7 @@ enum StorageType {
     Table,
     SparseSet,
     BitSet,
+    Stable,
 }
 
 impl Deriver for DeriveComponent {
@@ -55,13 +119,107 @@ impl Deriver for DeriveComponent {
                     self.storage.span() => ::quasar_ecs::StorageType::BitSet
                 }
             }
+            StorageType::Stable => {
+                quote_spanned! {
+                    self.storage.span() => ::quasar_ecs::StorageType::Stable
+                }
+            }
         };
 
+        let clone_fn = self.clone.is_present().then(|| {
+            quote_spanned! {
+                self.clone.span() =>
+                const CLONE_FN: ::std::option::Option<::quasar_ecs::CloneFn> =
+                    ::std::option::Option::Some(::quasar_ecs::component_impl::clone_ptr::<Self>);
+            }
+        });
+
+        let (mutable, mutable_impl) = if self.immutable.is_present() {
+            (
+                Some(quote_spanned! {
+                    self.immutable.span() => const MUTABLE: bool = false;
+                }),
+                None,
+            )
+        }
+        else {
+            (
+                None,
+                Some(quote! {
+                    #[automatically_derived]
+                    unsafe impl #impl_generics ::quasar_ecs::MutableComponent for #ident #ty_generics #where_clause {}
+                }),
+            )
+        };
+
+        let hook = |name: TokenStream, path: &Option<Path>| {
+            path.as_ref().map(|path| {
+                quote_spanned! {
+                    path.span() =>
+                    const #name: ::std::option::Option<::quasar_ecs::ComponentHook> =
+                        ::std::option::Option::Some(#path);
+                }
+            })
+        };
+        let on_add = hook(quote! { ON_ADD }, &self.on_add);
+        let on_replace = hook(quote! { ON_REPLACE }, &self.on_replace);
+        let on_remove = hook(quote! { ON_REMOVE }, &self.on_remove);
+
+        let register_required = self.generate_register_required()?;
+
         Ok(quote! {
             #[automatically_derived]
             impl #impl_generics ::quasar_ecs::Component for #ident #ty_generics #where_clause {
                 const STORAGE_TYPE: ::quasar_ecs::StorageType = #storage;
+                #clone_fn
+                #mutable
+                #on_add
+                #on_replace
+                #on_remove
+                #register_required
             }
+
+            #mutable_impl
         })
     }
 }
+
+impl DeriveComponent {
+    fn generate_register_required(&self) -> Result<Option<TokenStream>, Error> {
+        if self.require.is_empty() {
+            return Ok(None);
+        }
+
+        let mut seen = vec![];
+        let mut adds = vec![];
+        for required in self.require.iter().flat_map(|require| &require.components) {
+            let ty = &required.ty;
+            let ty_string = ty.to_token_stream().to_string();
+
+            if ty_string == "Self" || self.ident == ty_string {
+                return Err(Error::new(ty.span(), "a component can't require itself"));
+            }
+            if seen.contains(&ty_string) {
+                return Err(Error::new(
+                    ty.span(),
+                    format!("`{ty_string}` is required more than once"),
+                ));
+            }
+            seen.push(ty_string);
+
+            let constructor = match &required.constructor {
+                Some(constructor) => constructor.to_token_stream(),
+                None => quote_spanned! { ty.span() => <#ty as ::std::default::Default>::default },
+            };
+            adds.push(quote_spanned! {
+                ty.span() => required.add::<#ty>(#constructor);
+            });
+        }
+
+        Ok(Some(quote! {
+            fn register_required(required: &mut ::quasar_ecs::RequiredComponents) {
+                #(#adds)*
+            }
+        }))
+    }
+}
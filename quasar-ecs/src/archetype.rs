@@ -45,6 +45,9 @@ pub struct Archetype {
     table_id: TableId,
     entities: Vec<ArchetypeEntity>,
     components: ImmutableSparseMap<ComponentId, ArchetypeComponentInfo>,
+    /// The components that represent the values of shared components, by the
+    /// ids of their [`Shared`](crate::Shared) markers.
+    shared: ImmutableSparseMap<ComponentId, ComponentId>,
     edges: Edges,
 }
 
@@ -55,6 +58,18 @@ impl Archetype {
         ArchetypeRow::from_index(index)
     }
 
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    pub fn set_table_row(&mut self, archetype_row: ArchetypeRow, table_row: TableRow) {
+        self.entities[archetype_row.index()].table_row = table_row;
+    }
+
     pub fn remove_entity(
         &mut self,
         archetype_row: ArchetypeRow,
@@ -79,6 +94,10 @@ impl Archetype {
         self.id
     }
 
+    pub fn entities(&self) -> &[ArchetypeEntity] {
+        &self.entities
+    }
+
     pub fn table_id(&self) -> TableId {
         self.table_id
     }
@@ -87,6 +106,22 @@ impl Archetype {
         self.components.contains_key(&component_id)
     }
 
+    /// Returns the id of the component that represents the value of a shared
+    /// component, given the id of its [`Shared`](crate::Shared) marker.
+    pub fn shared_value(&self, shared_id: ComponentId) -> Option<ComponentId> {
+        self.shared.get(&shared_id).copied()
+    }
+
+    /// Returns the edge for adding the bundle to this archetype, if it was
+    /// already created.
+    pub fn get_add_bundle(&self, bundle_id: BundleId) -> Option<&AddBundle> {
+        self.edges.add_bundle.get(&bundle_id)
+    }
+
+    pub fn component_ids(&self) -> impl Iterator<Item = ComponentId> + use<'_> {
+        self.components.keys()
+    }
+
     pub fn add_bundle(&self, bundle_id: BundleId) -> Option<&AddBundle> {
         self.edges.add_bundle.get(&bundle_id)
     }
@@ -107,6 +142,10 @@ impl ArchetypeId {
     fn from_index(index: usize) -> Self {
         Self(index.try_into().expect("ArchetypeId overflow"))
     }
+
+    pub fn is_invalid(&self) -> bool {
+        *self == Self::INVALID
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -169,6 +208,7 @@ impl Default for Archetypes {
                 table_id: TableId::EMPTY,
                 entities: vec![],
                 components: ImmutableSparseMap::default(),
+                shared: ImmutableSparseMap::default(),
                 edges: Edges::default(),
             }],
             by_components: {
@@ -190,6 +230,18 @@ impl Archetypes {
         &mut self.archetypes[archetype_id.index()]
     }
 
+    /// Removes all entities from all archetypes.
+    pub fn clear_entities(&mut self) {
+        for archetype in &mut self.archetypes {
+            archetype.clear();
+        }
+    }
+
+    /// Removes all archetypes and their edges, except the empty archetype.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
     pub fn iter(&self) -> ArchetypesIter {
         ArchetypesIter {
             iter: self.archetypes.iter(),
@@ -227,12 +279,19 @@ impl Archetypes {
             })
     }
 
-    pub fn add_bundle<'i, 'b>(
+    /// Follows the `AddBundle` edge for `bundle_info` from the archetype
+    /// `archetype_id`, creating the edge and the resulting archetype if
+    /// necessary.
+    ///
+    /// Returns `None` if the bundle is empty. Otherwise this returns the pair
+    /// of archetypes, or just the archetype with `archetype_id` if adding
+    /// the bundle doesn't change it.
+    pub fn add_bundle(
         &mut self,
         archetype_id: ArchetypeId,
         bundle_info: &BundleInfo,
         create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&mut Archetype, &mut Archetype)> {
+    ) -> Option<ArchetypePair> {
         if bundle_info.is_empty() {
             // inserting an empty bundle doesn't do anything
             return None;
@@ -244,68 +303,99 @@ impl Archetypes {
 
         // get the archetype id for the resulting archetype. creating the edge in the
         // process
-        let to_archetype_id =
-            if let Some(add_bundle) = from_archetype.edges.add_bundle.get(&bundle_info.id()) {
-                // an edge already exists
-                add_bundle.archetype_id
+        let to_archetype_id = if let Some(add_bundle) =
+            from_archetype.edges.add_bundle.get(&bundle_info.id())
+        {
+            // an edge already exists
+            add_bundle.archetype_id
+        }
+        else {
+            // an edge didn't exist, so we need to create it.
+
+            // the components that are already existing in `from_archetype`.
+            let existing = &from_archetype.components;
+
+            // stores any components that are added by the bundle, but already exist in
+            // `from_archetype`.
+            let mut duplicate = SparseSet::with_capacity(existing.len());
+
+            // inserting a value of a shared component replaces the value the archetype
+            // has.
+            let replaced_values = bundle_info
+                .component_ids()
+                .iter()
+                .filter_map(|component_id| from_archetype.shared_value(*component_id))
+                .filter(|value_id| !bundle_info.component_ids().contains(value_id))
+                .collect::<Vec<_>>();
+
+            // compute the component ids for the resulting archetype
+            let mut component_ids =
+                Vec::with_capacity(existing.len() + bundle_info.component_ids().len());
+            component_ids.extend(
+                existing
+                    .keys()
+                    .filter(|component_id| !replaced_values.contains(component_id)),
+            );
+            for component_id in bundle_info.component_ids() {
+                if existing.contains_key(component_id) {
+                    duplicate.insert(component_id);
+                }
+                else {
+                    component_ids.push(*component_id);
+                }
             }
-            else {
-                // an edge didn't exist, so we need to create it.
-
-                // the components that are already existing in `from_archetype`.
-                let existing = &from_archetype.components;
-
-                // stores any components that are added by the bundle, but already exist in
-                // `from_archetype`.
-                let mut duplicate = SparseSet::with_capacity(existing.len());
-
-                // compute the component ids for the resulting archetype
-                let mut component_ids =
-                    Vec::with_capacity(existing.len() + bundle_info.component_ids().len());
-                component_ids.extend(existing.keys());
-                for component_id in bundle_info.component_ids() {
-                    if existing.contains_key(component_id) {
-                        duplicate.insert(component_id);
-                    }
-                    else {
-                        component_ids.push(*component_id);
-                    }
+
+            // the required components that `from_archetype` doesn't have yet are added
+            // too.
+            let mut required = vec![];
+            for (index, (component_id, _)) in bundle_info.required_components().iter().enumerate() {
+                if !existing.contains_key(component_id) {
+                    component_ids.push(*component_id);
+                    required.push(index);
                 }
-                component_ids.sort_unstable();
-                let component_ids: Box<[ComponentId]> = component_ids.into();
+            }
 
-                // even if the edge didn't exist, the resulting archetype might already exist.
-                let to_archetype_id =
-                    self.get_or_insert_archetype_by_components(component_ids, create_archetype);
+            component_ids.sort_unstable();
+            let component_ids: Box<[ComponentId]> = component_ids.into();
 
-                self.archetypes[from_archetype_index]
-                    .edges
-                    .add_bundle
-                    .insert(
-                        &bundle_info.id(),
-                        AddBundle {
-                            archetype_id: to_archetype_id,
-                            duplicate: duplicate.into(),
-                        },
-                    );
-
-                to_archetype_id
-            };
+            // even if the edge didn't exist, the resulting archetype might already exist.
+            let to_archetype_id =
+                self.get_or_insert_archetype_by_components(component_ids, create_archetype);
 
-        slice_get_mut_pair(
+            self.archetypes[from_archetype_index]
+                .edges
+                .add_bundle
+                .insert(
+                    &bundle_info.id(),
+                    AddBundle {
+                        archetype_id: to_archetype_id,
+                        duplicate: duplicate.into(),
+                        required: required.into(),
+                    },
+                );
+
+            to_archetype_id
+        };
+
+        Some(slice_get_mut_pair(
             &mut self.archetypes,
             from_archetype_index,
             to_archetype_id.index(),
-        )
-        .ok()
+        ))
     }
 
-    pub fn remove_bundle<'i, 'b>(
+    /// Follows the `RemoveBundle` edge for `bundle_info` from the archetype
+    /// `archetype_id`, creating the edge and the resulting archetype if
+    /// necessary.
+    ///
+    /// Returns `None` if the bundle is empty, or if the archetype doesn't
+    /// contain all components of the bundle.
+    pub fn remove_bundle(
         &mut self,
         archetype_id: ArchetypeId,
         bundle_info: &BundleInfo,
         create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&mut Archetype, &mut Archetype)> {
+    ) -> Option<ArchetypePair> {
         if bundle_info.is_empty() {
             return None;
         }
@@ -325,11 +415,18 @@ impl Archetypes {
         else {
             // an edge didn't exist, so we need to create it.
 
-            // the components that need to be removed
+            // the components that need to be removed. removing the marker of a shared
+            // component also removes its value.
             let remove_components = bundle_info
                 .component_ids()
                 .iter()
                 .copied()
+                .chain(
+                    bundle_info
+                        .component_ids()
+                        .iter()
+                        .filter_map(|component_id| from_archetype.shared_value(*component_id)),
+                )
                 .collect::<ImmutableSparseSet<_>>();
 
             // the components that are kept
@@ -340,7 +437,7 @@ impl Archetypes {
                 .collect::<Box<[ComponentId]>>();
 
             let (to_archetype_id, edge) = if remove_components.len() + component_ids.len()
-                < from_archetype.components.len()
+                != from_archetype.components.len()
             {
                 // some components from the bundle are not in the archetype
                 //let missing = bundle_info.component_ids().iter().copied()
@@ -369,17 +466,20 @@ impl Archetypes {
             to_archetype_id
         };
 
-        to_archetype_id.and_then(|to_archetype_id| {
+        to_archetype_id.map(|to_archetype_id| {
             slice_get_mut_pair(
                 &mut self.archetypes,
                 from_archetype_index,
                 to_archetype_id.index(),
             )
-            .ok()
         })
     }
 }
 
+/// Either a pair of distinct archetypes, or a single archetype if both are the
+/// same.
+pub type ArchetypePair<'a> = Result<(&'a mut Archetype, &'a mut Archetype), &'a mut Archetype>;
+
 pub fn create_archetype(
     archetype_id: ArchetypeId,
     component_ids: &[ComponentId],
@@ -401,31 +501,45 @@ pub fn create_archetype(
             }
         }
 
-        fn add_component(&mut self, component_info: &ComponentInfo) {
+        fn add_component(&mut self, component_info: &ComponentInfo, tables: &Tables) {
             match self {
                 Table::Existing(_table_id) => {}
-                Table::New(table_builder) => table_builder.add_column(component_info),
+                Table::New(table_builder) => {
+                    if let Some(storage) = tables.get_storage(component_info.id()) {
+                        table_builder.add_custom(component_info.id(), storage.clone());
+                    }
+                    else {
+                        table_builder.add_column(component_info);
+                    }
+                }
             }
         }
 
         fn finish(self, tables: &mut Tables) -> TableId {
             match self {
                 Table::Existing(table_id) => table_id,
-                Table::New(table_builder) => tables.insert(table_builder.build()),
+                Table::New(table_builder) => {
+                    let table = table_builder.build(tables.layout());
+                    tables.insert(table)
+                }
             }
         }
     }
 
     let mut table = Table::new(tables, component_ids);
     let mut archetype_component_infos = SparseMap::with_capacity(component_ids.len());
+    let mut shared = SparseMap::new();
 
     for component_id in component_ids {
         let component_info = components.get_component_info(*component_id);
+        if let Some(shared_id) = component_info.shared() {
+            shared.insert(&shared_id, *component_id);
+        }
 
         archetype_component_infos
             .insert(component_id, ArchetypeComponentInfo::from(component_info));
 
-        table.add_component(component_info);
+        table.add_component(component_info, tables);
     }
 
     let table_id = table.finish(tables);
@@ -435,6 +549,7 @@ pub fn create_archetype(
         table_id,
         entities: Vec::with_capacity(1),
         components: archetype_component_infos.into(),
+        shared: shared.into(),
         edges: Edges::default(),
     }
 }
@@ -461,6 +576,9 @@ struct Edges {
 pub struct AddBundle {
     pub archetype_id: ArchetypeId,
     pub duplicate: ImmutableSparseSet<ComponentId>,
+    /// Indices into [`BundleInfo::required_components`] of the required
+    /// components that are added along with the bundle.
+    pub required: Box<[usize]>,
 }
 
 #[derive(Debug)]
@@ -1,6 +1,12 @@
 use std::{
-    any::type_name,
-    collections::HashSet,
+    any::{
+        type_name,
+        Any,
+    },
+    collections::{
+        HashMap,
+        HashSet,
+    },
 };
 
 use crate::{
@@ -9,7 +15,9 @@ use crate::{
         ComponentId,
         ComponentInfo,
         Components,
+        RequiredComponent,
     },
+    error::BundleError,
     storage::table::{
         InsertIntoTable,
         Table,
@@ -19,7 +27,6 @@ use crate::{
         partition_dedup,
         sparse_map::SparseMapKey,
         type_id_map::TypeIdMap,
-        Joined,
     },
 };
 
@@ -28,12 +35,15 @@ use crate::{
 /// This trait is not safe to implement, since the following invariants must be
 /// upheld:
 ///
-/// - [`for_each_component`] and [`into_each_component`] always call the
-///   callback with the same component types in the same order.
+/// - [`component_types`](Self::component_types),
+///   [`into_components`](Self::into_components) and
+///   [`component_refs`](Self::component_refs) always call the callback with the
+///   same component types in the same order.
 pub unsafe trait DynamicBundle: 'static {
     fn num_components(&self) -> usize;
     fn component_types<F: ComponentTypesCallback>(&self, callback: F);
     fn into_components<F: IntoComponentsCallback>(self, callback: F);
+    fn component_refs<F: ComponentRefsCallback>(&self, callback: F);
 }
 
 pub unsafe trait Bundle: 'static {
@@ -42,6 +52,10 @@ pub unsafe trait Bundle: 'static {
     fn component_types<F: ComponentTypesCallback>(callback: F);
     fn from_components<F: FromComponentsCallback>(callback: F) -> Self;
     fn into_components<F: IntoComponentsCallback>(self, callback: F);
+
+    /// Passes references to the components to the callback, e.g. to inspect
+    /// them before they're inserted.
+    fn component_refs<F: ComponentRefsCallback>(&self, callback: F);
 }
 
 unsafe impl<B: Bundle> DynamicBundle for B {
@@ -56,6 +70,10 @@ unsafe impl<B: Bundle> DynamicBundle for B {
     fn into_components<F: IntoComponentsCallback>(self, callback: F) {
         <B as Bundle>::into_components(self, callback)
     }
+
+    fn component_refs<F: ComponentRefsCallback>(&self, callback: F) {
+        <B as Bundle>::component_refs(self, callback)
+    }
 }
 
 unsafe impl<T: Component> Bundle for T {
@@ -72,20 +90,59 @@ unsafe impl<T: Component> Bundle for T {
     fn from_components<F: FromComponentsCallback>(mut callback: F) -> Self {
         callback.call()
     }
+
+    fn component_refs<F: ComponentRefsCallback>(&self, mut callback: F) {
+        callback.call(self)
+    }
 }
 
-unsafe impl Bundle for () {
-    const NUM_COMPONENTS: usize = 0;
+macro_rules! impl_bundle_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case, unused_variables, unused_mut, clippy::unused_unit)]
+        unsafe impl<$($name: Bundle),*> Bundle for ($($name,)*) {
+            const NUM_COMPONENTS: usize = 0 $(+ $name::NUM_COMPONENTS)*;
 
-    fn component_types<F: ComponentTypesCallback>(_callback: F) {}
+            fn component_types<F: ComponentTypesCallback>(mut callback: F) {
+                $($name::component_types(&mut callback);)*
+            }
 
-    fn into_components<F: IntoComponentsCallback>(self, _callback: F) {}
+            fn into_components<F: IntoComponentsCallback>(self, mut callback: F) {
+                let ($($name,)*) = self;
+                $($name.into_components(&mut callback);)*
+            }
 
-    fn from_components<F: FromComponentsCallback>(_callback: F) -> Self {
-        ()
-    }
+            fn from_components<F: FromComponentsCallback>(mut callback: F) -> Self {
+                // tuple expressions are evaluated left to right, so the components are
+                // requested in the same order as they're listed by `component_types`.
+                ($($name::from_components(&mut callback),)*)
+            }
+
+            fn component_refs<F: ComponentRefsCallback>(&self, mut callback: F) {
+                let ($($name,)*) = self;
+                $($name.component_refs(&mut callback);)*
+            }
+        }
+    };
 }
 
+impl_bundle_tuple!();
+impl_bundle_tuple!(T0);
+impl_bundle_tuple!(T0, T1);
+impl_bundle_tuple!(T0, T1, T2);
+impl_bundle_tuple!(T0, T1, T2, T3);
+impl_bundle_tuple!(T0, T1, T2, T3, T4);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
+impl_bundle_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
+
 pub trait ComponentTypesCallback {
     fn call<C: Component>(&mut self);
 }
@@ -98,6 +155,10 @@ pub trait FromComponentsCallback {
     fn call<C: Component>(&mut self) -> C;
 }
 
+pub trait ComponentRefsCallback {
+    fn call<C: Component>(&mut self, component: &C);
+}
+
 impl<'a, T: ComponentTypesCallback> ComponentTypesCallback for &'a mut T {
     fn call<C: Component>(&mut self) {
         <T as ComponentTypesCallback>::call::<C>(*self);
@@ -110,6 +171,18 @@ impl<'a, T: IntoComponentsCallback> IntoComponentsCallback for &'a mut T {
     }
 }
 
+impl<T: FromComponentsCallback> FromComponentsCallback for &mut T {
+    fn call<C: Component>(&mut self) -> C {
+        <T as FromComponentsCallback>::call::<C>(*self)
+    }
+}
+
+impl<T: ComponentRefsCallback> ComponentRefsCallback for &mut T {
+    fn call<C: Component>(&mut self, component: &C) {
+        <T as ComponentRefsCallback>::call::<C>(*self, component);
+    }
+}
+
 #[derive(Debug)]
 pub struct RegisterComponents<'a, F> {
     components: &'a mut Components,
@@ -140,6 +213,9 @@ pub struct InsertComponentsIntoTable<'a, 't, F> {
     component_ids: std::slice::Iter<'a, ComponentId>,
     filter: F,
     insert_into_table: &'a mut InsertIntoTable<'t>,
+    /// Whether the components already in the row were moved out, so that they
+    /// must not be dropped when they're replaced.
+    taken: bool,
 }
 
 impl<'a, 't, F> InsertComponentsIntoTable<'a, 't, F> {
@@ -152,6 +228,24 @@ impl<'a, 't, F> InsertComponentsIntoTable<'a, 't, F> {
             component_ids: bundle_info.component_ids().iter(),
             filter,
             insert_into_table,
+            taken: false,
+        }
+    }
+
+    /// Replaces the components of the bundle without dropping the old values.
+    ///
+    /// # Safety
+    ///
+    /// Components of the bundle that are already in the row must have been
+    /// moved out, e.g. with [`TakeComponentsFromTable`].
+    pub unsafe fn new_taken(
+        bundle_info: &'a BundleInfo,
+        filter: F,
+        insert_into_table: &'a mut InsertIntoTable<'t>,
+    ) -> Self {
+        Self {
+            taken: true,
+            ..Self::new(bundle_info, filter, insert_into_table)
         }
     }
 }
@@ -171,8 +265,14 @@ where
                 // SAFETY:
                 // The implementor of the Bundle trait must ensure that they only call this
                 // callback with components of the correct type.
-                self.insert_into_table
-                    .write_column(*component_id, component);
+                if self.taken {
+                    self.insert_into_table
+                        .initialize_column(*component_id, component);
+                }
+                else {
+                    self.insert_into_table
+                        .write_column(*component_id, component);
+                }
             }
         }
     }
@@ -210,11 +310,60 @@ impl<'a, 't> FromComponentsCallback for TakeComponentsFromTable<'a, 't> {
     }
 }
 
+/// Takes the components of the bundle that the row has out of the table, and
+/// boxes them. Used when the row has only some of the bundle's components.
+#[derive(Debug)]
+pub struct TakeSomeComponentsFromTable<'a, 't> {
+    component_ids: std::slice::Iter<'a, ComponentId>,
+    table: &'t mut Table,
+    table_row: TableRow,
+    taken: Vec<(ComponentId, Box<dyn Any>)>,
+}
+
+impl<'a, 't> TakeSomeComponentsFromTable<'a, 't> {
+    pub fn new(bundle_info: &'a BundleInfo, table: &'t mut Table, table_row: TableRow) -> Self {
+        Self {
+            component_ids: bundle_info.component_ids().iter(),
+            table,
+            table_row,
+            taken: vec![],
+        }
+    }
+
+    pub fn into_taken(self) -> Vec<(ComponentId, Box<dyn Any>)> {
+        self.taken
+    }
+}
+
+impl<'a, 't> ComponentTypesCallback for TakeSomeComponentsFromTable<'a, 't> {
+    fn call<C: Component>(&mut self) {
+        let component_id = *self
+            .component_ids
+            .next()
+            .expect("not enough component ids from bundle info");
+
+        if self.table.has_component(component_id) {
+            let value = unsafe {
+                // SAFETY: The bundle lists its component types in the same order as the
+                // ids in its bundle info.
+                self.table
+                    .take_component_and_remove_later::<C>(component_id, self.table_row)
+                    .unwrap()
+            };
+            self.taken.push((component_id, Box::new(value)));
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BundleInfo {
     id: BundleId,
     name: &'static str,
+    /// In the order in which the bundle lists its components.
     component_ids: Box<[ComponentId]>,
+    /// The components that the bundle's components require, recursively,
+    /// excluding the ones in the bundle.
+    required_components: Box<[(ComponentId, RequiredComponent)]>,
 }
 
 impl BundleInfo {
@@ -230,11 +379,49 @@ impl BundleInfo {
         &self.component_ids
     }
 
+    pub fn required_components(&self) -> &[(ComponentId, RequiredComponent)] {
+        &self.required_components
+    }
+
     pub fn is_empty(&self) -> bool {
         self.component_ids.is_empty()
     }
 }
 
+/// Resolves the components that are required by the components in a bundle.
+///
+/// Required components of required components are included too. If a component
+/// is required more than once, the constructor of the requirement that is the
+/// fewest steps away from the bundle is used.
+fn resolve_required_components(
+    component_ids: &[ComponentId],
+    components: &mut Components,
+) -> Box<[(ComponentId, RequiredComponent)]> {
+    let mut required_components = vec![];
+    let mut visited = component_ids.to_vec();
+
+    // breadth-first, so that closer requirements take precedence.
+    let mut index = 0;
+    while index < visited.len() {
+        let required = components
+            .get_component_info(visited[index])
+            .descriptor()
+            .required()
+            .clone();
+        index += 1;
+
+        for required_component in required.iter() {
+            let component_id = required_component.register(components);
+            if !visited.contains(&component_id) {
+                visited.push(component_id);
+                required_components.push((component_id, required_component.clone()));
+            }
+        }
+    }
+
+    required_components.into()
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BundleId(u32);
 
@@ -252,6 +439,7 @@ impl SparseMapKey for BundleId {
 pub struct Bundles {
     bundle_infos: Vec<BundleInfo>,
     by_type_id: TypeIdMap<BundleId>,
+    by_component_ids: HashMap<Box<[ComponentId]>, BundleId>,
     insert_component_ids_buf: Vec<ComponentId>,
 }
 
@@ -259,7 +447,7 @@ impl Bundles {
     pub fn get_mut_or_insert_static<B: Bundle>(
         &mut self,
         components: &mut Components,
-    ) -> &mut BundleInfo {
+    ) -> Result<&mut BundleInfo, BundleError> {
         self.get_mut_or_insert_inner::<B>(
             |buf, components| {
                 buf.reserve(B::NUM_COMPONENTS);
@@ -278,7 +466,7 @@ impl Bundles {
         &mut self,
         bundle: &B,
         components: &mut Components,
-    ) -> &mut BundleInfo {
+    ) -> Result<&mut BundleInfo, BundleError> {
         self.get_mut_or_insert_inner::<B>(
             |buf, components| {
                 buf.reserve(bundle.num_components());
@@ -297,49 +485,93 @@ impl Bundles {
         &mut self,
         component_types: impl FnOnce(&mut Vec<ComponentId>, &mut Components),
         components: &mut Components,
+    ) -> Result<&mut BundleInfo, BundleError> {
+        if let Some(bundle_id) = self.by_type_id.get::<B>() {
+            return Ok(&mut self.bundle_infos[bundle_id.index()]);
+        }
+
+        let index = self.bundle_infos.len();
+        let id = BundleId::from_index(index);
+        let name = type_name::<B>();
+
+        self.insert_component_ids_buf.clear(); // note: in case we panicked before draining this
+
+        component_types(&mut self.insert_component_ids_buf, components);
+
+        // the components are passed to and from the bundle in this order, so we keep
+        // it.
+        let component_ids: Box<[ComponentId]> = self.insert_component_ids_buf.as_slice().into();
+
+        self.insert_component_ids_buf.sort_unstable();
+        let (_, duplicates) = partition_dedup(&mut self.insert_component_ids_buf);
+        if !duplicates.is_empty() {
+            let duplicates = duplicates.iter().copied().collect::<HashSet<_>>();
+            let names = duplicates
+                .into_iter()
+                .map(|component_id| {
+                    components
+                        .get_component_info(component_id)
+                        .descriptor()
+                        .name()
+                })
+                .collect::<Vec<_>>();
+            self.insert_component_ids_buf.clear();
+            return Err(BundleError::DuplicateComponents {
+                bundle: name,
+                components: names,
+            });
+        }
+
+        let required_components = resolve_required_components(&component_ids, components);
+
+        self.bundle_infos.push(BundleInfo {
+            id,
+            name,
+            component_ids,
+            required_components,
+        });
+        self.insert_component_ids_buf.clear();
+        self.by_type_id.insert::<B>(id);
+
+        Ok(&mut self.bundle_infos[index])
+    }
+
+    /// Returns the [`BundleInfo`] for a set of components that is only known at
+    /// runtime, e.g. the components of an entity that is being cloned.
+    ///
+    /// `component_ids` must be sorted and must not contain duplicates.
+    pub fn get_mut_or_insert_by_component_ids(
+        &mut self,
+        component_ids: &[ComponentId],
+        components: &mut Components,
     ) -> &mut BundleInfo {
-        let occupied_entry = self.by_type_id.entry::<B>().or_insert_with(|| {
+        debug_assert!(component_ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let index = if let Some(bundle_id) = self.by_component_ids.get(component_ids) {
+            bundle_id.index()
+        }
+        else {
             let index = self.bundle_infos.len();
             let id = BundleId::from_index(index);
-            let name = type_name::<B>();
-
-            self.insert_component_ids_buf.clear(); // note: in case we panicked before draining this
-
-            component_types(&mut self.insert_component_ids_buf, components);
-
-            self.insert_component_ids_buf.sort_unstable();
-            let (_, duplicates) = partition_dedup(&mut self.insert_component_ids_buf);
-            if !duplicates.is_empty() {
-                let duplicates = duplicates.iter().copied().collect::<HashSet<_>>();
-                let names = duplicates
-                    .into_iter()
-                    .map(|component_id| {
-                        components
-                            .get_component_info(component_id)
-                            .descriptor()
-                            .name()
-                    })
-                    .collect::<Vec<_>>();
-                panic!(
-                    "Bundle {name} contains duplicate components: {}",
-                    Joined::new(", ", &names)
-                );
-            }
-
             self.bundle_infos.push(BundleInfo {
                 id,
-                name: type_name::<B>(),
-                component_ids: self.insert_component_ids_buf.drain(..).collect(),
+                name: "dynamic bundle",
+                component_ids: component_ids.into(),
+                required_components: resolve_required_components(component_ids, components),
             });
+            self.by_component_ids.insert(component_ids.into(), id);
+            index
+        };
 
-            id
-        });
-
-        &mut self.bundle_infos[occupied_entry.get().index()]
+        &mut self.bundle_infos[index]
     }
 
     pub fn get<B: DynamicBundle>(&self) -> Option<&BundleInfo> {
         let index = self.by_type_id.get::<B>()?;
         Some(&self.bundle_infos[index.index()])
     }
+
+    pub fn get_by_id(&self, bundle_id: BundleId) -> &BundleInfo {
+        &self.bundle_infos[bundle_id.index()]
+    }
 }
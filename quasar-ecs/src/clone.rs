@@ -0,0 +1,139 @@
+use bevy_ptr::Ptr;
+
+use crate::{
+    archetype::Archetype,
+    component::{
+        ComponentDescriptor,
+        ComponentId,
+        Components,
+    },
+    error::QuasarError,
+    storage::{
+        column::Column,
+        table::{
+            InsertIntoTable,
+            Table,
+            TableRow,
+        },
+    },
+    util::CloneFn,
+};
+
+/// What to do with components that can't be cloned, when cloning an entity.
+///
+/// A component can be cloned if its [`ComponentDescriptor`] has a clone
+/// function. See [`Component::CLONE_FN`](crate::Component::CLONE_FN).
+#[derive(Clone, Copy, Debug, Default)]
+pub enum UncloneablePolicy {
+    /// The component is not cloned, and the clone won't have it.
+    Skip,
+
+    /// Cloning fails with [`QuasarError::Uncloneable`].
+    #[default]
+    Error,
+
+    /// The handler is asked for a clone function for the component. If it
+    /// returns `None`, the component is skipped.
+    Custom(fn(&ComponentDescriptor) -> Option<CloneFn>),
+}
+
+impl UncloneablePolicy {
+    fn resolve(&self, descriptor: &ComponentDescriptor) -> Result<Option<CloneFn>, QuasarError> {
+        if let Some(clone_fn) = descriptor.clone_fn() {
+            return Ok(Some(clone_fn));
+        }
+
+        match self {
+            Self::Skip => Ok(None),
+            Self::Error => {
+                Err(QuasarError::Uncloneable {
+                    component: descriptor.name(),
+                })
+            }
+            Self::Custom(handler) => Ok(handler(descriptor)),
+        }
+    }
+}
+
+/// Clones of the components of an entity, which can be written to another
+/// entity.
+#[derive(Debug)]
+pub struct ClonedComponents {
+    /// Sorted, so that they can be used to look up a bundle.
+    component_ids: Vec<ComponentId>,
+    /// Each column holds exactly one clone of the component with the id at the
+    /// same index in `component_ids`.
+    columns: Vec<Column>,
+}
+
+impl ClonedComponents {
+    pub fn new(
+        archetype: &Archetype,
+        table: &Table,
+        table_row: TableRow,
+        components: &Components,
+        policy: &UncloneablePolicy,
+    ) -> Result<Self, QuasarError> {
+        let mut component_ids = vec![];
+        let mut columns = vec![];
+
+        for component_id in archetype.component_ids() {
+            let component_info = components.get_component_info(component_id);
+            let descriptor = component_info.descriptor();
+            let Some(clone_fn) = policy.resolve(descriptor)?
+            else {
+                continue;
+            };
+
+            let value = table
+                .get_component_ptr(component_id, table_row)
+                .expect("archetype component missing from table");
+
+            let mut column = Column::new(component_info, 1);
+            unsafe {
+                // SAFETY: The column was created from the same descriptor that `clone_fn`
+                // belongs to, and `value` points to a component of that type.
+                column.push_clone(value, clone_fn);
+            }
+
+            component_ids.push(component_id);
+            columns.push(column);
+        }
+
+        Ok(Self {
+            component_ids,
+            columns,
+        })
+    }
+
+    pub fn component_ids(&self) -> &[ComponentId] {
+        &self.component_ids
+    }
+
+    /// Returns the cloned components and their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (ComponentId, Ptr<'_>)> {
+        self.component_ids
+            .iter()
+            .zip(&self.columns)
+            .map(|(component_id, column)| {
+                let value = unsafe {
+                    // SAFETY: Each column holds exactly one clone.
+                    column.get_ptr(0)
+                };
+                (*component_id, value)
+            })
+    }
+
+    /// Moves the cloned components into a table row, replacing components that
+    /// are already there.
+    ///
+    /// # Safety
+    ///
+    /// The table must have a column for each cloned component.
+    pub unsafe fn write(self, insert_into_table: &mut InsertIntoTable) {
+        for (component_id, mut column) in self.component_ids.into_iter().zip(self.columns) {
+            let value = column.take_item_ptr(0);
+            insert_into_table.write_column_ptr(component_id, value);
+        }
+    }
+}
@@ -4,24 +4,208 @@ use std::{
         type_name,
         TypeId,
     },
+    fmt::Debug,
     mem::needs_drop,
+    sync::Arc,
+};
+
+use bevy_ptr::{
+    OwningPtr,
+    Ptr,
 };
 
 use crate::{
+    entity::Entity,
+    shared::{
+        Shared,
+        SharedComponent,
+    },
     storage::StorageType,
     util::{
+        clone_ptr,
         drop_ptr,
         sparse_map::SparseMapKey,
         type_id_map::{
             self,
             TypeIdMap,
         },
+        CloneFn,
         DropFn,
     },
+    world::World,
 };
 
 pub trait Component: 'static {
     const STORAGE_TYPE: StorageType;
+
+    /// Function used to clone this component when cloning entities.
+    ///
+    /// This is `None` by default. Use `#[quasar(clone)]` when deriving
+    /// [`Component`], or register the component with
+    /// [`World::register_cloneable_component`](crate::World::register_cloneable_component)
+    /// to make it cloneable.
+    const CLONE_FN: Option<CloneFn> = None;
+
+    /// Whether the component can be mutated in place. Immutable components
+    /// can only be changed by inserting a new value.
+    ///
+    /// Use `#[quasar(immutable)]` when deriving [`Component`] to make it
+    /// immutable. Only components with `MUTABLE` set to `false` can be
+    /// indexed, and they must not implement [`MutableComponent`].
+    const MUTABLE: bool = true;
+
+    /// Runs after the component was added to an entity that didn't have it.
+    const ON_ADD: Option<ComponentHook> = None;
+
+    /// Runs after the component of an entity was replaced by inserting a new
+    /// value.
+    const ON_REPLACE: Option<ComponentHook> = None;
+
+    /// Runs after the component was removed from an entity, or the entity was
+    /// despawned.
+    const ON_REMOVE: Option<ComponentHook> = None;
+
+    /// Registers the components that are inserted along with this one, if the
+    /// entity doesn't have them yet.
+    ///
+    /// Use `#[quasar(require(Transform, Visibility = default_visibility))]`
+    /// when deriving [`Component`]. Required components without a constructor
+    /// are created with [`Default`].
+    fn register_required(_required: &mut RequiredComponents) {}
+}
+
+/// A component that can be mutated in place, e.g. with
+/// [`EntityWorldMut::get_mut`](crate::EntityWorldMut::get_mut) or by querying
+/// for `&mut C`.
+///
+/// `#[derive(Component)]` implements this, unless `#[quasar(immutable)]` is
+/// used. Immutable components can only be changed by inserting a new value,
+/// which runs their [`ON_REPLACE`](Component::ON_REPLACE) hook.
+///
+/// # Safety
+///
+/// [`Component::MUTABLE`] must be `true`. Indexes only accept immutable
+/// components, and rely on their values not changing in place.
+pub unsafe trait MutableComponent: Component {}
+
+/// A hook that runs when a component is added, replaced or removed. It gets
+/// the entity and the id of the component.
+///
+/// Hooks run once the operation that triggered them is complete, so they may
+/// change the world freely. If a hook despawns the entity, the
+/// [`EntityWorldMut`](crate::EntityWorldMut) that triggered it panics when
+/// it's used afterwards, and its fallible methods fail. See
+/// [`EntityWorldMut::is_despawned`](crate::EntityWorldMut::is_despawned).
+pub type ComponentHook = fn(&mut World, Entity, ComponentId);
+
+/// The hooks of a component. See [`Component::ON_ADD`],
+/// [`Component::ON_REPLACE`] and [`Component::ON_REMOVE`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ComponentHooks {
+    pub on_add: Option<ComponentHook>,
+    pub on_replace: Option<ComponentHook>,
+    pub on_remove: Option<ComponentHook>,
+}
+
+impl ComponentHooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_add.is_none() && self.on_replace.is_none() && self.on_remove.is_none()
+    }
+}
+
+/// The components that a component requires. See
+/// [`Component::register_required`].
+#[derive(Clone, Debug, Default)]
+pub struct RequiredComponents {
+    components: Vec<RequiredComponent>,
+}
+
+impl RequiredComponents {
+    /// Requires the component `R`, which is created with `constructor` if the
+    /// entity doesn't have it yet.
+    pub fn add<R: Component>(&mut self, constructor: fn() -> R) -> &mut Self {
+        if !self.contains(TypeId::of::<R>()) {
+            self.components.push(RequiredComponent::new(constructor));
+        }
+        self
+    }
+
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.components
+            .iter()
+            .any(|required| required.type_id == type_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RequiredComponent> {
+        self.components.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+/// A required component, with a type-erased constructor.
+#[derive(Clone)]
+pub struct RequiredComponent {
+    name: &'static str,
+    type_id: TypeId,
+    register: fn(&mut Components) -> ComponentId,
+    constructor: Arc<RequiredConstructor>,
+    inspector: Arc<RequiredInspector>,
+}
+
+type RequiredConstructor = dyn Fn(&mut dyn FnMut(OwningPtr<'_>)) + Send + Sync;
+type RequiredInspector = dyn Fn(&mut dyn FnMut(Ptr<'_>)) + Send + Sync;
+
+impl RequiredComponent {
+    fn new<R: Component>(constructor: fn() -> R) -> Self {
+        Self {
+            name: type_name::<R>(),
+            type_id: TypeId::of::<R>(),
+            register: |components| components.register::<R>().id(),
+            constructor: Arc::new(move |write| OwningPtr::make(constructor(), write)),
+            inspector: Arc::new(move |read| read(Ptr::from(&constructor()))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    /// Registers the required component, and returns its id.
+    pub fn register(&self, components: &mut Components) -> ComponentId {
+        (self.register)(components)
+    }
+
+    /// Creates a value of the required component, and passes a pointer to it
+    /// to `write`, which takes ownership of it.
+    pub fn construct(&self, write: &mut dyn FnMut(OwningPtr<'_>)) {
+        (self.constructor)(write)
+    }
+
+    /// Creates a value of the required component, passes a pointer to it to
+    /// `read`, and drops it afterwards. Used to check the value before the
+    /// component is inserted.
+    pub fn inspect(&self, read: &mut dyn FnMut(Ptr<'_>)) {
+        (self.inspector)(read)
+    }
+}
+
+impl Debug for RequiredComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequiredComponent")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -43,15 +227,30 @@ pub struct ComponentDescriptor {
     type_id: TypeId,
     layout: Layout,
     drop_fn: Option<DropFn>,
+    clone_fn: Option<CloneFn>,
+    mutable: bool,
+    hooks: ComponentHooks,
+    required: RequiredComponents,
 }
 
 impl ComponentDescriptor {
     pub fn new<C: Component>() -> Self {
+        let mut required = RequiredComponents::default();
+        C::register_required(&mut required);
+
         Self {
             name: type_name::<C>(),
             type_id: TypeId::of::<C>(),
             layout: Layout::new::<C>(),
             drop_fn: needs_drop::<C>().then_some(drop_ptr::<C>),
+            clone_fn: C::CLONE_FN,
+            mutable: C::MUTABLE,
+            hooks: ComponentHooks {
+                on_add: C::ON_ADD,
+                on_replace: C::ON_REPLACE,
+                on_remove: C::ON_REMOVE,
+            },
+            required,
         }
     }
 
@@ -63,9 +262,25 @@ impl ComponentDescriptor {
         self.drop_fn
     }
 
+    pub fn clone_fn(&self) -> Option<CloneFn> {
+        self.clone_fn
+    }
+
     pub fn name(&self) -> &'static str {
         self.name
     }
+
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+
+    pub fn required(&self) -> &RequiredComponents {
+        &self.required
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -73,6 +288,9 @@ pub struct ComponentInfo {
     id: ComponentId,
     storage_type: StorageType,
     descriptor: ComponentDescriptor,
+    /// If this component represents a value of a shared component, the id of
+    /// its [`Shared`] marker.
+    shared: Option<ComponentId>,
 }
 
 impl ComponentInfo {
@@ -87,6 +305,12 @@ impl ComponentInfo {
     pub fn storage_type(&self) -> StorageType {
         self.storage_type
     }
+
+    /// If this component represents a value of a shared component, returns the
+    /// id of its [`Shared`] marker. See [`SharedComponent`].
+    pub fn shared(&self) -> Option<ComponentId> {
+        self.shared
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -106,6 +330,7 @@ impl Components {
                     id,
                     storage_type: C::STORAGE_TYPE,
                     descriptor: ComponentDescriptor::new::<C>(),
+                    shared: None,
                 });
                 vacant_entry.insert(id);
                 index
@@ -115,6 +340,31 @@ impl Components {
         &mut self.components[index]
     }
 
+    /// Registers a component and captures its [`Clone`] implementation, even
+    /// if [`Component::CLONE_FN`] is `None`.
+    pub fn register_cloneable<C: Component + Clone>(&mut self) -> &mut ComponentInfo {
+        let component_info = self.register::<C>();
+        component_info.descriptor.clone_fn = Some(clone_ptr::<C>);
+        component_info
+    }
+
+    /// Registers a new component that represents a value of the shared
+    /// component `C`. It has no data, and is inserted along with the
+    /// [`Shared<C>`] marker with `shared_id`.
+    pub fn register_shared_value<C: SharedComponent>(
+        &mut self,
+        shared_id: ComponentId,
+    ) -> ComponentId {
+        let id = ComponentId(self.components.len());
+        self.components.push(ComponentInfo {
+            id,
+            storage_type: StorageType::Table,
+            descriptor: ComponentDescriptor::new::<Shared<C>>(),
+            shared: Some(shared_id),
+        });
+        id
+    }
+
     pub fn get_component_info(&self, component_id: ComponentId) -> &ComponentInfo {
         &self.components[component_id.index()]
     }
@@ -0,0 +1,28 @@
+use crate::{
+    component::{
+        Component,
+        MutableComponent,
+    },
+    storage::StorageType,
+    util::{
+        clone_ptr,
+        CloneFn,
+    },
+};
+
+/// Marks an entity as disabled.
+///
+/// Disabled entities keep their components, but are excluded from queries,
+/// unless the query explicitly mentions [`Disabled`], e.g. with
+/// `Allow<Disabled>` or `With<Disabled>`. See
+/// [`EntityWorldMut::disable`](crate::EntityWorldMut::disable).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Disabled;
+
+impl Component for Disabled {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+    const CLONE_FN: Option<CloneFn> = Some(clone_ptr::<Self>);
+}
+
+// SAFETY: `Disabled` is mutable.
+unsafe impl MutableComponent for Disabled {}
@@ -1,5 +1,7 @@
 use core::fmt;
 use std::{
+    cmp::Ordering,
+    collections::VecDeque,
     hash::Hash,
     num::NonZero,
 };
@@ -9,6 +11,7 @@ use crate::{
         ArchetypeId,
         ArchetypeRow,
     },
+    error::EntityError,
     storage::table::{
         TableId,
         TableRow,
@@ -91,39 +94,173 @@ fn format_entity(entity: Entity, f: &mut fmt::Formatter) -> fmt::Result {
     }
 }
 
+/// How freed entity indices are reused.
+///
+/// Reusing an index soon after it was freed makes it more likely that a stale
+/// handle is mixed up with the new entity, if it's compared by index only, e.g.
+/// by external systems. Delaying reuse makes that less likely.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntityRecycling {
+    /// The most recently freed index is reused first.
+    #[default]
+    Lifo,
+
+    /// The least recently freed index is reused first.
+    Fifo,
+
+    /// Like [`Fifo`](Self::Fifo), but indices are only reused once at least
+    /// `min_free` of them are free. Until then, new indices are allocated.
+    Delayed { min_free: usize },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("All entity indices are in use")]
+pub struct EntitiesExhausted;
+
 #[derive(Debug, Default)]
 pub struct Entities {
     meta: Vec<EntityMeta>,
-    free_list: Vec<Entity>,
+    /// Indices of freed entities. Their generation was already incremented.
+    free_list: VecDeque<u32>,
+    recycling: EntityRecycling,
 }
 
 impl Entities {
+    /// The highest index an entity can have. `u32::MAX` is used by
+    /// [`Entity::PLACEHOLDER`].
+    const MAX_INDEX: u32 = u32::MAX - 1;
+
+    pub fn recycling(&self) -> EntityRecycling {
+        self.recycling
+    }
+
+    pub fn set_recycling(&mut self, recycling: EntityRecycling) {
+        self.recycling = recycling;
+    }
+
+    /// Frees all entities, including the ones that were allocated but don't
+    /// have a location yet.
+    ///
+    /// The generations of all indices are kept, so that handles to the freed
+    /// entities don't validate for entities that are allocated later.
     pub fn clear(&mut self) {
-        self.meta.clear();
-        // todo: don't we need to keep track of entity generations?
-        self.free_list.clear();
+        // the indices that aren't free belong to allocated entities, whether they
+        // have a location or not.
+        let mut free = vec![false; self.meta.len()];
+        for index in self.free_list.drain(..) {
+            free[index as usize] = true;
+        }
+        self.free_list.reserve(self.meta.len());
+
+        for (index, meta) in self.meta.iter_mut().enumerate() {
+            if !free[index] && !meta.generation.is_invalid() {
+                meta.location = EntityLocation::INVALID;
+                meta.generation = meta.generation.next();
+            }
+            if !meta.generation.is_invalid() {
+                self.free_list.push_back(index as u32);
+            }
+        }
+
+        if self.recycling == EntityRecycling::Lifo {
+            // so that the lowest indices are reused first.
+            self.free_list.make_contiguous().reverse();
+        }
     }
 
-    pub fn allocate(&mut self) -> Entity {
-        if let Some(mut entity) = self.free_list.pop() {
-            entity.generation.increment();
-            entity
+    /// Allocates an entity. It doesn't have a location until one is set with
+    /// [`set_location`](Self::set_location).
+    pub fn allocate(&mut self) -> Result<Entity, EntitiesExhausted> {
+        if let Some(index) = self.pop_free() {
+            Ok(Entity {
+                index,
+                generation: self.meta[index as usize].generation,
+            })
         }
-        else {
-            let index = self.meta.len();
+        else if self.meta.len() <= Self::MAX_INDEX as usize {
+            let index = self.meta.len() as u32;
             self.meta.push(EntityMeta::EMPTY);
-            Entity {
-                index: index.try_into().expect("Entity index overflow"),
+            Ok(Entity {
+                index,
                 generation: EntityGeneration::NEW,
+            })
+        }
+        else {
+            // with delayed reuse there might still be free indices.
+            let index = self.free_list.pop_front().ok_or(EntitiesExhausted)?;
+            Ok(Entity {
+                index,
+                generation: self.meta[index as usize].generation,
+            })
+        }
+    }
+
+    /// Allocates up to `count` entities at once, like calling
+    /// [`allocate`](Self::allocate) `count` times. Fewer are returned if the
+    /// entity indices run out.
+    pub fn allocate_many(&mut self, count: usize) -> Vec<Entity> {
+        let mut allocated = Vec::with_capacity(count);
+        while allocated.len() < count {
+            let Some(index) = self.pop_free()
+            else {
+                break;
+            };
+            allocated.push(Entity {
+                index,
+                generation: self.meta[index as usize].generation,
+            });
+        }
+
+        // the remaining entities get new indices.
+        let start = self.meta.len();
+        let end = (start + count - allocated.len()).min(Self::MAX_INDEX as usize + 1);
+        if start < end {
+            self.meta.resize(end, EntityMeta::EMPTY);
+            allocated.extend((start..end).map(|index| {
+                Entity {
+                    index: index as u32,
+                    generation: EntityGeneration::NEW,
+                }
+            }));
+        }
+        allocated
+    }
+
+    /// Takes a freed index to reuse, if the recycling policy allows it.
+    fn pop_free(&mut self) -> Option<u32> {
+        match self.recycling {
+            EntityRecycling::Lifo => self.free_list.pop_back(),
+            EntityRecycling::Fifo => self.free_list.pop_front(),
+            EntityRecycling::Delayed { min_free } => {
+                if self.free_list.len() >= min_free.max(1) {
+                    self.free_list.pop_front()
+                }
+                else {
+                    None
+                }
             }
         }
     }
 
+    /// Reserves space, so that at least `additional` entities can be allocated
+    /// without reallocating.
+    pub fn reserve(&mut self, additional: usize) {
+        self.meta
+            .reserve(additional.saturating_sub(self.free_list.len()));
+    }
+
+    /// Frees an entity. Its generation is incremented, so that existing handles
+    /// to it become invalid.
+    ///
+    /// If the generation is exhausted, the index is retired and never reused.
     pub fn free(&mut self, entity: Entity) {
         let meta = &mut self.meta[entity.index()];
         if meta.generation == entity.generation {
-            *meta = EntityMeta::EMPTY;
-            self.free_list.push(entity);
+            meta.location = EntityLocation::INVALID;
+            meta.generation = meta.generation.next();
+            if !meta.generation.is_invalid() {
+                self.free_list.push_back(entity.index);
+            }
         }
         else {
             assert!(entity.generation < meta.generation);
@@ -137,13 +274,22 @@ impl Entities {
     }
 
     pub fn get_location(&self, entity: Entity) -> Option<EntityLocation> {
-        let meta = self.meta.get(entity.index as usize)?;
-        if entity.generation == meta.generation {
-            Some(meta.location)
-        }
+        self.check(entity).ok()
+    }
+
+    /// Returns the location of an entity, or why it doesn't have one.
+    pub fn check(&self, entity: Entity) -> Result<EntityLocation, EntityError> {
+        let Some(meta) = self.meta.get(entity.index())
         else {
-            assert!(entity.generation < meta.generation);
-            None
+            return Err(EntityError::NeverExisted(entity));
+        };
+
+        match entity.generation.cmp(&meta.generation) {
+            Ordering::Less => Err(EntityError::Despawned(entity)),
+            // the entity was allocated, but not spawned yet.
+            Ordering::Equal if meta.location.is_invalid() => Err(EntityError::NeverExisted(entity)),
+            Ordering::Equal => Ok(meta.location),
+            Ordering::Greater => Err(EntityError::NeverExisted(entity)),
         }
     }
 
@@ -197,10 +343,6 @@ impl EntityMeta {
         generation: EntityGeneration::NEW,
         location: EntityLocation::INVALID,
     };
-
-    pub fn is_empty(&self) -> bool {
-        *self == Self::EMPTY
-    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -217,8 +359,10 @@ impl EntityGeneration {
 }
 
 impl EntityGeneration {
-    pub fn increment(&mut self) {
-        self.0 = NonZero::new(self.0.get() + 1).expect("EntityGeneration overflow");
+    /// Returns the next generation. If the generation is exhausted, this
+    /// returns [`INVALID`](Self::INVALID).
+    pub fn next(self) -> Self {
+        self.0.checked_add(1).map_or(Self::INVALID, Self)
     }
 }
 
@@ -276,7 +420,7 @@ impl<'a> Iterator for EntitiesIter<'a> {
 fn entities_iter_filter_map(
     (index, entity_meta): (usize, &EntityMeta),
 ) -> Option<(Entity, EntityLocation)> {
-    if entity_meta.is_empty() {
+    if entity_meta.location.is_invalid() {
         None
     }
     else {
@@ -289,3 +433,120 @@ fn entities_iter_filter_map(
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use super::{
+        Entities,
+        Entity,
+        EntityGeneration,
+        EntityLocation,
+        EntityRecycling,
+    };
+
+    #[test]
+    fn exhausted_index_is_retired() {
+        let mut entities = Entities::default();
+        let entity = entities.allocate().unwrap();
+        entities.set_location(entity, EntityLocation::EMPTY);
+        assert_eq!(entity.index, 0);
+
+        // fast-forward to the last valid generation
+        let last = EntityGeneration(NonZero::new(u32::MAX - 1).unwrap());
+        entities.meta[0].generation = last;
+        let entity = Entity::new(0, last);
+
+        entities.free(entity);
+        assert!(entities.get_location(entity).is_none());
+
+        let new = entities.allocate().unwrap();
+        assert_ne!(new.index, 0);
+    }
+
+    #[test]
+    fn fifo_recycling() {
+        let mut entities = Entities::default();
+        entities.set_recycling(EntityRecycling::Fifo);
+        let allocated = (0..3)
+            .map(|_| entities.allocate().unwrap())
+            .collect::<Vec<_>>();
+        for entity in &allocated {
+            entities.set_location(*entity, EntityLocation::EMPTY);
+        }
+
+        entities.free(allocated[0]);
+        entities.free(allocated[1]);
+        assert_eq!(entities.allocate().unwrap().index, 0);
+        assert_eq!(entities.allocate().unwrap().index, 1);
+    }
+
+    #[test]
+    fn delayed_recycling() {
+        let mut entities = Entities::default();
+        entities.set_recycling(EntityRecycling::Delayed { min_free: 2 });
+        let first = entities.allocate().unwrap();
+        entities.set_location(first, EntityLocation::EMPTY);
+
+        entities.free(first);
+        let second = entities.allocate().unwrap();
+        assert_eq!(second.index, 1);
+        entities.set_location(second, EntityLocation::EMPTY);
+
+        entities.free(second);
+        let third = entities.allocate().unwrap();
+        assert_eq!(third.index, 0);
+        assert_ne!(third, first);
+    }
+
+    #[test]
+    fn allocate_many() {
+        let mut entities = Entities::default();
+        let allocated = entities.allocate_many(3);
+        for entity in &allocated {
+            entities.set_location(*entity, EntityLocation::EMPTY);
+        }
+        entities.free(allocated[1]);
+
+        // freed indices are reused first
+        let allocated = entities.allocate_many(2);
+        assert_eq!(
+            allocated
+                .iter()
+                .map(|entity| entity.index)
+                .collect::<Vec<_>>(),
+            [1, 3]
+        );
+        assert_eq!(allocated[0].generation, entities.meta[1].generation);
+        assert_eq!(entities.allocate().unwrap().index, 4);
+    }
+
+    #[test]
+    fn clear_invalidates_allocated_entities() {
+        let mut entities = Entities::default();
+        let spawned = entities.allocate().unwrap();
+        entities.set_location(spawned, EntityLocation::EMPTY);
+        let reserved = entities.allocate_many(2);
+        let freed = entities.allocate().unwrap();
+        entities.set_location(freed, EntityLocation::EMPTY);
+        entities.free(freed);
+
+        entities.clear();
+        for entity in reserved.iter().chain([&spawned]) {
+            assert!(entity.generation < entities.meta[entity.index as usize].generation);
+        }
+        // free indices aren't freed again
+        assert_eq!(
+            entities.meta[freed.index as usize].generation,
+            freed.generation.next()
+        );
+
+        // handles to the reserved entities don't validate for the new ones
+        let allocated = entities.allocate_many(4);
+        for entity in &allocated {
+            assert!(!reserved.contains(entity));
+            assert_ne!(*entity, spawned);
+        }
+    }
+}
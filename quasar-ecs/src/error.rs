@@ -0,0 +1,87 @@
+use crate::{
+    entity::{
+        EntitiesExhausted,
+        Entity,
+    },
+    util::Joined,
+};
+
+/// Errors returned by fallible operations on a [`World`](crate::World) and its
+/// entities.
+#[derive(Debug, thiserror::Error)]
+pub enum QuasarError {
+    #[error(transparent)]
+    Entity(#[from] EntityError),
+
+    #[error(transparent)]
+    Bundle(#[from] BundleError),
+
+    #[error("Component {component} can't be cloned")]
+    Uncloneable { component: &'static str },
+
+    /// A value of a unique component was inserted, but another entity already
+    /// has it. See [`World::set_unique`](crate::World::set_unique).
+    #[error("Component {component} is unique, but entity {entity:?} already has the value")]
+    NotUnique {
+        component: &'static str,
+        entity: Entity,
+    },
+
+    /// The storage of a component was set after an entity already had it. See
+    /// [`World::set_component_storage`](crate::World::set_component_storage).
+    #[error("Component {component} is already stored in the world's tables")]
+    StorageInUse { component: &'static str },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EntityError {
+    #[error("Entity {0:?} was despawned")]
+    Despawned(Entity),
+
+    /// The entity was never spawned, e.g. because it belongs to another world.
+    #[error("Entity {0:?} never existed")]
+    NeverExisted(Entity),
+
+    /// The same entity was passed more than once where distinct entities are
+    /// required, e.g. to
+    /// [`World::get_many_entities_mut`](crate::World::get_many_entities_mut).
+    #[error("Entity {0:?} was passed more than once")]
+    Duplicate(Entity),
+
+    /// An entity inherits components from another entity that was passed
+    /// too, so they would share the inherited components. See
+    /// [`World::get_many_entities_mut`](crate::World::get_many_entities_mut).
+    #[error("Entity {entity:?} inherits from entity {prefab:?}, which was passed too")]
+    Inherits { entity: Entity, prefab: Entity },
+
+    #[error("Entity {entity:?} doesn't have component {component}")]
+    MissingComponent {
+        entity: Entity,
+        component: &'static str,
+    },
+
+    #[error(
+        "Entity {entity:?} doesn't have components {} of bundle {bundle}",
+        Joined::new(", ", .components)
+    )]
+    MissingComponents {
+        entity: Entity,
+        bundle: &'static str,
+        components: Vec<&'static str>,
+    },
+
+    #[error(transparent)]
+    Exhausted(#[from] EntitiesExhausted),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error(
+        "Bundle {bundle} contains duplicate components: {}",
+        Joined::new(", ", .components)
+    )]
+    DuplicateComponents {
+        bundle: &'static str,
+        components: Vec<&'static str>,
+    },
+}
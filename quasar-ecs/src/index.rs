@@ -0,0 +1,327 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt::Debug,
+    hash::Hash,
+    ops::RangeBounds,
+};
+
+use bevy_ptr::Ptr;
+use downcast_rs::{
+    impl_downcast,
+    Downcast,
+};
+
+use crate::{
+    component::ComponentId,
+    entity::Entity,
+    storage::table::{
+        Table,
+        TableRow,
+    },
+    util::sparse_map::SparseMap,
+};
+
+/// An index that maps values of a component to the entities that have them.
+///
+/// Indexes are created with
+/// [`World::add_hash_index`](crate::World::add_hash_index)
+/// or [`World::add_ordered_index`](crate::World::add_ordered_index), and are
+/// kept up to date by the world as components are inserted, replaced and
+/// removed. Only immutable components can be indexed, since mutating a
+/// component in place would bypass the index.
+trait ComponentIndex: Downcast {
+    /// # Safety
+    ///
+    /// `value` must point to a value of the indexed component.
+    unsafe fn insert(&mut self, entity: Entity, value: Ptr<'_>);
+
+    /// # Safety
+    ///
+    /// `value` must point to a value of the indexed component.
+    unsafe fn remove(&mut self, entity: Entity, value: Ptr<'_>);
+
+    /// # Safety
+    ///
+    /// `value` must point to a value of the indexed component.
+    unsafe fn lookup(&self, value: Ptr<'_>) -> &[Entity];
+
+    /// Returns an entity that shares its value with another entity, if there
+    /// is one.
+    fn find_duplicate(&self) -> Option<Entity>;
+
+    fn clear(&mut self);
+}
+
+impl_downcast!(ComponentIndex);
+
+/// Removes `entity` from the entities that have a value, and removes the
+/// value if no entity has it anymore.
+fn remove_entity(entities: &mut Vec<Entity>, entity: Entity) -> bool {
+    if let Some(index) = entities.iter().position(|other| *other == entity) {
+        entities.swap_remove(index);
+    }
+    entities.is_empty()
+}
+
+/// An index for components that are [`Eq`] and [`Hash`]. Lookups take O(1).
+struct HashIndex<C> {
+    entities: HashMap<C, Vec<Entity>>,
+}
+
+impl<C: Clone + Eq + Hash + 'static> ComponentIndex for HashIndex<C> {
+    unsafe fn insert(&mut self, entity: Entity, value: Ptr<'_>) {
+        let value = value.deref::<C>();
+        self.entities.entry(value.clone()).or_default().push(entity);
+    }
+
+    unsafe fn remove(&mut self, entity: Entity, value: Ptr<'_>) {
+        let value = value.deref::<C>();
+        if let Some(entities) = self.entities.get_mut(value) {
+            if remove_entity(entities, entity) {
+                self.entities.remove(value);
+            }
+        }
+    }
+
+    unsafe fn lookup(&self, value: Ptr<'_>) -> &[Entity] {
+        self.entities
+            .get(value.deref::<C>())
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    fn find_duplicate(&self) -> Option<Entity> {
+        self.entities
+            .values()
+            .find(|entities| entities.len() > 1)
+            .map(|entities| entities[1])
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+/// An index for components that are [`Ord`]. Lookups take O(log n), and
+/// ranges of values can be looked up.
+struct OrderedIndex<C> {
+    entities: BTreeMap<C, Vec<Entity>>,
+}
+
+impl<C: Clone + Ord + 'static> ComponentIndex for OrderedIndex<C> {
+    unsafe fn insert(&mut self, entity: Entity, value: Ptr<'_>) {
+        let value = value.deref::<C>();
+        self.entities.entry(value.clone()).or_default().push(entity);
+    }
+
+    unsafe fn remove(&mut self, entity: Entity, value: Ptr<'_>) {
+        let value = value.deref::<C>();
+        if let Some(entities) = self.entities.get_mut(value) {
+            if remove_entity(entities, entity) {
+                self.entities.remove(value);
+            }
+        }
+    }
+
+    unsafe fn lookup(&self, value: Ptr<'_>) -> &[Entity] {
+        self.entities
+            .get(value.deref::<C>())
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    fn find_duplicate(&self) -> Option<Entity> {
+        self.entities
+            .values()
+            .find(|entities| entities.len() > 1)
+            .map(|entities| entities[1])
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+/// What happens when a value of a unique component is inserted, that another
+/// entity already has. See [`World::set_unique`](crate::World::set_unique).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UniquePolicy {
+    /// Inserting the value fails with
+    /// [`QuasarError::NotUnique`](crate::QuasarError::NotUnique), and nothing
+    /// is changed.
+    #[default]
+    Error,
+
+    /// The component is removed from the entity that had the value, and the
+    /// value is inserted.
+    Replace,
+}
+
+/// The component indexes of a world.
+#[derive(Default)]
+pub struct Indexes {
+    indexes: SparseMap<ComponentId, Box<dyn ComponentIndex>>,
+    /// The indexed components whose values must be unique.
+    unique: SparseMap<ComponentId, UniquePolicy>,
+}
+
+impl Indexes {
+    pub fn insert_hash_index<C: Clone + Eq + Hash + 'static>(&mut self, component_id: ComponentId) {
+        self.indexes.insert(
+            &component_id,
+            Box::new(HashIndex::<C> {
+                entities: HashMap::new(),
+            }),
+        );
+    }
+
+    pub fn insert_ordered_index<C: Clone + Ord + 'static>(&mut self, component_id: ComponentId) {
+        self.indexes.insert(
+            &component_id,
+            Box::new(OrderedIndex::<C> {
+                entities: BTreeMap::new(),
+            }),
+        );
+    }
+
+    /// Makes the values of an indexed component unique. Returns `None` if the
+    /// component isn't indexed, or an entity that shares its value with
+    /// another one, in which case the component isn't made unique.
+    pub fn set_unique(
+        &mut self,
+        component_id: ComponentId,
+        policy: UniquePolicy,
+    ) -> Option<Result<(), Entity>> {
+        if let Some(entity) = self.indexes.get(&component_id)?.find_duplicate() {
+            return Some(Err(entity));
+        }
+        self.unique.insert(&component_id, policy);
+        Some(Ok(()))
+    }
+
+    /// Returns whether any of the components must be unique.
+    pub fn any_unique(&self, component_ids: impl IntoIterator<Item = ComponentId>) -> bool {
+        !self.unique.is_empty()
+            && component_ids
+                .into_iter()
+                .any(|component_id| self.unique.contains_key(&component_id))
+    }
+
+    /// Returns another entity that has the value of a unique component, and
+    /// the policy for the component.
+    ///
+    /// # Safety
+    ///
+    /// `value` must point to a value of the component with `component_id`.
+    pub unsafe fn find_conflict(
+        &self,
+        component_id: ComponentId,
+        value: Ptr<'_>,
+        entity: Entity,
+    ) -> Option<(Entity, UniquePolicy)> {
+        let policy = *self.unique.get(&component_id)?;
+        let other = self
+            .lookup(component_id, value)?
+            .iter()
+            .copied()
+            .find(|other| *other != entity)?;
+        Some((other, policy))
+    }
+
+    /// Returns the entities whose component has the value `value`, or `None`
+    /// if the component isn't indexed.
+    ///
+    /// # Safety
+    ///
+    /// `value` must point to a value of the component with `component_id`.
+    pub unsafe fn lookup(&self, component_id: ComponentId, value: Ptr<'_>) -> Option<&[Entity]> {
+        Some(self.indexes.get(&component_id)?.lookup(value))
+    }
+
+    /// Returns the entities whose component has a value in `range`, in the
+    /// order of their values, or `None` if the component doesn't have an
+    /// ordered index.
+    pub fn lookup_range<C: Ord + 'static>(
+        &self,
+        component_id: ComponentId,
+        range: impl RangeBounds<C>,
+    ) -> Option<impl Iterator<Item = Entity> + '_> {
+        let index: &dyn ComponentIndex = &**self.indexes.get(&component_id)?;
+        let index = index.as_any().downcast_ref::<OrderedIndex<C>>()?;
+        Some(
+            index
+                .entities
+                .range(range)
+                .flat_map(|(_value, entities)| entities.iter().copied()),
+        )
+    }
+
+    /// Adds the entity's values of the components to their indexes. Components
+    /// that aren't indexed, or that the entity doesn't own, are skipped.
+    pub fn insert_entity(
+        &mut self,
+        entity: Entity,
+        component_ids: impl IntoIterator<Item = ComponentId>,
+        table: &Table,
+        table_row: TableRow,
+    ) {
+        if self.indexes.is_empty() {
+            return;
+        }
+        for component_id in component_ids {
+            let Some(index) = self.indexes.get_mut(&component_id)
+            else {
+                continue;
+            };
+            if let Some(value) = table.get_component_ptr(component_id, table_row) {
+                unsafe {
+                    // SAFETY: The value was taken from the column of the indexed component.
+                    index.insert(entity, value);
+                }
+            }
+        }
+    }
+
+    /// Removes the entity's values of the components from their indexes. This
+    /// must be called before the components are replaced or removed.
+    pub fn remove_entity(
+        &mut self,
+        entity: Entity,
+        component_ids: impl IntoIterator<Item = ComponentId>,
+        table: &Table,
+        table_row: TableRow,
+    ) {
+        if self.indexes.is_empty() {
+            return;
+        }
+        for component_id in component_ids {
+            let Some(index) = self.indexes.get_mut(&component_id)
+            else {
+                continue;
+            };
+            if let Some(value) = table.get_component_ptr(component_id, table_row) {
+                unsafe {
+                    // SAFETY: The value was taken from the column of the indexed component.
+                    index.remove(entity, value);
+                }
+            }
+        }
+    }
+
+    /// Removes all entities from the indexes, but keeps the indexes.
+    pub fn clear(&mut self) {
+        for index in self.indexes.values_mut() {
+            index.clear();
+        }
+    }
+}
+
+impl Debug for Indexes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Indexes")
+            .field("components", &self.indexes.keys().collect::<Vec<_>>())
+            .field("unique", &self.unique)
+            .finish()
+    }
+}
@@ -1,9 +1,16 @@
 mod archetype;
 mod bundle;
+mod clone;
 mod command;
 mod component;
+mod disabled;
 mod entity;
+mod error;
+mod index;
+mod prefab;
+mod query;
 mod resources;
+mod shared;
 mod storage;
 mod util;
 mod world;
@@ -13,22 +20,103 @@ extern crate alloc;
 // hack to get the proc-macro working from this crate
 extern crate self as quasar_ecs;
 
+#[cfg(feature = "derive")]
+pub use quasar_ecs_derive::{
+    Bundle,
+    Component,
+};
+
 pub use crate::{
-    bundle::DynamicBundle,
-    component::Component,
-    storage::StorageType,
+    archetype::Archetypes,
+    bundle::{
+        Bundle,
+        DynamicBundle,
+    },
+    clone::UncloneablePolicy,
+    component::{
+        Component,
+        ComponentDescriptor,
+        ComponentHook,
+        ComponentHooks,
+        ComponentId,
+        Components,
+        MutableComponent,
+        RequiredComponent,
+        RequiredComponents,
+    },
+    disabled::Disabled,
+    entity::{
+        Entities,
+        EntitiesExhausted,
+        Entity,
+        EntityRecycling,
+    },
+    error::{
+        BundleError,
+        EntityError,
+        QuasarError,
+    },
+    index::UniquePolicy,
+    prefab::{
+        IsA,
+        Prefab,
+    },
+    query::{
+        Access,
+        Allow,
+        QueryData,
+        QueryFilter,
+        QueryIter,
+        ReadOnlyQueryData,
+        With,
+        Without,
+    },
+    resources::{
+        Resource,
+        Resources,
+    },
+    shared::{
+        Shared,
+        SharedComponent,
+    },
+    storage::{
+        custom::{
+            ComponentStorage,
+            Tick,
+        },
+        table::{
+            TableLayout,
+            Tables,
+        },
+        StorageType,
+    },
+    util::CloneFn,
     world::{
+        ComponentEntry,
         EntityIter,
         EntityMut,
         EntityRef,
+        EntityWorldMut,
+        InheritedComponentEntry,
+        OccupiedComponentEntry,
+        Replaced,
+        SpawnBatchIter,
+        UnsafeWorldCell,
+        VacantComponentEntry,
         World,
         WorldId,
     },
 };
 
+#[doc(hidden)]
+pub mod component_impl {
+    pub use crate::util::clone_ptr;
+}
+
 #[doc(hidden)]
 pub mod bundle_impl {
     pub use crate::bundle::{
+        ComponentRefsCallback,
         ComponentTypesCallback,
         FromComponentsCallback,
         IntoComponentsCallback,
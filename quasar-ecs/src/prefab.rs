@@ -0,0 +1,151 @@
+use crate::{
+    archetype::Archetypes,
+    component::{
+        Component,
+        ComponentId,
+        Components,
+        MutableComponent,
+    },
+    entity::{
+        Entities,
+        Entity,
+        EntityLocation,
+    },
+    storage::{
+        table::{
+            Table,
+            TableRow,
+            Tables,
+        },
+        StorageType,
+    },
+    util::{
+        clone_ptr,
+        CloneFn,
+    },
+};
+
+/// Marks an entity as a prefab.
+///
+/// Prefabs are excluded from queries, unless the query explicitly mentions
+/// [`Prefab`], e.g. with `With<Prefab>`. Other entities can inherit a
+/// prefab's components with [`IsA`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Prefab;
+
+impl Component for Prefab {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+    const CLONE_FN: Option<CloneFn> = Some(clone_ptr::<Self>);
+}
+
+// SAFETY: `Prefab` is mutable.
+unsafe impl MutableComponent for Prefab {}
+
+/// Makes an entity an instance of a prefab.
+///
+/// An instance inherits all components of its prefab that it doesn't have
+/// itself. Inherited components can be read as if the instance had them.
+/// Mutably accessing an inherited component through
+/// [`EntityWorldMut::get_mut`](crate::EntityWorldMut::get_mut) first clones it
+/// into the instance, after which the instance has its own copy.
+///
+/// Prefabs can themselves be instances of other prefabs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IsA(pub Entity);
+
+impl Component for IsA {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+    const CLONE_FN: Option<CloneFn> = Some(clone_ptr::<Self>);
+}
+
+// SAFETY: `IsA` is mutable.
+unsafe impl MutableComponent for IsA {}
+
+/// Maximum length of a chain of prefabs. This guards against cycles.
+const MAX_DEPTH: usize = 64;
+
+/// Finds where the component of an entity is stored. This is either the
+/// entity itself, or the closest prefab in its [`IsA`] chain that has the
+/// component. The [`Prefab`] marker itself isn't inherited.
+pub fn find_component<'w>(
+    entity_location: EntityLocation,
+    component_id: ComponentId,
+    components: &Components,
+    entities: &Entities,
+    archetypes: &Archetypes,
+    tables: &'w Tables,
+) -> Option<(&'w Table, TableRow)> {
+    let location = find_component_owner(
+        entity_location,
+        component_id,
+        components,
+        entities,
+        archetypes,
+        tables,
+    )?;
+    Some((tables.get(location.table_id), location.table_row))
+}
+
+/// Like [`find_component`], but returns the location of the entity that has
+/// the component. This is `entity_location` itself, if the entity isn't
+/// inheriting it.
+pub fn find_component_owner(
+    mut entity_location: EntityLocation,
+    component_id: ComponentId,
+    components: &Components,
+    entities: &Entities,
+    archetypes: &Archetypes,
+    tables: &Tables,
+) -> Option<EntityLocation> {
+    let is_a_id = components.get_component_id::<IsA>();
+
+    for _ in 0..MAX_DEPTH {
+        if entity_location.archetype_id.is_invalid() {
+            return None;
+        }
+
+        let archetype = archetypes.get(entity_location.archetype_id);
+        if archetype.contains_component(component_id) {
+            return Some(entity_location);
+        }
+        if components.get_component_id::<Prefab>() == Some(component_id) {
+            return None;
+        }
+
+        let is_a = unsafe {
+            // SAFETY: The column for `IsA` stores values of type `IsA`.
+            tables
+                .get(entity_location.table_id)
+                .get_component::<IsA>(is_a_id?, entity_location.table_row)?
+        };
+        entity_location = entities.get_location(is_a.0)?;
+    }
+
+    None
+}
+
+/// Returns the prefabs in the [`IsA`] chain of an entity, starting with its
+/// own prefab.
+pub fn prefabs<'w>(
+    mut entity_location: EntityLocation,
+    components: &Components,
+    entities: &'w Entities,
+    tables: &'w Tables,
+) -> impl Iterator<Item = Entity> + 'w {
+    let is_a_id = components.get_component_id::<IsA>();
+
+    std::iter::from_fn(move || {
+        if entity_location.archetype_id.is_invalid() {
+            return None;
+        }
+        let is_a = unsafe {
+            // SAFETY: The column for `IsA` stores values of type `IsA`.
+            tables
+                .get(entity_location.table_id)
+                .get_component::<IsA>(is_a_id?, entity_location.table_row)?
+        };
+        entity_location = entities.get_location(is_a.0)?;
+        Some(is_a.0)
+    })
+    .take(MAX_DEPTH)
+}
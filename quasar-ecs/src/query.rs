@@ -0,0 +1,646 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+};
+
+use crate::{
+    archetype::{
+        Archetype,
+        ArchetypeEntity,
+        ArchetypeId,
+    },
+    component::{
+        Component,
+        ComponentId,
+        MutableComponent,
+    },
+    entity::Entity,
+    prefab::{
+        self,
+        IsA,
+    },
+    storage::table::Table,
+    util::{
+        sparse_set::SparseSet,
+        Joined,
+    },
+    world::World,
+};
+
+/// Data that can be fetched by a query, e.g. `&C`, `&mut C`, [`Entity`] or
+/// tuples of these.
+///
+/// # Safety
+///
+/// - [`update_access`](Self::update_access) must register all components that
+///   [`fetch`](Self::fetch) reads or writes.
+/// - [`fetch`](Self::fetch) must only be called for archetypes for which
+///   [`matches_archetype`](Self::matches_archetype) returned `true`.
+pub unsafe trait QueryData {
+    type Item<'w>;
+    type State;
+
+    fn init_state(world: &World) -> Self::State;
+
+    fn update_access(state: &Self::State, access: &mut Access);
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool;
+
+    /// Fetches the data for an entity. Returns `None` if the entity should be
+    /// skipped.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that no other references to components that this
+    /// query accesses mutably exist.
+    unsafe fn fetch<'w>(
+        state: &Self::State,
+        world: &'w World,
+        archetype: &'w Archetype,
+        table: &'w Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>>;
+}
+
+/// [`QueryData`] that only reads components.
+///
+/// # Safety
+///
+/// The implementation must not register any write access.
+pub unsafe trait ReadOnlyQueryData: QueryData {}
+
+/// Filters the archetypes a query matches, without fetching any data.
+pub trait QueryFilter {
+    type State;
+
+    fn init_state(world: &World) -> Self::State;
+
+    fn update_access(state: &Self::State, access: &mut Access);
+
+    /// Returns whether the filter may match entities in an archetype, whose
+    /// components are stored in `table`.
+    fn matches_archetype(state: &Self::State, archetype: &Archetype, table: &Table) -> bool;
+
+    /// Returns whether the filter matches an entity in an archetype that it
+    /// matched. This is for filters that depend on the entity's row, e.g.
+    /// whether a component is [disabled](World::disable_component).
+    fn matches_entity(
+        _state: &Self::State,
+        _table: &Table,
+        _archetype_entity: ArchetypeEntity,
+    ) -> bool {
+        true
+    }
+}
+
+/// The components a query reads, writes and filters by.
+#[derive(Debug, Default)]
+pub struct Access {
+    read: SparseSet<ComponentId>,
+    write: SparseSet<ComponentId>,
+    mentioned: SparseSet<ComponentId>,
+    conflicts: Vec<ComponentId>,
+}
+
+impl Access {
+    pub fn add_read(&mut self, component_id: ComponentId) {
+        if self.write.contains(&component_id) {
+            self.conflicts.push(component_id);
+        }
+        self.read.insert(&component_id);
+        self.mentioned.insert(&component_id);
+    }
+
+    pub fn add_write(&mut self, component_id: ComponentId) {
+        if self.read.contains(&component_id) || self.write.contains(&component_id) {
+            self.conflicts.push(component_id);
+        }
+        self.write.insert(&component_id);
+        self.mentioned.insert(&component_id);
+    }
+
+    /// Registers a component that the query filters by, without accessing it.
+    pub fn add_filter(&mut self, component_id: ComponentId) {
+        self.mentioned.insert(&component_id);
+    }
+
+    /// Returns whether the query explicitly mentions this component, e.g. by
+    /// reading it or filtering by it.
+    pub fn mentions(&self, component_id: ComponentId) -> bool {
+        self.mentioned.contains(&component_id)
+    }
+}
+
+/// Components that cause entities to be excluded from queries, unless the
+/// query explicitly mentions them.
+#[derive(Debug, Default)]
+pub struct DefaultQueryFilters {
+    excluded: Vec<ComponentId>,
+}
+
+impl DefaultQueryFilters {
+    pub fn exclude(&mut self, component_id: ComponentId) {
+        if !self.excluded.contains(&component_id) {
+            self.excluded.push(component_id);
+        }
+    }
+
+    pub(crate) fn matches_archetype(&self, access: &Access, archetype: &Archetype) -> bool {
+        self.excluded.iter().all(|component_id| {
+            access.mentions(*component_id) || !archetype.contains_component(*component_id)
+        })
+    }
+}
+
+/// Returns the archetypes that the filter `F` matches, taking the world's
+/// [`DefaultQueryFilters`] into account, and the entities in each that it
+/// matches.
+pub(crate) fn filter_entities<F: QueryFilter>(world: &World) -> Vec<(ArchetypeId, Vec<Entity>)> {
+    let state = F::init_state(world);
+    let mut access = Access::default();
+    F::update_access(&state, &mut access);
+
+    world
+        .archetypes()
+        .iter()
+        .filter(|archetype| {
+            F::matches_archetype(&state, archetype, world.tables().get(archetype.table_id()))
+                && world
+                    .default_query_filters()
+                    .matches_archetype(&access, archetype)
+        })
+        .map(|archetype| {
+            let table = world.tables().get(archetype.table_id());
+            let entities = archetype
+                .entities()
+                .iter()
+                .filter(|archetype_entity| F::matches_entity(&state, table, **archetype_entity))
+                .map(|archetype_entity| archetype_entity.entity)
+                .collect();
+            (archetype.id(), entities)
+        })
+        .collect()
+}
+
+/// Iterator over the entities matching a query.
+///
+/// Created by [`World::query`] and its siblings.
+pub struct QueryIter<'w, D: QueryData, F: QueryFilter> {
+    world: &'w World,
+    state: D::State,
+    filter_state: F::State,
+    archetype_ids: std::vec::IntoIter<ArchetypeId>,
+    current: Option<(&'w Archetype, &'w Table)>,
+    archetype_entities: std::slice::Iter<'w, ArchetypeEntity>,
+    _filter: PhantomData<fn() -> F>,
+}
+
+impl<'w, D: QueryData, F: QueryFilter> QueryIter<'w, D, F> {
+    /// # Safety
+    ///
+    /// If `D` is not [`ReadOnlyQueryData`], the caller must have exclusive
+    /// access to the world for `'w`.
+    pub(crate) unsafe fn new(world: &'w World) -> Self {
+        Self::with_archetype_filter(world, |_archetype| true)
+    }
+
+    /// Like [`new`](Self::new), but only matches the archetypes for which
+    /// `filter` returns `true`.
+    ///
+    /// # Safety
+    ///
+    /// See [`new`](Self::new).
+    pub(crate) unsafe fn with_archetype_filter(
+        world: &'w World,
+        filter: impl Fn(&Archetype) -> bool,
+    ) -> Self {
+        let state = D::init_state(world);
+        let filter_state = F::init_state(world);
+
+        let mut access = Access::default();
+        D::update_access(&state, &mut access);
+        F::update_access(&filter_state, &mut access);
+        if !access.conflicts.is_empty() {
+            let names = access
+                .conflicts
+                .iter()
+                .map(|component_id| {
+                    world
+                        .components()
+                        .get_component_info(*component_id)
+                        .descriptor()
+                        .name()
+                })
+                .collect::<Vec<_>>();
+            panic!(
+                "Query {} accesses components mutably that it also accesses otherwise: {}",
+                std::any::type_name::<D>(),
+                Joined::new(", ", &names)
+            );
+        }
+
+        let archetype_ids = world
+            .archetypes()
+            .iter()
+            .filter(|archetype| {
+                filter(archetype)
+                    && D::matches_archetype(&state, archetype)
+                    && F::matches_archetype(
+                        &filter_state,
+                        archetype,
+                        world.tables().get(archetype.table_id()),
+                    )
+                    && world
+                        .default_query_filters()
+                        .matches_archetype(&access, archetype)
+            })
+            .map(|archetype| archetype.id())
+            .collect::<Vec<_>>();
+
+        Self {
+            world,
+            state,
+            filter_state,
+            archetype_ids: archetype_ids.into_iter(),
+            current: None,
+            archetype_entities: [].iter(),
+            _filter: PhantomData,
+        }
+    }
+}
+
+impl<'w, D: QueryData, F: QueryFilter> Iterator for QueryIter<'w, D, F> {
+    type Item = D::Item<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((archetype, table)) = self.current {
+                for archetype_entity in self.archetype_entities.by_ref() {
+                    if !F::matches_entity(&self.filter_state, table, *archetype_entity) {
+                        continue;
+                    }
+                    let item = unsafe {
+                        // SAFETY: The archetype was matched by `D`, and the caller of `new`
+                        // ensured that we have the required access. Since every entity is
+                        // only visited once, no two items alias.
+                        D::fetch(&self.state, self.world, archetype, table, *archetype_entity)
+                    };
+                    if item.is_some() {
+                        return item;
+                    }
+                }
+            }
+
+            let archetype = self.world.archetypes().get(self.archetype_ids.next()?);
+            self.current = Some((archetype, self.world.tables().get(archetype.table_id())));
+            self.archetype_entities = archetype.entities().iter();
+        }
+    }
+}
+
+impl<'w, D: QueryData, F: QueryFilter> Debug for QueryIter<'w, D, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryIter").finish_non_exhaustive()
+    }
+}
+
+unsafe impl QueryData for Entity {
+    type Item<'w> = Entity;
+    type State = ();
+
+    fn init_state(_world: &World) -> Self::State {}
+
+    fn update_access(_state: &Self::State, _access: &mut Access) {}
+
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    unsafe fn fetch<'w>(
+        _state: &Self::State,
+        _world: &'w World,
+        _archetype: &'w Archetype,
+        _table: &'w Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>> {
+        Some(archetype_entity.entity)
+    }
+}
+
+unsafe impl ReadOnlyQueryData for Entity {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReadState {
+    component_id: ComponentId,
+    is_a_id: Option<ComponentId>,
+}
+
+/// Reads a component. This also matches entities that inherit the component
+/// from a prefab.
+unsafe impl<C: Component> QueryData for &C {
+    type Item<'w> = &'w C;
+    type State = Option<ReadState>;
+
+    fn init_state(world: &World) -> Self::State {
+        Some(ReadState {
+            component_id: world.components().get_component_id::<C>()?,
+            is_a_id: world.components().get_component_id::<IsA>(),
+        })
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(state) = state {
+            access.add_read(state.component_id);
+            // inherited components are found through `IsA`.
+            if let Some(is_a_id) = state.is_a_id {
+                access.add_read(is_a_id);
+            }
+        }
+    }
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        state.is_some_and(|state| {
+            archetype.contains_component(state.component_id)
+                || state
+                    .is_a_id
+                    .is_some_and(|is_a_id| archetype.contains_component(is_a_id))
+        })
+    }
+
+    unsafe fn fetch<'w>(
+        state: &Self::State,
+        world: &'w World,
+        _archetype: &'w Archetype,
+        table: &'w Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>> {
+        let state = state.as_ref()?;
+
+        if let Some(component) =
+            table.get_component::<C>(state.component_id, archetype_entity.table_row)
+        {
+            // a disabled component is treated as absent, and doesn't fall back to an
+            // inherited one.
+            return table
+                .is_enabled(state.component_id, archetype_entity.table_row)
+                .then_some(component);
+        }
+
+        // the entity doesn't have the component, but it's an instance and might
+        // inherit it.
+        let entity_location = world.entities().get_location(archetype_entity.entity)?;
+        let (table, table_row) = prefab::find_component(
+            entity_location,
+            state.component_id,
+            world.components(),
+            world.entities(),
+            world.archetypes(),
+            world.tables(),
+        )?;
+        table.get_component::<C>(state.component_id, table_row)
+    }
+}
+
+unsafe impl<C: Component> ReadOnlyQueryData for &C {}
+
+/// Mutably accesses a component. Unlike `&C`, this only matches entities that
+/// have the component themselves, and not those that inherit it from a prefab.
+///
+/// Only [mutable components](MutableComponent) can be queried mutably.
+unsafe impl<C: MutableComponent> QueryData for &mut C {
+    type Item<'w> = &'w mut C;
+    type State = Option<ComponentId>;
+
+    fn init_state(world: &World) -> Self::State {
+        world.components().get_component_id::<C>()
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(component_id) = state {
+            access.add_write(*component_id);
+        }
+    }
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        state.is_some_and(|component_id| archetype.contains_component(component_id))
+    }
+
+    unsafe fn fetch<'w>(
+        state: &Self::State,
+        _world: &'w World,
+        _archetype: &'w Archetype,
+        table: &'w Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>> {
+        let component_id = (*state)?;
+        if !table.is_enabled(component_id, archetype_entity.table_row) {
+            return None;
+        }
+        let cell = table.get_component_unsafe::<C>(component_id, archetype_entity.table_row)?;
+        Some(&mut *cell.get())
+    }
+}
+
+/// Fetches `D` if it matches, and `None` otherwise. This matches all
+/// archetypes.
+unsafe impl<D: QueryData> QueryData for Option<D> {
+    type Item<'w> = Option<D::Item<'w>>;
+    type State = D::State;
+
+    fn init_state(world: &World) -> Self::State {
+        D::init_state(world)
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        D::update_access(state, access);
+    }
+
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    unsafe fn fetch<'w>(
+        state: &Self::State,
+        world: &'w World,
+        archetype: &'w Archetype,
+        table: &'w Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>> {
+        if D::matches_archetype(state, archetype) {
+            Some(D::fetch(state, world, archetype, table, archetype_entity))
+        }
+        else {
+            Some(None)
+        }
+    }
+}
+
+unsafe impl<D: ReadOnlyQueryData> ReadOnlyQueryData for Option<D> {}
+
+/// Only matches entities that have the component `C`, and haven't
+/// [disabled](World::disable_component) it.
+#[derive(Debug)]
+pub struct With<C>(PhantomData<fn() -> C>);
+
+impl<C: Component> QueryFilter for With<C> {
+    type State = Option<ComponentId>;
+
+    fn init_state(world: &World) -> Self::State {
+        world.components().get_component_id::<C>()
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(component_id) = state {
+            access.add_filter(*component_id);
+        }
+    }
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype, _table: &Table) -> bool {
+        state.is_some_and(|component_id| archetype.contains_component(component_id))
+    }
+
+    fn matches_entity(
+        state: &Self::State,
+        table: &Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> bool {
+        state.is_some_and(|component_id| table.is_enabled(component_id, archetype_entity.table_row))
+    }
+}
+
+/// Only matches entities that don't have the component `C`, or have
+/// [disabled](World::disable_component) it.
+#[derive(Debug)]
+pub struct Without<C>(PhantomData<fn() -> C>);
+
+impl<C: Component> QueryFilter for Without<C> {
+    type State = Option<ComponentId>;
+
+    fn init_state(world: &World) -> Self::State {
+        world.components().get_component_id::<C>()
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(component_id) = state {
+            access.add_filter(*component_id);
+        }
+    }
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype, table: &Table) -> bool {
+        // archetypes with the component may still have entities that disabled it.
+        state.is_none_or(|component_id| {
+            !archetype.contains_component(component_id) || table.has_disabled(component_id)
+        })
+    }
+
+    fn matches_entity(
+        state: &Self::State,
+        table: &Table,
+        archetype_entity: ArchetypeEntity,
+    ) -> bool {
+        state.is_none_or(|component_id| !table.is_enabled(component_id, archetype_entity.table_row))
+    }
+}
+
+/// Matches all entities, but mentions the component `C`, so that entities with
+/// it are no longer excluded by default, e.g. `Allow<Disabled>` to include
+/// [disabled](crate::Disabled) entities.
+#[derive(Debug)]
+pub struct Allow<C>(PhantomData<fn() -> C>);
+
+impl<C: Component> QueryFilter for Allow<C> {
+    type State = Option<ComponentId>;
+
+    fn init_state(world: &World) -> Self::State {
+        world.components().get_component_id::<C>()
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(component_id) = state {
+            access.add_filter(*component_id);
+        }
+    }
+
+    fn matches_archetype(_state: &Self::State, _archetype: &Archetype, _table: &Table) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: QueryData),*> QueryData for ($($name,)*) {
+            type Item<'w> = ($($name::Item<'w>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access);)*
+            }
+
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_archetype($name, archetype))*
+            }
+
+            unsafe fn fetch<'w>(
+                state: &Self::State,
+                world: &'w World,
+                archetype: &'w Archetype,
+                table: &'w Table,
+                archetype_entity: ArchetypeEntity,
+            ) -> Option<Self::Item<'w>> {
+                let ($($name,)*) = state;
+                Some(($($name::fetch($name, world, archetype, table, archetype_entity)?,)*))
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyQueryData),*> ReadOnlyQueryData for ($($name,)*) {}
+
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access);)*
+            }
+
+            fn matches_archetype(
+                state: &Self::State,
+                archetype: &Archetype,
+                table: &Table,
+            ) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_archetype($name, archetype, table))*
+            }
+
+            fn matches_entity(
+                state: &Self::State,
+                table: &Table,
+                archetype_entity: ArchetypeEntity,
+            ) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_entity($name, table, archetype_entity))*
+            }
+        }
+    };
+}
+
+impl_query_tuple!();
+impl_query_tuple!(T0);
+impl_query_tuple!(T0, T1);
+impl_query_tuple!(T0, T1, T2);
+impl_query_tuple!(T0, T1, T2, T3);
+impl_query_tuple!(T0, T1, T2, T3, T4);
+impl_query_tuple!(T0, T1, T2, T3, T4, T5);
+impl_query_tuple!(T0, T1, T2, T3, T4, T5, T6);
+impl_query_tuple!(T0, T1, T2, T3, T4, T5, T6, T7);
@@ -0,0 +1,339 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Debug,
+    hash::{
+        BuildHasher,
+        Hash,
+        RandomState,
+    },
+    marker::PhantomData,
+};
+
+use crate::{
+    archetype::{
+        Archetype,
+        ArchetypeEntity,
+        Archetypes,
+    },
+    component::{
+        Component,
+        ComponentId,
+        Components,
+    },
+    query::{
+        Access,
+        QueryData,
+        ReadOnlyQueryData,
+    },
+    storage::{
+        table::Table,
+        StorageType,
+    },
+    util::{
+        clone_ptr,
+        sparse_map::SparseMap,
+        CloneFn,
+    },
+    world::World,
+};
+
+/// A component whose values are stored once per distinct value, and shared by
+/// all entities that have them, e.g. a material or a mesh handle.
+///
+/// Shared components are inserted with
+/// [`EntityWorldMut::insert_shared`](crate::EntityWorldMut::insert_shared),
+/// and read with the query data [`Shared<C>`] or
+/// [`EntityWorldMut::get_shared`](crate::EntityWorldMut::get_shared).
+///
+/// Each distinct value is part of the archetype of the entities that have it,
+/// so entities with the same value are stored next to each other, and can be
+/// queried as a group with [`World::query_shared`]. Values can't be mutated in
+/// place, only replaced by inserting another value. Values that no entity has
+/// anymore are eventually forgotten, and the components that represented them
+/// are reused for other values.
+pub trait SharedComponent: Eq + Hash + 'static {}
+
+/// Marks the entities that have a value of the shared component `C`, e.g. to
+/// filter by it with `With<Shared<C>>`. Removing it removes the value.
+///
+/// As query data, this fetches the entity's value of `C`.
+pub struct Shared<C>(PhantomData<fn() -> C>);
+
+impl<C> Shared<C> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<C> Clone for Shared<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Shared<C> {}
+
+impl<C> Debug for Shared<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Shared").finish()
+    }
+}
+
+impl<C: SharedComponent> Component for Shared<C> {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+    const CLONE_FN: Option<CloneFn> = Some(clone_ptr::<Self>);
+    const MUTABLE: bool = false;
+}
+
+/// Fetches the entity's value of the shared component `C`.
+unsafe impl<C: SharedComponent> QueryData for Shared<C> {
+    type Item<'w> = &'w C;
+    type State = Option<ComponentId>;
+
+    fn init_state(world: &World) -> Self::State {
+        world.components().get_component_id::<Shared<C>>()
+    }
+
+    fn update_access(state: &Self::State, access: &mut Access) {
+        if let Some(shared_id) = state {
+            access.add_read(*shared_id);
+        }
+    }
+
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+        state.is_some_and(|shared_id| archetype.contains_component(shared_id))
+    }
+
+    unsafe fn fetch<'w>(
+        state: &Self::State,
+        world: &'w World,
+        archetype: &'w Archetype,
+        _table: &'w Table,
+        _archetype_entity: ArchetypeEntity,
+    ) -> Option<Self::Item<'w>> {
+        let shared_id = (*state)?;
+        let value_id = archetype.shared_value(shared_id)?;
+        world.shared().get(shared_id, value_id)
+    }
+}
+
+unsafe impl<C: SharedComponent> ReadOnlyQueryData for Shared<C> {}
+
+/// The values of the shared components of a world.
+///
+/// Each distinct value is represented by its own component, which has no data
+/// and is inserted along with [`Shared<C>`]. Archetypes and tables are thus
+/// split by value.
+#[derive(Default)]
+pub struct SharedComponents {
+    /// The values of each shared component, by the id of its [`Shared`]
+    /// marker. The values are [`SharedValues`].
+    values: SparseMap<ComponentId, Box<dyn ErasedSharedValues>>,
+}
+
+impl SharedComponents {
+    /// Returns the id of the component that represents `value`. If no entity
+    /// has the value, this reuses the id of a value that no entity has
+    /// anymore, or registers a new one.
+    pub fn get_or_insert<C: SharedComponent>(
+        &mut self,
+        shared_id: ComponentId,
+        value: C,
+        components: &mut Components,
+        archetypes: &Archetypes,
+    ) -> ComponentId {
+        let values = self
+            .values
+            .entry(&shared_id)
+            .or_insert_with(|| Box::new(SharedValues::<C>::default()))
+            .into_mut()
+            .as_any_mut()
+            .downcast_mut::<SharedValues<C>>()
+            .expect("shared values of the wrong type");
+
+        let hash = values.hasher.hash_one(&value);
+        if let Some(index) = values.find(hash, &value) {
+            return values.value_ids[index];
+        }
+
+        // looking for unused values takes a pass over all archetypes, so it's only
+        // done once the number of values doubled.
+        if values.free.is_empty() && values.values.len() >= values.reclaim_at {
+            values.reclaim_unused(shared_id, archetypes);
+        }
+
+        let index = if let Some(index) = values.free.pop() {
+            values.values[index] = Some(value);
+            index
+        }
+        else {
+            // the value ids are registered after the `Shared<C>` marker, so they always
+            // sort after it.
+            let value_id = components.register_shared_value::<C>(shared_id);
+            let index = values.values.len();
+            values.values.push(Some(value));
+            values.value_ids.push(value_id);
+            values.by_id.insert(&value_id, index);
+            index
+        };
+        values.by_hash.entry(hash).or_default().push(index);
+        values.value_ids[index]
+    }
+
+    /// Forgets the values that no entity has, so that the components that
+    /// represent them can be reused for other values.
+    pub fn reclaim_unused(&mut self, archetypes: &Archetypes) {
+        for (shared_id, values) in self.values.iter_mut() {
+            values.reclaim_unused(shared_id, archetypes);
+        }
+    }
+
+    /// Returns the id of the component that represents `value`, if an entity
+    /// has the value. This might also return the id of a value that no entity
+    /// has anymore, but that wasn't forgotten yet.
+    pub fn get_id<C: SharedComponent>(
+        &self,
+        shared_id: ComponentId,
+        value: &C,
+    ) -> Option<ComponentId> {
+        let values = self.get_values::<C>(shared_id)?;
+        let index = values.find(values.hasher.hash_one(value), value)?;
+        Some(values.value_ids[index])
+    }
+
+    /// Returns the value that the component with `value_id` represents.
+    pub fn get<C: SharedComponent>(
+        &self,
+        shared_id: ComponentId,
+        value_id: ComponentId,
+    ) -> Option<&C> {
+        let values = self.get_values::<C>(shared_id)?;
+        let index = *values.by_id.get(&value_id)?;
+        values.values[index].as_ref()
+    }
+
+    /// Returns all distinct values of a shared component that weren't
+    /// forgotten.
+    pub fn iter<C: SharedComponent>(&self, shared_id: ComponentId) -> impl Iterator<Item = &C> {
+        self.get_values::<C>(shared_id)
+            .into_iter()
+            .flat_map(|values| values.values.iter().flatten())
+    }
+
+    fn get_values<C: SharedComponent>(&self, shared_id: ComponentId) -> Option<&SharedValues<C>> {
+        self.values.get(&shared_id)?.as_any().downcast_ref()
+    }
+}
+
+impl Debug for SharedComponents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedComponents")
+            .field("components", &self.values.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// [`SharedValues`] of any shared component.
+trait ErasedSharedValues {
+    /// Forgets the values that no entity in `archetypes` has.
+    fn reclaim_unused(&mut self, shared_id: ComponentId, archetypes: &Archetypes);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// The number of values of a shared component before unused values are
+/// looked for the first time.
+const MIN_RECLAIM_AT: usize = 8;
+
+/// The distinct values of a shared component.
+struct SharedValues<C> {
+    /// The values, or `None` where a value was forgotten.
+    values: Vec<Option<C>>,
+    /// The ids of the components that represent the values, at the same
+    /// indices. The ids of forgotten values are reused.
+    value_ids: Vec<ComponentId>,
+    /// Indices of the values, by the ids of the components that represent
+    /// them.
+    by_id: SparseMap<ComponentId, usize>,
+    /// Indices of the values, by their hashes.
+    by_hash: HashMap<u64, Vec<usize>>,
+    hasher: RandomState,
+    /// Indices of forgotten values.
+    free: Vec<usize>,
+    /// The number of values at which unused values are looked for next.
+    reclaim_at: usize,
+}
+
+impl<C: Eq> SharedValues<C> {
+    /// Returns the index of `value`, which has the hash `hash`.
+    fn find(&self, hash: u64, value: &C) -> Option<usize> {
+        self.by_hash
+            .get(&hash)?
+            .iter()
+            .copied()
+            .find(|index| self.values[*index].as_ref() == Some(value))
+    }
+}
+
+impl<C: SharedComponent> ErasedSharedValues for SharedValues<C> {
+    fn reclaim_unused(&mut self, shared_id: ComponentId, archetypes: &Archetypes) {
+        let mut used = vec![false; self.values.len()];
+        for archetype in archetypes.iter() {
+            if archetype.entities().is_empty() {
+                continue;
+            }
+            if let Some(index) = archetype
+                .shared_value(shared_id)
+                .and_then(|value_id| self.by_id.get(&value_id))
+            {
+                used[*index] = true;
+            }
+        }
+
+        for (index, used) in used.into_iter().enumerate() {
+            if used {
+                continue;
+            }
+            let Some(value) = self.values[index].take()
+            else {
+                continue;
+            };
+            let hash = self.hasher.hash_one(&value);
+            if let Some(indices) = self.by_hash.get_mut(&hash) {
+                indices.retain(|other| *other != index);
+                if indices.is_empty() {
+                    self.by_hash.remove(&hash);
+                }
+            }
+            self.free.push(index);
+        }
+
+        let live = self.values.len() - self.free.len();
+        self.reclaim_at = (2 * live).max(MIN_RECLAIM_AT);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<C> Default for SharedValues<C> {
+    fn default() -> Self {
+        Self {
+            values: vec![],
+            value_ids: vec![],
+            by_id: SparseMap::default(),
+            by_hash: HashMap::new(),
+            hasher: RandomState::new(),
+            free: vec![],
+            reclaim_at: MIN_RECLAIM_AT,
+        }
+    }
+}
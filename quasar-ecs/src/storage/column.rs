@@ -1,92 +1,461 @@
 use std::{
+    alloc::Layout,
     cell::UnsafeCell,
     marker::PhantomData,
+    num::NonZero,
     ops::{
         Deref,
         DerefMut,
     },
+    ptr::NonNull,
 };
 
-use bevy_ptr::OwningPtr;
+use bevy_ptr::{
+    OwningPtr,
+    Ptr,
+    PtrMut,
+};
 
 use crate::{
-    component::ComponentDescriptor,
-    util::blob_vec::BlobVec,
+    component::ComponentInfo,
+    storage::StorageType,
+    util::{
+        blob_vec::BlobVec,
+        CloneFn,
+        DropFn,
+    },
 };
 
 #[derive(Debug)]
 pub struct Column {
-    data: BlobVec,
+    /// The rows, split into chunks. Without a chunk size, there's a single
+    /// chunk that grows as needed. With one, each chunk is allocated with
+    /// room for exactly `chunk_rows` rows and never reallocated. All chunks
+    /// before the one holding the last row are full, and chunks after it
+    /// are empty.
+    chunks: Vec<BlobVec>,
+    chunk_rows: Option<NonZero<usize>>,
+    len: usize,
+    item_layout: Layout,
+    drop: Option<DropFn>,
+    /// Whether each component is stored in its own box, so that its address
+    /// doesn't change when the row moves. The rows then hold pointers to the
+    /// boxes. See [`StorageType::Stable`].
+    stable: bool,
+    /// Boxes whose components were moved out, to be reused by new rows.
+    free_boxes: Vec<NonNull<u8>>,
+    /// Whether the component in each row is disabled. This is empty while no
+    /// component in the column is disabled, and has an entry for each row
+    /// otherwise.
+    disabled: Vec<bool>,
+    num_disabled: usize,
 }
 
 impl Column {
-    pub fn new(component_descriptor: &ComponentDescriptor, capacity: usize) -> Self {
-        Self {
-            data: unsafe {
-                // SAFETY: the components stored in this BlobVec will match the
-                // ComponentDescriptor
-                BlobVec::new(
-                    component_descriptor.layout(),
-                    component_descriptor.drop_fn(),
-                    capacity,
-                )
-            },
+    pub fn new(component_info: &ComponentInfo, capacity: usize) -> Self {
+        let descriptor = component_info.descriptor();
+        let mut column = Self {
+            chunks: vec![],
+            chunk_rows: None,
+            len: 0,
+            item_layout: descriptor.layout(),
+            drop: descriptor.drop_fn(),
+            stable: component_info.storage_type() == StorageType::Stable,
+            free_boxes: vec![],
+            disabled: vec![],
+            num_disabled: 0,
+        };
+        column.chunks.push(column.new_chunk(capacity));
+        column
+    }
+
+    /// Switches the empty column to chunks of `chunk_rows` rows. See
+    /// [`TableLayout`](super::table::TableLayout).
+    pub fn set_chunk_rows(&mut self, chunk_rows: NonZero<usize>) {
+        assert!(self.is_empty());
+        self.chunks.clear();
+        self.chunk_rows = Some(chunk_rows);
+    }
+
+    /// Returns whether the components are boxed. See [`StorageType::Stable`].
+    pub fn is_stable(&self) -> bool {
+        self.stable
+    }
+
+    fn new_chunk(&self, capacity: usize) -> BlobVec {
+        unsafe {
+            // SAFETY: the components stored in this BlobVec will match the
+            // ComponentDescriptor, or are pointers to boxes that the column drops
+            // itself.
+            if self.stable {
+                BlobVec::new(Layout::new::<NonNull<u8>>(), None, capacity)
+            }
+            else {
+                BlobVec::new(self.item_layout, self.drop, capacity)
+            }
+        }
+    }
+
+    /// Returns a box for a new component, reusing a free one if possible.
+    fn alloc_box(&mut self) -> NonNull<u8> {
+        if let Some(ptr) = self.free_boxes.pop() {
+            return ptr;
+        }
+        if self.item_layout.size() == 0 {
+            let align = NonZero::new(self.item_layout.align()).expect("alignment must be > 0");
+            return bevy_ptr::dangling_with_align(align);
+        }
+        let ptr = unsafe {
+            // SAFETY: The layout has a non-zero size.
+            std::alloc::alloc(self.item_layout)
+        };
+        NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(self.item_layout))
+    }
+
+    /// Returns the chunk and the index in the chunk of a row.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        match self.chunk_rows {
+            Some(chunk_rows) => (index / chunk_rows, index % chunk_rows),
+            None => (0, index),
         }
     }
 
     pub fn reserve(&mut self, additional: usize) {
-        self.data.reserve(additional);
+        match self.chunk_rows {
+            Some(chunk_rows) => {
+                let needed = (self.len + additional).div_ceil(chunk_rows.get());
+                while self.chunks.len() < needed {
+                    self.chunks.push(self.new_chunk(chunk_rows.get()));
+                }
+            }
+            None => self.chunks[0].reserve(additional),
+        }
     }
 
     pub fn clear(&mut self) {
-        self.data.clear();
+        if self.stable {
+            let len = self.len;
+            // set the length first, so that no box is dropped twice if a drop panics.
+            self.len = 0;
+            for index in 0..len {
+                let ptr = unsafe {
+                    // SAFETY: `index` was less than the length, and stable rows hold pointers
+                    // to boxes.
+                    *self.row_ptr(index).deref::<NonNull<u8>>()
+                };
+                self.free_boxes.push(ptr);
+                if let Some(drop) = self.drop {
+                    unsafe {
+                        // SAFETY: The box holds a component, which is no longer reachable.
+                        drop(OwningPtr::new(ptr));
+                    }
+                }
+            }
+        }
+        for chunk in &mut self.chunks {
+            chunk.clear();
+        }
+        self.len = 0;
+        self.disabled.clear();
+        self.num_disabled = 0;
     }
 
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.len
     }
 
     pub fn is_empty(&self) -> bool {
-        self.data.is_empty()
+        self.len == 0
     }
 
-    pub unsafe fn get_slice_unsafe<T>(&self) -> &[UnsafeCell<T>] {
-        self.data.get_slice()
+    /// Returns whether the component in the row is enabled. See
+    /// [`World::disable_component`](crate::World::disable_component).
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.disabled.get(index).is_none_or(|disabled| !*disabled)
     }
 
-    pub unsafe fn get_slice<T>(&self) -> &[T] {
-        self.data.get_slice()
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        assert!(index < self.len());
+        if self.is_enabled(index) == enabled {
+            return;
+        }
+        if enabled {
+            self.num_disabled -= 1;
+            if self.num_disabled == 0 {
+                self.disabled.clear();
+                return;
+            }
+        }
+        else {
+            if self.disabled.is_empty() {
+                self.disabled.resize(self.len(), false);
+            }
+            self.num_disabled += 1;
+        }
+        self.disabled[index] = !enabled;
     }
 
-    pub unsafe fn get_mut_slice<T>(&mut self) -> &mut [T] {
-        self.data.get_mut_slice()
+    /// Returns whether a component in the column is disabled.
+    pub fn has_disabled(&self) -> bool {
+        self.num_disabled > 0
+    }
+
+    /// Adds an entry for a pushed row, if rows are tracked.
+    fn push_enabled(&mut self) {
+        if !self.disabled.is_empty() {
+            self.disabled.push(false);
+        }
+    }
+
+    /// Removes the entry of a row like `Vec::swap_remove`, and returns whether
+    /// the row was enabled.
+    fn swap_remove_enabled(&mut self, index: usize) -> bool {
+        if self.disabled.is_empty() || !self.disabled.swap_remove(index) {
+            return true;
+        }
+        self.num_disabled -= 1;
+        if self.num_disabled == 0 {
+            self.disabled.clear();
+        }
+        false
+    }
+
+    /// Returns the rows in a chunk.
+    ///
+    /// # Safety
+    ///
+    /// The column must store values of type `T`, and `chunk` must hold rows.
+    pub unsafe fn get_chunk_slice_unsafe<T>(&self, chunk: usize) -> &[UnsafeCell<T>] {
+        self.chunks[chunk].get_slice_unsafe()
+    }
+
+    pub unsafe fn get_unsafe<T>(&self, index: usize) -> Option<&UnsafeCell<T>> {
+        (index < self.len).then(|| &*self.get_ptr(index).as_ptr().cast::<UnsafeCell<T>>())
+    }
+
+    pub unsafe fn get<T>(&self, index: usize) -> Option<&T> {
+        (index < self.len).then(|| self.get_ptr(index).deref())
+    }
+
+    pub unsafe fn get_mut<T>(&mut self, index: usize) -> Option<&mut T> {
+        (index < self.len).then(|| self.get_ptr_mut(index).deref_mut())
+    }
+
+    /// Returns a pointer to the component in a row.
+    pub unsafe fn get_ptr(&self, index: usize) -> Ptr<'_> {
+        let ptr = self.row_ptr(index);
+        if self.stable {
+            Ptr::new(*ptr.deref::<NonNull<u8>>())
+        }
+        else {
+            ptr
+        }
+    }
+
+    unsafe fn get_ptr_mut(&mut self, index: usize) -> PtrMut<'_> {
+        let stable = self.stable;
+        let ptr = self.row_ptr_mut(index);
+        if stable {
+            PtrMut::new(*ptr.deref_mut::<NonNull<u8>>())
+        }
+        else {
+            ptr
+        }
+    }
+
+    /// Returns a pointer to what's stored in a row, which is a pointer to the
+    /// component's box if the column is stable.
+    unsafe fn row_ptr(&self, index: usize) -> Ptr<'_> {
+        let (chunk, index) = self.locate(index);
+        self.chunks[chunk].get_unchecked(index)
+    }
+
+    unsafe fn row_ptr_mut(&mut self, index: usize) -> PtrMut<'_> {
+        let (chunk, index) = self.locate(index);
+        self.chunks[chunk].get_unchecked_mut(index)
+    }
+
+    /// Returns the chunk the next row is pushed to, allocating it if needed.
+    fn push_chunk(&mut self) -> &mut BlobVec {
+        let (chunk, _) = self.locate(self.len);
+        if chunk == self.chunks.len() {
+            let chunk_rows = self.chunk_rows.map_or(0, NonZero::get);
+            self.chunks.push(self.new_chunk(chunk_rows));
+        }
+        &mut self.chunks[chunk]
     }
 
     pub unsafe fn push<T>(&mut self, value: T) {
         OwningPtr::make(value, |ptr| {
-            self.data.push(ptr);
+            self.push_ptr(ptr);
+        });
+    }
+
+    pub unsafe fn push_ptr(&mut self, value: OwningPtr<'_>) {
+        if self.stable {
+            let ptr = self.alloc_box();
+            std::ptr::copy_nonoverlapping::<u8>(
+                value.as_ptr(),
+                ptr.as_ptr(),
+                self.item_layout.size(),
+            );
+            self.push_box(ptr);
+        }
+        else {
+            self.push_chunk().push(value);
+            self.len += 1;
+            self.push_enabled();
+        }
+    }
+
+    pub unsafe fn push_clone(&mut self, value: Ptr<'_>, clone_fn: CloneFn) {
+        if self.stable {
+            let ptr = self.alloc_box();
+            // keep the box in the free list while cloning, so that it's not lost if
+            // `clone_fn` panics.
+            self.free_boxes.push(ptr);
+            clone_fn(value, PtrMut::new(ptr));
+            self.free_boxes.pop();
+            self.push_box(ptr);
+        }
+        else {
+            self.push_chunk().push_clone(value, clone_fn);
+            self.len += 1;
+            self.push_enabled();
+        }
+    }
+
+    /// Pushes a row with a box that holds a component.
+    unsafe fn push_box(&mut self, ptr: NonNull<u8>) {
+        OwningPtr::make(ptr, |ptr| {
+            self.push_chunk().push(ptr);
         });
+        self.len += 1;
+        self.push_enabled();
+    }
+
+    /// Replaces the component in a row. If the column is stable, the new
+    /// component is written into the same box.
+    pub unsafe fn replace_ptr(&mut self, index: usize, value: OwningPtr<'_>) {
+        if self.stable {
+            let ptr = self.get_ptr_mut(index).as_ptr();
+            if let Some(drop) = self.drop {
+                drop(OwningPtr::new(NonNull::new_unchecked(ptr)));
+            }
+            std::ptr::copy_nonoverlapping::<u8>(value.as_ptr(), ptr, self.item_layout.size());
+        }
+        else {
+            let (chunk, index) = self.locate(index);
+            self.chunks[chunk].replace_unchecked(index, value);
+        }
+    }
+
+    /// Writes to an index whose value was moved out, without dropping it.
+    pub unsafe fn initialize_ptr(&mut self, index: usize, value: OwningPtr<'_>) {
+        if self.stable {
+            let ptr = self.get_ptr_mut(index).as_ptr();
+            std::ptr::copy_nonoverlapping::<u8>(value.as_ptr(), ptr, self.item_layout.size());
+        }
+        else {
+            let (chunk, index) = self.locate(index);
+            self.chunks[chunk].initialize_unchecked(index, value);
+        }
+    }
+
+    /// Removes the item like `Vec::swap_remove`, without dropping it. If the
+    /// column is stable, the box is freed for reuse, but stays valid until the
+    /// next row is pushed.
+    unsafe fn swap_remove_and_forget(&mut self, index: usize) -> OwningPtr<'_> {
+        if self.stable {
+            let ptr = self.swap_remove_row(index).read::<NonNull<u8>>();
+            self.free_boxes.push(ptr);
+            OwningPtr::new(ptr)
+        }
+        else {
+            self.swap_remove_row(index)
+        }
+    }
+
+    /// Removes a row like `Vec::swap_remove`, and returns what was stored in
+    /// it. If the last row is in another chunk, it's swapped into place first,
+    /// so that only the last chunk shrinks.
+    unsafe fn swap_remove_row(&mut self, index: usize) -> OwningPtr<'_> {
+        debug_assert!(index < self.len);
+        self.swap_remove_enabled(index);
+        let last = self.len - 1;
+        let (chunk, chunk_index) = self.locate(index);
+        let (last_chunk, last_index) = self.locate(last);
+        self.len = last;
+        if chunk == last_chunk {
+            self.chunks[chunk].swap_remove_and_forget_unchecked(chunk_index)
+        }
+        else {
+            let row_size = self.chunks[chunk].layout().size();
+            let item = self.row_ptr_mut(index).as_ptr();
+            let last_item = self.chunks[last_chunk]
+                .get_unchecked_mut(last_index)
+                .as_ptr();
+            // SAFETY: The rows are in different chunks, so they don't overlap.
+            std::ptr::swap_nonoverlapping::<u8>(item, last_item, row_size);
+            self.chunks[last_chunk].swap_remove_and_forget_unchecked(last_index)
+        }
     }
 
+    /// Moves the item to the end of another column. Whether it's enabled is
+    /// kept. If the columns are stable, only the pointer to the box is moved.
     pub unsafe fn move_item(&mut self, index: usize, to_column: &mut Self) {
-        let ptr = self.data.swap_remove_and_forget_unchecked(index);
-        to_column.push(ptr);
+        let enabled = self.is_enabled(index);
+        if self.stable && to_column.stable {
+            let ptr = self.swap_remove_row(index).read::<NonNull<u8>>();
+            to_column.push_box(ptr);
+        }
+        else {
+            let ptr = self.swap_remove_and_forget(index);
+            to_column.push_ptr(ptr);
+        }
+        if !enabled {
+            to_column.set_enabled(to_column.len() - 1, false);
+        }
     }
 
     pub unsafe fn remove_item(&mut self, index: usize) {
-        self.data.swap_remove_and_drop_unchecked(index);
+        let drop = self.drop;
+        let ptr = self.swap_remove_and_forget(index);
+        if let Some(drop) = drop {
+            drop(ptr);
+        }
     }
 
     pub unsafe fn take_item<T>(&mut self, index: usize) -> T {
-        let ptr = self.data.swap_remove_and_forget_unchecked(index);
-        ptr.read::<T>()
+        self.swap_remove_and_forget(index).read::<T>()
     }
 
     pub unsafe fn take_item_and_remove_later<T>(&mut self, index: usize) -> T {
-        self.data.get_unchecked_mut(index).promote().read()
+        self.get_ptr_mut(index).promote().read()
+    }
+
+    pub unsafe fn take_item_ptr(&mut self, index: usize) -> OwningPtr<'_> {
+        self.swap_remove_and_forget(index)
     }
 
     pub unsafe fn forget_item(&mut self, index: usize) {
-        self.data.swap_remove_and_forget_unchecked(index);
+        let _ = self.swap_remove_and_forget(index);
+    }
+}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        if !self.stable {
+            return;
+        }
+        self.clear();
+        if self.item_layout.size() > 0 {
+            for ptr in self.free_boxes.drain(..) {
+                unsafe {
+                    // SAFETY: The box was allocated with the item layout.
+                    std::alloc::dealloc(ptr.as_ptr(), self.item_layout);
+                }
+            }
+        }
     }
 }
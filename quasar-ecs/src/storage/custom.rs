@@ -0,0 +1,254 @@
+use std::{
+    any::{
+        type_name,
+        Any,
+    },
+    cell::UnsafeCell,
+    fmt::Debug,
+    ptr::NonNull,
+    rc::Rc,
+};
+
+use bevy_ptr::{
+    OwningPtr,
+    Ptr,
+    PtrMut,
+};
+
+use crate::{
+    component::Component,
+    entity::Entity,
+};
+
+/// The value of the world's change counter at the time a component was
+/// inserted or mutably accessed. See
+/// [`World::increment_change_tick`](crate::World::increment_change_tick).
+///
+/// The counter wraps around after [`u32::MAX`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Tick(u32);
+
+impl Tick {
+    pub const fn new(tick: u32) -> Self {
+        Self(tick)
+    }
+
+    pub const fn get(&self) -> u32 {
+        self.0
+    }
+
+    pub(crate) fn next(&self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+}
+
+/// A user-provided storage for the components `C` of a world, used instead of
+/// its tables, e.g. a spatial hash or a buffer that's mirrored to the GPU. See
+/// [`World::set_component_storage`](crate::World::set_component_storage).
+///
+/// The world still tracks which entities have the component in their
+/// archetypes, so queries and filters work as usual, and only fetch the
+/// component from the storage. Components are looked up by their entity.
+///
+/// `tick` is the world's current [change tick](crate::World::change_tick).
+/// The storage can record it, and report it from
+/// [`changed_tick`](Self::changed_tick).
+///
+/// # Safety
+///
+/// - [`get`](Self::get), [`get_mut`](Self::get_mut) and
+///   [`get_ptr`](Self::get_ptr) must return the component that was last
+///   inserted for the entity, and `None` after it was removed.
+/// - The pointer returned by [`get_ptr`](Self::get_ptr) must be valid for reads
+///   and writes, until the component is removed or the storage is borrowed
+///   mutably. Calling the methods that take `&self`, for any entity, must
+///   neither move the component nor invalidate the pointer, and the storage
+///   must not read or write the component while the pointer is in use, except
+///   through [`get_ptr`](Self::get_ptr) itself. This usually means storing the
+///   components in an [`UnsafeCell`], and keeping the change ticks in a
+///   [`Cell`](std::cell::Cell).
+///
+/// The world never uses pointers to the components of the same entity, or a
+/// reference to one, at the same time, unless they're all shared.
+pub unsafe trait ComponentStorage<C: Component>: 'static {
+    /// Inserts the component of an entity, and returns the one it replaced.
+    fn insert(&mut self, entity: Entity, component: C, tick: Tick) -> Option<C>;
+
+    /// Removes the component of an entity.
+    fn remove(&mut self, entity: Entity) -> Option<C>;
+
+    fn get(&self, entity: Entity) -> Option<&C>;
+
+    /// Returns the component of an entity for mutation, while the world is
+    /// borrowed exclusively, and records `tick` as its changed tick.
+    fn get_mut(&mut self, entity: Entity, tick: Tick) -> Option<&mut C>;
+
+    /// Returns a pointer to the component of an entity for mutation, while
+    /// the components of other entities may be borrowed, e.g. by a `&mut C`
+    /// query, and records `tick` as its changed tick. See the safety section
+    /// of the trait.
+    fn get_ptr(&self, entity: Entity, tick: Tick) -> Option<NonNull<C>>;
+
+    /// Iterates over all entities in the storage and their components, in any
+    /// order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (Entity, &C)> + '_>;
+
+    /// Returns the tick at which the component of an entity was last inserted
+    /// or mutably accessed.
+    fn changed_tick(&self, entity: Entity) -> Option<Tick>;
+}
+
+/// Type-erased access to a [`ComponentStorage`].
+pub trait ErasedStorage: 'static {
+    fn set_change_tick(&mut self, change_tick: Tick);
+
+    /// Writes a component, and drops the one it replaced.
+    ///
+    /// # Safety
+    ///
+    /// `value` must point to a value of the component type of the storage.
+    unsafe fn insert_ptr(&mut self, entity: Entity, value: OwningPtr<'_>);
+
+    /// Like [`insert_ptr`](Self::insert_ptr), but doesn't drop the replaced
+    /// component, since it was moved out already.
+    ///
+    /// # Safety
+    ///
+    /// `value` must point to a value of the component type of the storage.
+    unsafe fn initialize_ptr(&mut self, entity: Entity, value: OwningPtr<'_>);
+
+    fn remove_and_drop(&mut self, entity: Entity);
+
+    /// Removes a component without dropping it, since it was moved out
+    /// already.
+    fn remove_and_forget(&mut self, entity: Entity);
+
+    fn contains(&self, entity: Entity) -> bool;
+
+    fn get_ptr(&self, entity: Entity) -> Option<Ptr<'_>>;
+
+    fn get_ptr_mut(&mut self, entity: Entity) -> Option<PtrMut<'_>>;
+
+    /// Returns a pointer through which the component can be mutated while
+    /// only the storage is borrowed shared. See
+    /// [`ComponentStorage::get_ptr`].
+    fn get_ptr_unsafe(&self, entity: Entity) -> Option<NonNull<u8>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct CustomStorage<C> {
+    storage: Box<dyn ComponentStorage<C>>,
+    change_tick: Tick,
+}
+
+impl<C: Component> ErasedStorage for CustomStorage<C> {
+    fn set_change_tick(&mut self, change_tick: Tick) {
+        self.change_tick = change_tick;
+    }
+
+    unsafe fn insert_ptr(&mut self, entity: Entity, value: OwningPtr<'_>) {
+        self.storage
+            .insert(entity, value.read::<C>(), self.change_tick);
+    }
+
+    unsafe fn initialize_ptr(&mut self, entity: Entity, value: OwningPtr<'_>) {
+        if let Some(replaced) = self
+            .storage
+            .insert(entity, value.read::<C>(), self.change_tick)
+        {
+            std::mem::forget(replaced);
+        }
+    }
+
+    fn remove_and_drop(&mut self, entity: Entity) {
+        self.storage.remove(entity);
+    }
+
+    fn remove_and_forget(&mut self, entity: Entity) {
+        if let Some(removed) = self.storage.remove(entity) {
+            std::mem::forget(removed);
+        }
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.storage.get(entity).is_some()
+    }
+
+    fn get_ptr(&self, entity: Entity) -> Option<Ptr<'_>> {
+        self.storage.get(entity).map(Ptr::from)
+    }
+
+    fn get_ptr_mut(&mut self, entity: Entity) -> Option<PtrMut<'_>> {
+        self.storage
+            .get_mut(entity, self.change_tick)
+            .map(PtrMut::from)
+    }
+
+    fn get_ptr_unsafe(&self, entity: Entity) -> Option<NonNull<u8>> {
+        self.storage
+            .get_ptr(entity, self.change_tick)
+            .map(NonNull::cast)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A [`ComponentStorage`], shared by all tables that have its component.
+#[derive(Clone)]
+pub struct StorageHandle {
+    storage: Rc<UnsafeCell<dyn ErasedStorage>>,
+    type_name: &'static str,
+}
+
+impl StorageHandle {
+    pub fn new<C: Component>(storage: impl ComponentStorage<C>, change_tick: Tick) -> Self {
+        Self {
+            storage: Rc::new(UnsafeCell::new(CustomStorage {
+                storage: Box::new(storage),
+                change_tick,
+            })),
+            type_name: type_name::<C>(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// There must be no mutable reference to the storage while the returned
+    /// reference is alive. Components that are mutated through
+    /// [`ErasedStorage::get_ptr_unsafe`] must not be read through it.
+    pub unsafe fn get(&self) -> &dyn ErasedStorage {
+        &*self.storage.get()
+    }
+
+    /// # Safety
+    ///
+    /// There must be no other reference to the storage, or to a component in
+    /// it, while the returned reference is alive. The handle is shared by all
+    /// tables that have the component, so this requires exclusive access to
+    /// all of them.
+    pub unsafe fn get_mut(&mut self) -> &mut dyn ErasedStorage {
+        &mut *self.storage.get()
+    }
+
+    /// Returns the storage, if it stores the components `C`.
+    ///
+    /// # Safety
+    ///
+    /// There must be no mutable reference to the storage.
+    pub unsafe fn downcast<C: Component>(&self) -> Option<&dyn ComponentStorage<C>> {
+        let storage = self.get().as_any().downcast_ref::<CustomStorage<C>>()?;
+        Some(&*storage.storage)
+    }
+}
+
+impl Debug for StorageHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StorageHandle")
+            .field(&self.type_name)
+            .finish()
+    }
+}
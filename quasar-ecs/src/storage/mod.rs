@@ -1,4 +1,5 @@
 pub mod column;
+pub mod custom;
 pub mod table;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -6,4 +7,18 @@ pub enum StorageType {
     Table,
     SparseSet,
     BitSet,
+
+    /// Like [`Table`](Self::Table), but each component is stored in its own
+    /// box, and the table only holds a pointer to it. The address of the
+    /// component doesn't change while it's attached to the entity, even when
+    /// the entity moves to another table because other components are
+    /// inserted or removed, or when other rows are removed from the table.
+    /// Replacing the component writes the new value to the same address.
+    ///
+    /// This makes it possible to keep raw pointers to the component, e.g. from
+    /// native code, as long as it's not removed, the entity isn't despawned and
+    /// the world isn't cleared. The usual borrowing rules still apply to
+    /// references to it. Accessing these components costs an extra
+    /// indirection.
+    Stable,
 }
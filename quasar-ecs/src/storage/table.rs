@@ -1,11 +1,16 @@
 use std::{
     cell::UnsafeCell,
     collections::HashMap,
+    num::NonZero,
+};
+
+use bevy_ptr::{
+    OwningPtr,
+    Ptr,
 };
 
 use crate::{
     component::{
-        self,
         ComponentId,
         ComponentInfo,
     },
@@ -13,7 +18,14 @@ use crate::{
         ChangedLocation,
         Entity,
     },
-    storage::column::Column,
+    storage::{
+        column::Column,
+        custom::{
+            ErasedStorage,
+            StorageHandle,
+            Tick,
+        },
+    },
     util::{
         slice_get_mut_pair,
         sparse_map::{
@@ -65,10 +77,42 @@ impl TableRow {
     }
 }
 
+/// How the rows of new tables are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableLayout {
+    /// Each column is stored in a single allocation, which is reallocated as
+    /// the table grows.
+    #[default]
+    Contiguous,
+
+    /// Each column is stored in fixed-size chunks, which are never
+    /// reallocated, so growing a table only allocates a new chunk. Each chunk
+    /// holds as many rows as fit into `chunk_size` bytes of the table's
+    /// components, but at least one.
+    Chunked { chunk_size: usize },
+}
+
+impl TableLayout {
+    /// Returns the number of rows per chunk, for rows of `row_size` bytes.
+    fn chunk_rows(&self, row_size: usize) -> Option<NonZero<usize>> {
+        match self {
+            Self::Contiguous => None,
+            Self::Chunked { chunk_size } => {
+                Some(NonZero::new(chunk_size / row_size.max(1)).unwrap_or(NonZero::<usize>::MIN))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Table {
     columns: ImmutableSparseMap<ComponentId, Column>,
+    /// Components that are stored in a
+    /// [`ComponentStorage`](super::custom::ComponentStorage) instead of a
+    /// column. They're looked up by entity.
+    custom: ImmutableSparseMap<ComponentId, StorageHandle>,
     entities: Vec<Entity>,
+    chunk_rows: Option<NonZero<usize>>,
 }
 
 impl Table {
@@ -84,6 +128,52 @@ impl Table {
         self.columns.contains_key(&component_id)
     }
 
+    /// Returns whether the table has the component, either in a column or in
+    /// a custom storage.
+    pub fn has_component(&self, component_id: ComponentId) -> bool {
+        self.has_column(component_id) || self.custom.contains_key(&component_id)
+    }
+
+    /// Returns whether the row has the component, and it's enabled. See
+    /// [`World::disable_component`](crate::World::disable_component).
+    pub fn is_enabled(&self, component_id: ComponentId, table_row: TableRow) -> bool {
+        if let Some(storage) = self.custom.get(&component_id) {
+            // components in custom storages can't be disabled.
+            return self.entities.get(table_row.index()).is_some_and(|entity| {
+                unsafe {
+                    // SAFETY: The storage isn't borrowed mutably while the table is borrowed.
+                    storage.get().contains(*entity)
+                }
+            });
+        }
+        self.columns
+            .get(&component_id)
+            .is_some_and(|column| column.is_enabled(table_row.index()))
+    }
+
+    /// Returns whether the component is disabled in any row.
+    pub fn has_disabled(&self, component_id: ComponentId) -> bool {
+        self.columns
+            .get(&component_id)
+            .is_some_and(|column| column.has_disabled())
+    }
+
+    /// Enables or disables the component in the row. Returns `false` if the
+    /// table doesn't have the component, or it's in a custom storage.
+    pub fn set_enabled(
+        &mut self,
+        component_id: ComponentId,
+        table_row: TableRow,
+        enabled: bool,
+    ) -> bool {
+        let Some(column) = self.columns.get_mut(&component_id)
+        else {
+            return false;
+        };
+        column.set_enabled(table_row.index(), enabled);
+        true
+    }
+
     pub fn reserve(&mut self, additional: usize) {
         for (_, column) in &mut self.columns {
             column.reserve(additional);
@@ -96,7 +186,7 @@ impl Table {
     }
 
     pub fn num_components(&self) -> usize {
-        self.columns.len()
+        self.columns.len() + self.custom.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -104,6 +194,15 @@ impl Table {
     }
 
     pub fn clear(&mut self) {
+        for storage in self.custom.values_mut() {
+            for entity in &self.entities {
+                unsafe {
+                    // SAFETY: Tables are only borrowed mutably while all tables are, so we
+                    // have exclusive access to the storage.
+                    storage.get_mut().remove_and_drop(*entity);
+                }
+            }
+        }
         self.entities.clear();
         for column in self.columns.values_mut() {
             column.clear();
@@ -116,8 +215,66 @@ impl Table {
         InsertIntoTable { table: self, index }
     }
 
+    /// Returns an [`InsertIntoTable`] for a row that already exists. Any
+    /// components written to it will replace the existing ones.
+    pub fn write_row(&mut self, table_row: TableRow) -> InsertIntoTable<'_> {
+        assert!(
+            table_row.index() < self.entities.len(),
+            "table_row ({table_row:?}) < self.entities.len() ({})",
+            self.entities.len()
+        );
+        InsertIntoTable {
+            table: self,
+            index: table_row.index(),
+        }
+    }
+
+    /// Returns the ids of the components in the table. Components in custom
+    /// storages come after the columns.
     pub fn component_ids(&self) -> impl Iterator<Item = ComponentId> + use<'_> {
-        self.columns.iter().map(|(k, _)| k)
+        self.columns
+            .iter()
+            .map(|(k, _)| k)
+            .chain(self.custom.iter().map(|(k, _)| k))
+    }
+
+    /// Returns the custom storage of a component, and the entity in the row.
+    fn get_custom(
+        &self,
+        component_id: ComponentId,
+        table_row: TableRow,
+    ) -> Option<(&StorageHandle, Entity)> {
+        let storage = self.custom.get(&component_id)?;
+        let entity = *self.entities.get(table_row.index())?;
+        Some((storage, entity))
+    }
+
+    /// Returns the number of rows per chunk, or `None` if the table is
+    /// contiguous. See [`TableLayout`].
+    pub fn chunk_rows(&self) -> Option<NonZero<usize>> {
+        self.chunk_rows
+    }
+
+    /// Iterates over the chunks of the table. A contiguous table has a single
+    /// chunk with all rows.
+    pub fn chunks(&self) -> impl Iterator<Item = TableChunk<'_>> {
+        let num_chunks = match self.chunk_rows {
+            Some(chunk_rows) => self.entities.len().div_ceil(chunk_rows.get()),
+            None => usize::from(!self.entities.is_empty()),
+        };
+        (0..num_chunks).map(|index| {
+            let start = self
+                .chunk_rows
+                .map_or(0, |chunk_rows| index * chunk_rows.get());
+            let end = self.chunk_rows.map_or(self.entities.len(), |chunk_rows| {
+                (start + chunk_rows.get()).min(self.entities.len())
+            });
+            TableChunk {
+                table: self,
+                index,
+                entities: &self.entities[start..end],
+            }
+        })
     }
 
     pub unsafe fn get_component<T>(
@@ -125,8 +282,50 @@ impl Table {
         component_id: ComponentId,
         table_row: TableRow,
     ) -> Option<&T> {
+        if let Some((storage, entity)) = self.get_custom(component_id, table_row) {
+            // SAFETY: The storage isn't borrowed mutably while the table is borrowed.
+            return Some(storage.get().get_ptr(entity)?.deref());
+        }
+        let column = self.columns.get(&component_id)?;
+        column.get(table_row.index())
+    }
+
+    /// Returns the component as an [`UnsafeCell`], through which it can be
+    /// mutated while only holding a shared borrow of the table.
+    pub unsafe fn get_component_unsafe<T>(
+        &self,
+        component_id: ComponentId,
+        table_row: TableRow,
+    ) -> Option<&UnsafeCell<T>> {
+        if let Some((storage, entity)) = self.get_custom(component_id, table_row) {
+            // SAFETY: Like the column, this must only be called if the component isn't
+            // aliased. The storage is only borrowed shared, and the pointer stays valid
+            // while the components of other entities are accessed.
+            let ptr = storage.get().get_ptr_unsafe(entity)?;
+            return Some(&*ptr.as_ptr().cast::<UnsafeCell<T>>());
+        }
         let column = self.columns.get(&component_id)?;
-        Some(&column.get_slice()[table_row.index()])
+        column.get_unsafe(table_row.index())
+    }
+
+    pub fn get_component_ptr(
+        &self,
+        component_id: ComponentId,
+        table_row: TableRow,
+    ) -> Option<Ptr<'_>> {
+        if let Some((storage, entity)) = self.get_custom(component_id, table_row) {
+            return unsafe {
+                // SAFETY: The storage isn't borrowed mutably while the table is borrowed.
+                storage.get().get_ptr(entity)
+            };
+        }
+        let column = self.columns.get(&component_id)?;
+        (table_row.index() < column.len()).then(|| {
+            unsafe {
+                // SAFETY: We just checked that the row is in bounds.
+                column.get_ptr(table_row.index())
+            }
+        })
     }
 
     pub unsafe fn get_component_mut<T>(
@@ -134,8 +333,14 @@ impl Table {
         component_id: ComponentId,
         table_row: TableRow,
     ) -> Option<&mut T> {
+        if let Some(storage) = self.custom.get_mut(&component_id) {
+            let entity = *self.entities.get(table_row.index())?;
+            // SAFETY: Tables are only borrowed mutably while all tables are, so we have
+            // exclusive access to the storage.
+            return Some(storage.get_mut().get_ptr_mut(entity)?.deref_mut());
+        }
         let column = self.columns.get_mut(&component_id)?;
-        Some(&mut column.get_mut_slice()[table_row.index()])
+        column.get_mut(table_row.index())
     }
 
     pub unsafe fn take_component_and_remove_later<T>(
@@ -143,6 +348,16 @@ impl Table {
         component_id: ComponentId,
         table_row: TableRow,
     ) -> Option<T> {
+        if let Some(storage) = self.custom.get_mut(&component_id) {
+            let entity = *self.entities.get(table_row.index())?;
+            // SAFETY: Tables are only borrowed mutably while all tables are, so we have
+            // exclusive access to the storage. The component is removed from the storage
+            // right away, which leaves nothing to do later.
+            let storage = storage.get_mut();
+            let value = storage.get_ptr_mut(entity)?.as_ptr().cast::<T>().read();
+            storage.remove_and_forget(entity);
+            return Some(value);
+        }
         let column = self.columns.get_mut(&component_id)?;
         Some(column.take_item_and_remove_later(table_row.index()))
     }
@@ -154,8 +369,8 @@ impl Table {
         entity: Entity,
         mut handle_unmatched: impl MoveRowHandleUnmatched,
     ) -> MoveRowResult<'t> {
+        let to_row = TableRow::from_index(to_table.entities.len());
         to_table.entities.push(entity);
-        let to_row = TableRow::from_index(self.entities.len());
 
         let swapped = if from_row.is_valid() {
             let from_row_index = from_row.index();
@@ -179,6 +394,14 @@ impl Table {
                 }
             }
 
+            // components in custom storages stay where they are, unless the target
+            // table doesn't have them.
+            for (component_id, storage) in &mut self.custom {
+                if !to_table.custom.contains_key(&component_id) {
+                    handle_unmatched.handle_custom(storage.get_mut(), entity, component_id);
+                }
+            }
+
             swapped.then(|| {
                 ChangedLocation {
                     entity: self.entities[from_row_index],
@@ -199,6 +422,74 @@ impl Table {
         }
     }
 
+    /// Moves several rows to the end of `to_table`, like
+    /// [`move_row`](Self::move_row), but column by column.
+    ///
+    /// `from_rows` must be sorted in descending order. That way, the rows that
+    /// are swapped into the place of a moved row are never moved themselves,
+    /// and all columns swap the same rows. The rows are appended to `to_table`
+    /// in the same order.
+    ///
+    /// The rows that other entities were swapped into are returned in the
+    /// order they were swapped. An entity may be swapped more than once, in
+    /// which case its last row is the current one.
+    pub unsafe fn move_rows(
+        &mut self,
+        from_rows: &[TableRow],
+        to_table: &mut Self,
+        mut handle_unmatched: impl MoveRowHandleUnmatched,
+    ) -> MoveRowsResult {
+        debug_assert!(from_rows.windows(2).all(|rows| rows[0] > rows[1]));
+        to_table.reserve(from_rows.len());
+
+        let first_row = to_table.entities.len();
+        let mut swapped = vec![];
+        for &from_row in from_rows {
+            let from_row_index = from_row.index();
+            assert!(
+                from_row_index < self.entities.len(),
+                "row_index ({from_row_index}) < self.entities.len() ({})",
+                self.entities.len()
+            );
+
+            let entity = self.entities.swap_remove(from_row_index);
+            to_table.entities.push(entity);
+
+            for (component_id, storage) in &mut self.custom {
+                if !to_table.custom.contains_key(&component_id) {
+                    handle_unmatched.handle_custom(storage.get_mut(), entity, component_id);
+                }
+            }
+
+            if let Some(swapped_entity) = self.entities.get(from_row_index) {
+                swapped.push(ChangedLocation {
+                    entity: *swapped_entity,
+                    changed_value: from_row,
+                });
+            }
+        }
+
+        for (component_id, from_column) in &mut self.columns {
+            if let Some(to_column) = to_table.get_column_mut(component_id) {
+                for from_row in from_rows {
+                    from_column.move_item(from_row.index(), to_column);
+                }
+            }
+            else {
+                for from_row in from_rows {
+                    handle_unmatched.handle(from_column, from_row.index(), component_id);
+                }
+            }
+        }
+
+        MoveRowsResult {
+            swapped,
+            to_rows: (first_row..first_row + from_rows.len())
+                .map(TableRow::from_index)
+                .collect(),
+        }
+    }
+
     pub unsafe fn remove_row(&mut self, row: TableRow) -> Option<ChangedLocation<TableRow>> {
         if row.is_invalid() {
             return None;
@@ -213,7 +504,11 @@ impl Table {
 
         let swapped = row_index != self.entities.len() - 1;
 
-        self.entities.swap_remove(row_index);
+        let entity = self.entities.swap_remove(row_index);
+
+        for storage in self.custom.values_mut() {
+            storage.get_mut().remove_and_drop(entity);
+        }
 
         for column in self.columns.values_mut() {
             column.remove_item(row_index);
@@ -228,8 +523,75 @@ impl Table {
     }
 }
 
+/// A chunk of rows in a [`Table`]. See [`Table::chunks`].
+#[derive(Clone, Copy, Debug)]
+pub struct TableChunk<'a> {
+    table: &'a Table,
+    index: usize,
+    entities: &'a [Entity],
+}
+
+impl<'a> TableChunk<'a> {
+    pub fn entities(&self) -> &'a [Entity] {
+        self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Returns the components in this chunk, in the same order as
+    /// [`entities`](Self::entities). Returns `None` if the table doesn't have
+    /// the component, or if it's [stable](super::StorageType::Stable) or in a
+    /// custom storage, since those components aren't stored next to each
+    /// other.
+    ///
+    /// # Safety
+    ///
+    /// The column with `component_id` must store values of type `T`.
+    pub unsafe fn get_slice<T>(&self, component_id: ComponentId) -> Option<&'a [T]> {
+        let slice = self.get_slice_unsafe::<T>(component_id)?;
+        Some(std::slice::from_raw_parts(
+            slice.as_ptr().cast(),
+            slice.len(),
+        ))
+    }
+
+    /// Returns the components in this chunk as [`UnsafeCell`]s, through which
+    /// they can be mutated while only holding a shared borrow of the table.
+    ///
+    /// # Safety
+    ///
+    /// The column with `component_id` must store values of type `T`.
+    pub unsafe fn get_slice_unsafe<T>(
+        &self,
+        component_id: ComponentId,
+    ) -> Option<&'a [UnsafeCell<T>]> {
+        // components in custom storages aren't in the table at all.
+        let column = self.table.columns.get(&component_id)?;
+        if column.is_stable() {
+            return None;
+        }
+        let slice = column.get_chunk_slice_unsafe(self.index);
+        debug_assert_eq!(slice.len(), self.entities.len());
+        Some(slice)
+    }
+}
+
 pub trait MoveRowHandleUnmatched {
     unsafe fn handle(&mut self, column: &mut Column, row_index: usize, component_id: ComponentId);
+
+    /// Like [`handle`](Self::handle), for a component in a custom storage.
+    unsafe fn handle_custom(
+        &mut self,
+        storage: &mut dyn ErasedStorage,
+        entity: Entity,
+        component_id: ComponentId,
+    );
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -241,6 +603,15 @@ impl MoveRowHandleUnmatched for MoveRowDropUnmatched {
             column.remove_item(row_index);
         }
     }
+
+    unsafe fn handle_custom(
+        &mut self,
+        storage: &mut dyn ErasedStorage,
+        entity: Entity,
+        _component_id: ComponentId,
+    ) {
+        storage.remove_and_drop(entity);
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -252,6 +623,15 @@ impl MoveRowHandleUnmatched for MoveRowForgetUnmatched {
             column.forget_item(row_index);
         }
     }
+
+    unsafe fn handle_custom(
+        &mut self,
+        storage: &mut dyn ErasedStorage,
+        entity: Entity,
+        _component_id: ComponentId,
+    ) {
+        storage.remove_and_forget(entity);
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -266,6 +646,15 @@ impl MoveRowHandleUnmatched for MoveRowPanicUnmatched {
     ) {
         panic!("unexpected unmatched column: {component_id:?}");
     }
+
+    unsafe fn handle_custom(
+        &mut self,
+        _storage: &mut dyn ErasedStorage,
+        _entity: Entity,
+        component_id: ComponentId,
+    ) {
+        panic!("unexpected unmatched custom storage: {component_id:?}");
+    }
 }
 
 #[derive(Debug)]
@@ -280,6 +669,13 @@ impl<'a> MoveRowResult<'a> {
     }
 }
 
+#[derive(Debug)]
+pub struct MoveRowsResult {
+    pub swapped: Vec<ChangedLocation<TableRow>>,
+    /// The rows in the target table, in the order of the moved rows.
+    pub to_rows: Vec<TableRow>,
+}
+
 #[derive(Debug)]
 pub struct InsertIntoTable<'a> {
     table: &'a mut Table,
@@ -287,20 +683,92 @@ pub struct InsertIntoTable<'a> {
 }
 
 impl<'a> InsertIntoTable<'a> {
+    /// Writes a component to this row.
+    ///
+    /// If the column already contains a value for this row, it is dropped and
+    /// replaced.
+    ///
+    /// # Safety
+    ///
+    /// The column with `component_id` must store values of type `T`.
     pub unsafe fn write_column<T>(&mut self, component_id: ComponentId, value: T) {
-        let column = if let Some(column) = self.table.get_column_mut(component_id) {
-            column
+        OwningPtr::make(value, |ptr| {
+            self.write_column_ptr(component_id, ptr);
+        });
+    }
+
+    /// Type-erased version of [`write_column`](Self::write_column).
+    ///
+    /// # Safety
+    ///
+    /// `value` must point to a value of the type stored in the column with
+    /// `component_id`.
+    pub unsafe fn write_column_ptr(&mut self, component_id: ComponentId, value: OwningPtr<'_>) {
+        if let Some((storage, entity)) = self.custom_storage(component_id) {
+            storage.insert_ptr(entity, value);
+            return;
+        }
+        let index = self.index;
+        let column = self.column_mut(component_id);
+        if column.len() == index {
+            column.push_ptr(value);
         }
         else {
+            assert!(index < column.len());
+            column.replace_ptr(index, value);
+        }
+    }
+
+    /// Like [`write_column`](Self::write_column), but if the column already
+    /// contains a value for this row, it's not dropped.
+    ///
+    /// # Safety
+    ///
+    /// The column with `component_id` must store values of type `T`, and a
+    /// value already in this row must have been moved out.
+    pub unsafe fn initialize_column<T>(&mut self, component_id: ComponentId, value: T) {
+        OwningPtr::make(value, |ptr| {
+            if let Some((storage, entity)) = self.custom_storage(component_id) {
+                storage.initialize_ptr(entity, ptr);
+                return;
+            }
+            let index = self.index;
+            let column = self.column_mut(component_id);
+            if column.len() == index {
+                column.push_ptr(ptr);
+            }
+            else {
+                assert!(index < column.len());
+                column.initialize_ptr(index, ptr);
+            }
+        });
+    }
+
+    /// Returns the custom storage of a component, if it's not stored in a
+    /// column, and the entity in this row.
+    fn custom_storage(
+        &mut self,
+        component_id: ComponentId,
+    ) -> Option<(&mut dyn ErasedStorage, Entity)> {
+        let entity = self.table.entities[self.index];
+        let storage = self.table.custom.get_mut(&component_id)?;
+        let storage = unsafe {
+            // SAFETY: Tables are only borrowed mutably while all tables are, so we have
+            // exclusive access to the storage.
+            storage.get_mut()
+        };
+        Some((storage, entity))
+    }
+
+    fn column_mut(&mut self, component_id: ComponentId) -> &mut Column {
+        if !self.table.has_column(component_id) {
             let component_ids = self.table.component_ids().collect::<Box<[ComponentId]>>();
             panic!(
                 "trying to write to column {component_id:?} to, but table has only columns [{:?}]",
                 Joined::new(", ", &component_ids)
             );
-        };
-
-        assert_eq!(column.len(), self.index);
-        column.push(value);
+        }
+        self.table.get_column_mut(component_id).unwrap()
     }
 
     pub fn table_row(&self) -> TableRow {
@@ -311,22 +779,37 @@ impl<'a> InsertIntoTable<'a> {
 #[derive(Debug, Default)]
 pub struct TableBuilder {
     columns: SparseMap<ComponentId, Column>,
+    custom: SparseMap<ComponentId, StorageHandle>,
     row_capacity: usize,
+    row_size: usize,
 }
 
 impl TableBuilder {
     pub fn new(row_capacity: usize, column_capacity: usize) -> Self {
         Self {
             columns: SparseMap::with_capacity(column_capacity),
+            custom: SparseMap::new(),
             row_capacity,
+            row_size: 0,
         }
     }
 
     pub fn add_column(&mut self, component_info: &ComponentInfo) {
-        self.columns.insert(
-            &component_info.id(),
-            Column::new(component_info.descriptor(), self.row_capacity),
-        );
+        let column = Column::new(component_info, self.row_capacity);
+        // stable columns only hold pointers to the components.
+        self.row_size += if column.is_stable() {
+            size_of::<usize>()
+        }
+        else {
+            component_info.descriptor().layout().size()
+        };
+        self.columns.insert(&component_info.id(), column);
+    }
+
+    /// Adds a component that's stored in a custom storage instead of a
+    /// column.
+    pub fn add_custom(&mut self, component_id: ComponentId, storage: StorageHandle) {
+        self.custom.insert(&component_id, storage);
     }
 
     pub fn reserve_rows(&mut self, additional: usize) {
@@ -340,10 +823,20 @@ impl TableBuilder {
         self.columns.reserve(additional);
     }
 
-    pub fn build(self) -> Table {
+    pub fn build(mut self, layout: TableLayout) -> Table {
+        let chunk_rows = layout.chunk_rows(self.row_size);
+        if let Some(chunk_rows) = chunk_rows {
+            for column in self.columns.values_mut() {
+                column.set_chunk_rows(chunk_rows);
+                column.reserve(self.row_capacity);
+            }
+        }
+
         Table {
             columns: self.columns.into(),
+            custom: self.custom.into(),
             entities: Vec::with_capacity(self.row_capacity),
+            chunk_rows,
         }
     }
 }
@@ -352,6 +845,12 @@ impl TableBuilder {
 pub struct Tables {
     tables: Vec<Table>,
     by_components: HashMap<Box<[ComponentId]>, TableId>,
+    layout: TableLayout,
+    /// The custom storages of components, shared by all tables with the
+    /// component. See
+    /// [`World::set_component_storage`](crate::World::set_component_storage).
+    storages: SparseMap<ComponentId, StorageHandle>,
+    change_tick: Tick,
 }
 
 impl Default for Tables {
@@ -360,8 +859,11 @@ impl Default for Tables {
         by_components.insert(std::iter::empty().collect(), TableId::EMPTY);
 
         Self {
-            tables: vec![TableBuilder::new(0, 0).build()],
+            tables: vec![TableBuilder::new(0, 0).build(TableLayout::Contiguous)],
             by_components,
+            layout: TableLayout::default(),
+            storages: SparseMap::new(),
+            change_tick: Tick::default(),
         }
     }
 }
@@ -370,10 +872,9 @@ impl Tables {
     pub fn insert(&mut self, table: Table) -> TableId {
         let table_id = TableId::from_index(self.tables.len());
 
-        if let Some(replaced_table_id) = self
-            .by_components
-            .insert(table.component_ids().collect(), table_id)
-        {
+        let mut component_ids = table.component_ids().collect::<Box<[ComponentId]>>();
+        component_ids.sort_unstable();
+        if let Some(replaced_table_id) = self.by_components.insert(component_ids, table_id) {
             panic!("tried to insert a table that already exists: replaced: {replaced_table_id:?}, new: {table_id:?}");
         }
 
@@ -382,6 +883,43 @@ impl Tables {
         table_id
     }
 
+    pub fn layout(&self) -> TableLayout {
+        self.layout
+    }
+
+    /// Sets the layout of tables that are created from now on. Existing tables
+    /// keep their layout.
+    pub fn set_layout(&mut self, layout: TableLayout) {
+        self.layout = layout;
+    }
+
+    /// Returns the custom storage of a component, if it has one.
+    pub fn get_storage(&self, component_id: ComponentId) -> Option<&StorageHandle> {
+        self.storages.get(&component_id)
+    }
+
+    /// Sets the custom storage of a component. Tables that are created from
+    /// now on store the component in it.
+    pub fn set_storage(&mut self, component_id: ComponentId, storage: StorageHandle) {
+        self.storages.insert(&component_id, storage);
+    }
+
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick
+    }
+
+    /// Sets the tick that custom storages are passed when components are
+    /// inserted or mutably accessed.
+    pub fn set_change_tick(&mut self, change_tick: Tick) {
+        self.change_tick = change_tick;
+        for storage in self.storages.values_mut() {
+            unsafe {
+                // SAFETY: We have exclusive access to the tables, and thus to the storages.
+                storage.get_mut().set_change_tick(change_tick);
+            }
+        }
+    }
+
     pub fn get(&self, table_id: TableId) -> &Table {
         &self.tables[table_id.index()]
     }
@@ -402,7 +940,109 @@ impl Tables {
         self.by_components.get(component_ids).copied()
     }
 
+    /// Removes all rows from all tables. The tables themselves are kept, since
+    /// archetypes refer to them.
     pub fn clear(&mut self) {
-        self.tables.clear();
+        for table in &mut self.tables {
+            table.clear();
+        }
+    }
+
+    /// Removes all tables, except the empty table. The layout, the custom
+    /// storages and the change tick are kept, but the storages are emptied.
+    pub fn reset(&mut self) {
+        self.clear();
+        *self = Self {
+            layout: self.layout,
+            storages: std::mem::take(&mut self.storages),
+            change_tick: self.change_tick,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quasar_ecs_derive::Component;
+
+    use super::{
+        MoveRowDropUnmatched,
+        TableBuilder,
+        TableLayout,
+        TableRow,
+    };
+    use crate::{
+        component::Components,
+        entity::Entities,
+    };
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+    struct A(u32);
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+    struct B(u32);
+
+    #[test]
+    fn move_rows() {
+        let mut components = Components::default();
+        let a = components.register::<A>().id();
+        let b = components.register::<B>().id();
+
+        let mut from_table = TableBuilder::new(6, 2);
+        from_table.add_column(components.get_component_info(a));
+        from_table.add_column(components.get_component_info(b));
+        let mut from_table = from_table.build(TableLayout::default());
+        let mut to_table = TableBuilder::new(0, 1);
+        to_table.add_column(components.get_component_info(a));
+        let mut to_table = to_table.build(TableLayout::default());
+
+        let entities = Entities::default().allocate_many(6);
+        for (i, entity) in entities.iter().enumerate() {
+            let mut insert = from_table.insert(*entity);
+            unsafe {
+                // SAFETY: The columns store `A` and `B`.
+                insert.write_column(a, A(i as u32));
+                insert.write_column(b, B(i as u32));
+            }
+        }
+
+        let from_rows = [5, 3, 1].map(TableRow::from_index);
+        let result = unsafe {
+            // SAFETY: The rows are in the table, and sorted in descending order.
+            from_table.move_rows(&from_rows, &mut to_table, MoveRowDropUnmatched)
+        };
+
+        // the rows are appended in the order they were moved
+        assert_eq!(result.to_rows, [0, 1, 2].map(TableRow::from_index));
+        assert_eq!(to_table.entities, [entities[5], entities[3], entities[1]]);
+        for (to_row, i) in result.to_rows.iter().zip([5, 3, 1]) {
+            assert_eq!(unsafe { to_table.get_component(a, *to_row) }, Some(&A(i)));
+        }
+
+        // the last entity was swapped into row 3, and then into row 1
+        let swapped = result
+            .swapped
+            .iter()
+            .map(|changed| (changed.entity, changed.changed_value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            swapped,
+            [
+                (entities[4], TableRow::from_index(3)),
+                (entities[4], TableRow::from_index(1))
+            ]
+        );
+        assert_eq!(from_table.entities, [entities[0], entities[4], entities[2]]);
+        for (from_row, i) in [0, 4, 2].into_iter().enumerate() {
+            let from_row = TableRow::from_index(from_row);
+            assert_eq!(
+                unsafe { from_table.get_component(a, from_row) },
+                Some(&A(i))
+            );
+            assert_eq!(
+                unsafe { from_table.get_component(b, from_row) },
+                Some(&B(i))
+            );
+        }
     }
 }
@@ -13,6 +13,7 @@ use bevy_ptr::{
 };
 
 use crate::util::{
+    CloneFn,
     DropFn,
     OnDrop,
 };
@@ -296,6 +297,33 @@ impl BlobVec {
         self.initialize_unchecked(index, value);
     }
 
+    /// Appends a clone of the element behind `value` to the back of the
+    /// vector.
+    ///
+    /// If `clone_fn` panics, the vector is left unchanged.
+    ///
+    /// # Safety
+    /// - The `value` must point to a valid element of the type stored in the
+    ///   [`BlobVec`].
+    /// - `clone_fn` must clone elements of the type stored in the [`BlobVec`].
+    pub unsafe fn push_clone(&mut self, value: Ptr<'_>, clone_fn: CloneFn) {
+        self.reserve(1);
+        let offset = self.len * self.item_layout.size();
+        // SAFETY:
+        // - We reserved space for one more element, so `offset` is in bounds for the
+        //   allocation.
+        // - The item size is a multiple of the erased type's alignment, so adding a
+        //   multiple of it will preserve alignment.
+        let destination = unsafe { self.get_ptr_mut().byte_add(offset) };
+        // SAFETY: The caller ensures that `value` and `clone_fn` match the element
+        // type. The destination is uninitialized memory owned by this vector.
+        unsafe {
+            clone_fn(value, destination);
+        }
+        // only make the element observable once it has been fully initialized.
+        self.len += 1;
+    }
+
     /// Performs a "swap remove" at the given `index`, which removes the item at
     /// `index` and moves the last item in the [`BlobVec`] to `index` (if
     /// `index` is not the last item). It is the caller's responsibility to
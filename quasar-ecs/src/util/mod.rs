@@ -13,10 +13,18 @@ use std::{
     mem::ManuallyDrop,
 };
 
-use bevy_ptr::OwningPtr;
+use bevy_ptr::{
+    OwningPtr,
+    Ptr,
+    PtrMut,
+};
 
 pub type DropFn = unsafe fn(OwningPtr<'_>);
 
+/// Clones the value behind the first pointer into the uninitialized memory
+/// behind the second pointer.
+pub type CloneFn = unsafe fn(Ptr<'_>, PtrMut<'_>);
+
 #[derive(Debug)]
 pub struct OnDrop<F: FnOnce()> {
     callback: ManuallyDrop<F>,
@@ -44,6 +52,19 @@ pub unsafe fn drop_ptr<T>(x: OwningPtr<'_>) {
     }
 }
 
+/// # Safety
+///
+/// `src` must point to a valid `T`, and `dst` must point to memory that is
+/// valid for writing a `T`. Any value at `dst` is overwritten without being
+/// dropped.
+pub unsafe fn clone_ptr<T: Clone>(src: Ptr<'_>, dst: PtrMut<'_>) {
+    // SAFETY: Contract is required to be upheld by the caller.
+    unsafe {
+        let value = src.deref::<T>().clone();
+        dst.as_ptr().cast::<T>().write(value);
+    }
+}
+
 pub fn partition_dedup<T: PartialEq>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
     if slice.is_empty() {
         (slice, &mut [])
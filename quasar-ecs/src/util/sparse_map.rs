@@ -314,8 +314,8 @@ impl<'a, K: SparseMapKey, V> Iterator for Iter<'a, K, V> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -330,8 +330,8 @@ impl<'a, K: SparseMapKey, V> DoubleEndedIterator for Iter<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next_back()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -353,8 +353,8 @@ impl<'a, K: SparseMapKey, V> Iterator for IterMut<'a, K, V> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -369,8 +369,8 @@ impl<'a, K: SparseMapKey, V> DoubleEndedIterator for IterMut<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next_back()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -391,8 +391,8 @@ impl<'a, V> Iterator for Values<'a, V> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let value = self.iter.next()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some(value);
             }
         }
@@ -407,8 +407,8 @@ impl<'a, V> DoubleEndedIterator for Values<'a, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let value = self.iter.next_back()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some(value);
             }
         }
@@ -429,8 +429,8 @@ impl<'a, V> Iterator for ValuesMut<'a, V> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let value = self.iter.next()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some(value);
             }
         }
@@ -445,8 +445,8 @@ impl<'a, V> DoubleEndedIterator for ValuesMut<'a, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let value = self.iter.next_back()?;
-            self.len -= 1;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some(value);
             }
         }
@@ -468,8 +468,8 @@ impl<'a, K: SparseMapKey, V> Iterator for Keys<'a, K, V> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next()?;
-            self.len -= 1;
             if value.is_some() {
+                self.len -= 1;
                 break Some(K::from_index(index));
             }
         }
@@ -484,8 +484,8 @@ impl<'a, K: SparseMapKey, V> DoubleEndedIterator for Keys<'a, K, V> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let (index, value) = self.iter.next_back()?;
-            self.len -= 1;
             if value.is_some() {
+                self.len -= 1;
                 break Some(K::from_index(index));
             }
         }
@@ -507,6 +507,7 @@ impl<'a, K: SparseMapKey, V> Iterator for IntoIter<K, V> {
         loop {
             let (index, value) = self.iter.next()?;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -522,6 +523,7 @@ impl<'a, K: SparseMapKey, V> DoubleEndedIterator for IntoIter<K, V> {
         loop {
             let (index, value) = self.iter.next_back()?;
             if let Some(value) = value {
+                self.len -= 1;
                 break Some((K::from_index(index), value));
             }
         }
@@ -7,11 +7,17 @@ use crate::util::sparse_map::{
     SparseMapKey,
 };
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct SparseSet<K> {
     map: SparseMap<K, ()>,
 }
 
+impl<K> Default for SparseSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K> SparseSet<K> {
     pub fn new() -> Self {
         Self {
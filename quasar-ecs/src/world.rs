@@ -1,42 +1,108 @@
 use std::{
+    any::{
+        type_name,
+        Any,
+    },
+    cell::{
+        Cell,
+        UnsafeCell,
+    },
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    hash::Hash,
     marker::PhantomData,
+    mem::MaybeUninit,
     num::NonZeroUsize,
+    ops::RangeBounds,
+    ptr::NonNull,
     sync::atomic::{
         AtomicUsize,
         Ordering,
     },
 };
 
+use bevy_ptr::{
+    Ptr,
+    PtrMut,
+};
+
 use crate::{
     archetype::{
         create_archetype,
         Archetype,
         ArchetypeEntity,
         ArchetypeId,
+        ArchetypePair,
         Archetypes,
     },
     bundle::{
         Bundle,
+        BundleId,
         BundleInfo,
         Bundles,
+        ComponentRefsCallback,
         DynamicBundle,
         InsertComponentsIntoTable,
         TakeComponentsFromTable,
+        TakeSomeComponentsFromTable,
+    },
+    clone::{
+        ClonedComponents,
+        UncloneablePolicy,
     },
     component::{
         Component,
+        ComponentHook,
         ComponentId,
         Components,
+        MutableComponent,
     },
+    disabled::Disabled,
     entity::{
         ChangedLocation,
         Entities,
         EntitiesIter,
         Entity,
         EntityLocation,
+        EntityRecycling,
+    },
+    error::{
+        BundleError,
+        EntityError,
+        QuasarError,
+    },
+    index::{
+        Indexes,
+        UniquePolicy,
+    },
+    prefab::{
+        self,
+        IsA,
+        Prefab,
+    },
+    query::{
+        self,
+        Access,
+        DefaultQueryFilters,
+        QueryData,
+        QueryFilter,
+        QueryIter,
+        ReadOnlyQueryData,
     },
     resources::Resources,
+    shared::{
+        Shared,
+        SharedComponent,
+        SharedComponents,
+    },
     storage::{
+        custom::{
+            ComponentStorage,
+            StorageHandle,
+            Tick,
+        },
         table::{
             InsertIntoTable,
             MoveRowDropUnmatched,
@@ -45,6 +111,7 @@ use crate::{
             MoveRowPanicUnmatched,
             Table,
             TableId,
+            TableLayout,
             TableRow,
             Tables,
         },
@@ -52,6 +119,17 @@ use crate::{
     },
 };
 
+mod component_entry;
+mod unsafe_world_cell;
+
+pub use component_entry::{
+    ComponentEntry,
+    InheritedComponentEntry,
+    OccupiedComponentEntry,
+    VacantComponentEntry,
+};
+pub use unsafe_world_cell::UnsafeWorldCell;
+
 #[derive(Debug)]
 pub struct World {
     id: WorldId,
@@ -61,6 +139,13 @@ pub struct World {
     tables: Tables,
     bundles: Bundles,
     resources: Resources,
+    indexes: Indexes,
+    shared: SharedComponents,
+    uncloneable_policy: UncloneablePolicy,
+    default_query_filters: DefaultQueryFilters,
+    /// Hooks that were triggered by the current operation, and run once it's
+    /// complete.
+    pending_hooks: Vec<PendingHook>,
 }
 
 impl Default for World {
@@ -71,7 +156,7 @@ impl Default for World {
 
 impl World {
     pub fn new() -> Self {
-        Self {
+        let mut world = Self {
             id: WorldId::new(),
             entities: Entities::default(),
             components: Default::default(),
@@ -79,16 +164,133 @@ impl World {
             tables: Tables::default(),
             bundles: Bundles::default(),
             resources: Resources::default(),
-        }
+            indexes: Indexes::default(),
+            shared: SharedComponents::default(),
+            uncloneable_policy: UncloneablePolicy::default(),
+            default_query_filters: DefaultQueryFilters::default(),
+            pending_hooks: vec![],
+        };
+
+        world.register_component::<IsA>();
+        let prefab_id = world.register_component::<Prefab>();
+        world.default_query_filters.exclude(prefab_id);
+        let disabled_id = world.register_component::<Disabled>();
+        world.default_query_filters.exclude(disabled_id);
+
+        world
     }
 
     pub fn id(&self) -> WorldId {
         self.id
     }
 
+    pub fn register_component<C: Component>(&mut self) -> ComponentId {
+        self.components.register::<C>().id()
+    }
+
+    /// Registers a component and makes it cloneable using its [`Clone`]
+    /// implementation.
+    pub fn register_cloneable_component<C: Component + Clone>(&mut self) -> ComponentId {
+        self.components.register_cloneable::<C>().id()
+    }
+
+    pub fn uncloneable_policy(&self) -> UncloneablePolicy {
+        self.uncloneable_policy
+    }
+
+    /// Sets what to do with components that can't be cloned, when cloning
+    /// entities.
+    pub fn set_uncloneable_policy(&mut self, policy: UncloneablePolicy) {
+        self.uncloneable_policy = policy;
+    }
+
+    pub fn entity_recycling(&self) -> EntityRecycling {
+        self.entities.recycling()
+    }
+
+    /// Sets how the indices of despawned entities are reused.
+    pub fn set_entity_recycling(&mut self, recycling: EntityRecycling) {
+        self.entities.set_recycling(recycling);
+    }
+
+    pub fn table_layout(&self) -> TableLayout {
+        self.tables.layout()
+    }
+
+    /// Sets how the components of tables that are created from now on are
+    /// stored, e.g. in chunks of 16 KiB with `TableLayout::Chunked {
+    /// chunk_size: 16 * 1024 }`. Tables that already exist keep their
+    /// layout, so this is usually set right after creating the world.
+    pub fn set_table_layout(&mut self, layout: TableLayout) {
+        self.tables.set_layout(layout);
+    }
+
+    /// Stores the components `C` in `storage` instead of the world's tables,
+    /// e.g. in a spatial hash. Queries, [`EntityRef::get`] and the other
+    /// accessors fetch the component from the storage, by entity. See
+    /// [`ComponentStorage`].
+    ///
+    /// Components in a custom storage can't be
+    /// [disabled](Self::disable_component), or iterated in
+    /// [chunks](Self::chunks).
+    ///
+    /// Fails if an entity already had `C`, since its storage can't be changed
+    /// anymore then. A storage that was set before is replaced.
+    pub fn set_component_storage<C: Component>(
+        &mut self,
+        storage: impl ComponentStorage<C>,
+    ) -> Result<(), QuasarError> {
+        let component_id = self.components.register::<C>().id();
+        if self
+            .archetypes
+            .iter()
+            .any(|archetype| archetype.contains_component(component_id))
+        {
+            return Err(QuasarError::StorageInUse {
+                component: type_name::<C>(),
+            });
+        }
+        self.tables.set_storage(
+            component_id,
+            StorageHandle::new(storage, self.tables.change_tick()),
+        );
+        Ok(())
+    }
+
+    /// Returns the custom storage of `C`, if it has one. See
+    /// [`set_component_storage`](Self::set_component_storage).
+    pub fn component_storage<C: Component>(&self) -> Option<&dyn ComponentStorage<C>> {
+        let component_id = self.components.get_component_id::<C>()?;
+        unsafe {
+            // SAFETY: The storage isn't borrowed mutably while the world is borrowed.
+            self.tables.get_storage(component_id)?.downcast::<C>()
+        }
+    }
+
+    /// Returns the current change tick, which custom storages are passed when
+    /// components are inserted or mutably accessed. See [`ComponentStorage`].
+    pub fn change_tick(&self) -> Tick {
+        self.tables.change_tick()
+    }
+
+    /// Advances the change tick, e.g. once per frame, and returns the new
+    /// tick.
+    pub fn increment_change_tick(&mut self) -> Tick {
+        let change_tick = self.tables.change_tick().next();
+        self.tables.set_change_tick(change_tick);
+        change_tick
+    }
+
+    /// Despawns all entities.
+    ///
+    /// Handles to the despawned entities stay invalid, even after their
+    /// indices are reused.
     pub fn clear_entities(&mut self) {
-        self.entities.clear();
         self.tables.clear();
+        self.archetypes.clear_entities();
+        self.entities.clear();
+        self.indexes.clear();
+        self.shared.reclaim_unused(&self.archetypes);
     }
 
     pub fn clear_resources(&mut self) {
@@ -100,41 +302,522 @@ impl World {
         self.clear_resources();
     }
 
+    /// Resets the world to the state of a new world, so that it can be reused.
+    ///
+    /// This despawns all entities, removes all resources and forgets all
+    /// archetypes, tables and bundles. Registered components, component
+    /// indexes, the [`UncloneablePolicy`] and the generations of entities are
+    /// kept, so handles to despawned entities stay invalid.
+    pub fn reset(&mut self) {
+        self.clear_all();
+        self.tables.reset();
+        self.archetypes.reset();
+        self.bundles = Bundles::default();
+    }
+
     pub fn spawn_empty(&mut self) -> EntityWorldMut {
-        let entity = self.entities.allocate();
-        EntityWorldMut {
+        self.try_spawn_empty()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Spawns a new entity without any components.
+    ///
+    /// Fails if all entity indices are in use.
+    pub fn try_spawn_empty(&mut self) -> Result<EntityWorldMut<'_>, QuasarError> {
+        let entity = self.entities.allocate().map_err(EntityError::from)?;
+
+        let table_row = self
+            .tables
+            .get_mut(TableId::EMPTY)
+            .insert(entity)
+            .table_row();
+        let archetype_row = self
+            .archetypes
+            .get_mut(ArchetypeId::EMPTY)
+            .insert_entity(ArchetypeEntity { entity, table_row });
+        let entity_location = EntityLocation {
+            archetype_id: ArchetypeId::EMPTY,
+            archetype_row,
+            table_id: TableId::EMPTY,
+            table_row,
+        };
+        self.entities.set_location(entity, entity_location);
+
+        Ok(EntityWorldMut {
             world: self,
             entity,
-            entity_location: EntityLocation::EMPTY,
-        }
+            entity_location,
+        })
     }
 
     pub fn spawn(&mut self, bundle: impl DynamicBundle) -> EntityWorldMut {
-        let mut entity = self.spawn_empty();
-        entity.insert(bundle);
+        self.try_spawn(bundle)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Spawns a new entity with the components in `bundle`.
+    ///
+    /// Fails if the bundle contains duplicate components, if it contains a
+    /// value of a unique component that another entity already has and its
+    /// policy is [`UniquePolicy::Error`], or if all entity indices are in use.
+    /// In all cases no entity is spawned.
+    pub fn try_spawn(
+        &mut self,
+        bundle: impl DynamicBundle,
+    ) -> Result<EntityWorldMut<'_>, QuasarError> {
+        // check the bundle before allocating an entity, so we don't leave an empty
+        // entity behind if it's invalid.
+        self.bundles
+            .get_mut_or_insert_dynamic(&bundle, &mut self.components)?;
+
+        let mut entity = self.try_spawn_empty()?;
+        if let Err(error) = entity.try_insert(bundle) {
+            // a unique component might have a value that another entity already has.
+            entity.despawn();
+            return Err(error);
+        }
+        Ok(entity)
+    }
+
+    /// Spawns a new entity that is marked as a [`Prefab`].
+    pub fn spawn_prefab(&mut self, bundle: impl DynamicBundle) -> EntityWorldMut<'_> {
+        let mut entity = self.spawn(bundle);
+        entity.insert(Prefab);
         entity
     }
 
+    /// Spawns a new entity that inherits all components from `prefab`. See
+    /// [`IsA`].
+    pub fn spawn_instance(&mut self, prefab: Entity) -> EntityWorldMut<'_> {
+        self.spawn(IsA(prefab))
+    }
+
+    /// Spawns an entity for each bundle in `bundles`.
+    ///
+    /// This is faster than calling [`spawn`](Self::spawn) for each bundle,
+    /// since the bundle and its archetype are only looked up once, and the
+    /// entities and their storage are reserved up-front using the iterator's
+    /// size hint.
+    ///
+    /// The entities are spawned as the returned iterator is advanced. If it's
+    /// dropped early, the remaining entities are spawned on drop. The
+    /// `on_add` and `on_insert` hooks run when the iterator is dropped, after
+    /// all entities are spawned, unless the bundle contains unique components.
+    /// Those are spawned one by one, like with [`spawn`](Self::spawn).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bundle type contains a component more than once.
+    ///
+    /// Panics if a bundle contains a value of a unique component that another
+    /// entity already has, and its policy is [`UniquePolicy::Error`]. No entity
+    /// is spawned for that bundle, but the ones before it stay spawned.
+    pub fn spawn_batch<I>(&mut self, bundles: I) -> SpawnBatchIter<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: Bundle,
+    {
+        SpawnBatchIter::new(self, bundles.into_iter())
+    }
+
     pub fn despawn(&mut self, entity: Entity) {
         if let Some(entity) = self.get_entity_world_mut(entity) {
             entity.despawn();
         }
     }
 
+    pub fn try_despawn(&mut self, entity: Entity) -> Result<(), EntityError> {
+        self.try_get_entity_world_mut(entity)?.despawn();
+        Ok(())
+    }
+
+    /// Despawns all entities that match the filter `F`, e.g.
+    /// `world.despawn_where::<With<Enemy>>()`.
+    ///
+    /// If the filter matches all entities of an archetype, they're despawned
+    /// at once, instead of one by one. Like queries, this skips prefabs and
+    /// disabled entities unless `F` mentions [`Prefab`] or [`Disabled`].
+    ///
+    /// The `on_remove` hooks run after all entities are despawned.
+    pub fn despawn_where<F: QueryFilter>(&mut self) {
+        // the hooks are deferred, so the entities that were matched up-front stay
+        // valid.
+        for (archetype_id, entities) in query::filter_entities::<F>(self) {
+            self.despawn_from_archetype(archetype_id, entities);
+        }
+        self.run_hooks();
+    }
+
+    /// Despawns all entities that match the filter `F` and for which `keep`
+    /// returns `false`.
+    ///
+    /// If `keep` returns `false` for all entities of an archetype, they're
+    /// despawned at once, like with [`despawn_where`](Self::despawn_where).
+    /// The `on_remove` hooks run after all entities are despawned.
+    pub fn retain<F: QueryFilter>(&mut self, mut keep: impl FnMut(EntityRef) -> bool) {
+        for (archetype_id, entities) in query::filter_entities::<F>(self) {
+            let despawned = entities
+                .into_iter()
+                .filter(|entity| !keep(self.get_entity(*entity).unwrap()))
+                .collect::<Vec<_>>();
+            self.despawn_from_archetype(archetype_id, despawned);
+        }
+        self.run_hooks();
+    }
+
+    /// Despawns `entities`, which are in the archetype `archetype_id`, and
+    /// queues their `on_remove` hooks.
+    fn despawn_from_archetype(&mut self, archetype_id: ArchetypeId, entities: Vec<Entity>) {
+        if entities.len() == self.archetypes.get(archetype_id).entities().len() {
+            self.despawn_archetype(archetype_id);
+        }
+        else {
+            for entity in entities {
+                self.get_entity_world_mut(entity)
+                    .expect("entity despawned before its hooks ran")
+                    .despawn_without_hooks();
+            }
+        }
+    }
+
+    /// Despawns all entities of an archetype, and queues their `on_remove`
+    /// hooks.
+    fn despawn_archetype(&mut self, archetype_id: ArchetypeId) {
+        let archetype = self.archetypes.get_mut(archetype_id);
+        let table = self.tables.get_mut(archetype.table_id());
+
+        // tables are looked up by all components of an archetype, so they're never
+        // shared.
+        debug_assert_eq!(table.num_entities(), archetype.entities().len());
+
+        let removed = on_remove_hooks(archetype, &self.components);
+        for archetype_entity in archetype.entities() {
+            self.indexes.remove_entity(
+                archetype_entity.entity,
+                archetype.component_ids(),
+                table,
+                archetype_entity.table_row,
+            );
+            for &(hook, component_id) in &removed {
+                self.pending_hooks.push(PendingHook {
+                    hook,
+                    entity: archetype_entity.entity,
+                    component_id,
+                });
+            }
+        }
+
+        table.clear();
+        for archetype_entity in archetype.entities() {
+            self.entities.free(archetype_entity.entity);
+        }
+        archetype.clear();
+    }
+
+    /// Runs the hooks that were triggered by the last operation.
+    fn run_hooks(&mut self) {
+        while !self.pending_hooks.is_empty() {
+            for pending in std::mem::take(&mut self.pending_hooks) {
+                (pending.hook)(self, pending.entity, pending.component_id);
+            }
+        }
+    }
+
+    /// Spawns a new entity with clones of all components of `entity`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `entity` doesn't exist, or if it has a component that can't be
+    /// cloned and the [`UncloneablePolicy`] is
+    /// [`Error`](UncloneablePolicy::Error).
+    pub fn clone_entity(&mut self, entity: Entity) -> Entity {
+        match self.try_clone_entity(entity) {
+            Ok(entity) => entity,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    /// Spawns a new entity with clones of all components of `entity`.
+    pub fn try_clone_entity(&mut self, entity: Entity) -> Result<Entity, QuasarError> {
+        let cloned = self.clone_components(entity)?;
+        let mut clone = self.try_spawn_empty()?;
+        if let Err(error) = clone.insert_remove_take_inner(InsertClonedOp { cloned }) {
+            clone.despawn();
+            return Err(error);
+        }
+        Ok(clone.id())
+    }
+
+    fn clone_components(&self, entity: Entity) -> Result<ClonedComponents, QuasarError> {
+        let entity_location = self.entities.check(entity)?;
+
+        ClonedComponents::new(
+            self.archetypes.get(entity_location.archetype_id),
+            self.tables.get(entity_location.table_id),
+            entity_location.table_row,
+            &self.components,
+            &self.uncloneable_policy,
+        )
+    }
+
     pub fn take<B: Bundle>(&mut self, entity: Entity) -> Option<B> {
         self.get_entity_world_mut(entity)?.take()
     }
 
+    pub fn try_take<B: Bundle>(&mut self, entity: Entity) -> Result<B, QuasarError> {
+        self.try_get_entity_world_mut(entity)?.try_take()
+    }
+
     pub fn remove<B: Bundle>(&mut self, entity: Entity) {
         if let Some(mut entity) = self.get_entity_world_mut(entity) {
             entity.remove::<B>();
         }
     }
 
+    pub fn try_remove<B: Bundle>(&mut self, entity: Entity) -> Result<(), QuasarError> {
+        self.try_get_entity_world_mut(entity)?.try_remove::<B>()
+    }
+
+    /// Disables the component `C` of an entity, so that queries treat it as
+    /// absent, without moving the entity to another archetype. The component
+    /// keeps its value, and can still be accessed through the entity. Does
+    /// nothing if the entity doesn't exist or doesn't have the component
+    /// itself, or if `C` is in a [custom storage](Self::set_component_storage).
+    pub fn disable_component<C: Component>(&mut self, entity: Entity) {
+        if let Some(mut entity) = self.get_entity_world_mut(entity) {
+            entity.disable_component::<C>();
+        }
+    }
+
+    /// Enables the component `C` of an entity again. See
+    /// [`disable_component`](Self::disable_component).
+    pub fn enable_component<C: Component>(&mut self, entity: Entity) {
+        if let Some(mut entity) = self.get_entity_world_mut(entity) {
+            entity.enable_component::<C>();
+        }
+    }
+
+    /// Inserts a bundle into each entity in `batch`. Entities that don't exist
+    /// are skipped.
+    ///
+    /// This is faster than inserting into each entity separately, since the
+    /// entities are grouped by archetype. The archetype they're moved to is
+    /// only looked up once per group, and their rows are moved to its table
+    /// together.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value of a unique component is inserted that another entity
+    /// already has, and its policy is [`UniquePolicy::Error`]. The entities
+    /// before it in the batch may already have been changed.
+    pub fn insert_batch<B: Bundle>(&mut self, batch: impl IntoIterator<Item = (Entity, B)>) {
+        self.batch_inner(
+            batch
+                .into_iter()
+                .map(|(entity, bundle)| (entity, InsertOp::replace(bundle))),
+        );
+    }
+
+    /// Removes the bundle `B` from each entity in `entities`. Entities that
+    /// don't exist or don't have all components of the bundle are skipped.
+    ///
+    /// See [`insert_batch`](Self::insert_batch).
+    pub fn remove_batch<B: Bundle>(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        self.batch_inner(entities.into_iter().map(|entity| {
+            (
+                entity,
+                RemoveOp::<B> {
+                    _bundle: PhantomData,
+                },
+            )
+        }));
+    }
+
+    /// Helper method to perform [`insert_batch`](Self::insert_batch) and
+    /// [`remove_batch`](Self::remove_batch).
+    ///
+    /// All ops must use the same bundle.
+    fn batch_inner<O: InsertRemoveTakeOp>(&mut self, batch: impl Iterator<Item = (Entity, O)>) {
+        // entities that appear more than once in the batch are only changed by the
+        // first op in it. the others, and ops that insert values of unique components
+        // that other entities already have, are applied one by one afterwards.
+        let mut deferred = vec![];
+        let mut seen = HashSet::new();
+
+        // group the entities by their current archetype
+        let mut groups: Vec<(ArchetypeId, Vec<(Entity, O)>)> = vec![];
+        let mut group_indices = HashMap::new();
+        for (entity, op) in batch {
+            let Some(entity_location) = self.entities.get_location(entity)
+            else {
+                continue;
+            };
+            if !seen.insert(entity) {
+                deferred.push((entity, op));
+                continue;
+            }
+            let group_index = *group_indices
+                .entry(entity_location.archetype_id)
+                .or_insert_with(|| {
+                    groups.push((entity_location.archetype_id, vec![]));
+                    groups.len() - 1
+                });
+            groups[group_index].1.push((entity, op));
+        }
+
+        let Some((_, first)) = groups.first()
+        else {
+            return;
+        };
+        let bundle_info = first[0]
+            .1
+            .get_bundle_info(&mut self.bundles, &mut self.components)
+            .unwrap_or_else(|error| panic!("{error}"));
+        let unique = self.indexes.any_unique(bundle_component_ids(bundle_info));
+
+        // entities that had another entity swapped into their table row
+        let mut table_row_changed = vec![];
+
+        for (archetype_id, ops) in groups {
+            match ops[0].1.get_bundle_edge(
+                &mut self.archetypes,
+                archetype_id,
+                bundle_info,
+                |archetype_id, component_ids| {
+                    create_archetype(
+                        archetype_id,
+                        component_ids,
+                        &self.components,
+                        &mut self.tables,
+                    )
+                },
+            ) {
+                Some(Ok((from_archetype, to_archetype))) => {
+                    // the entities of a group are only indexed once they were all moved,
+                    // so values of unique components wouldn't be checked against each
+                    // other.
+                    if unique {
+                        deferred.extend(ops);
+                        continue;
+                    }
+
+                    let op_hooks = ops[0].1.hooks();
+                    for (entity, _) in &ops {
+                        queue_hooks(
+                            op_hooks,
+                            bundle_info,
+                            from_archetype,
+                            &self.components,
+                            *entity,
+                            &mut self.pending_hooks,
+                        );
+                        unindex_components(
+                            bundle_info,
+                            &mut self.indexes,
+                            &self.tables,
+                            *entity,
+                            self.entities.get_location(*entity).unwrap(),
+                        );
+                    }
+
+                    let moved = ops.iter().map(|(entity, _)| *entity).collect::<Vec<_>>();
+                    table_row_changed.extend(move_entities(
+                        ops,
+                        bundle_info,
+                        from_archetype,
+                        to_archetype,
+                        &mut self.tables,
+                        &mut self.entities,
+                    ));
+
+                    for entity in moved {
+                        index_components(
+                            op_hooks,
+                            bundle_info,
+                            from_archetype,
+                            &mut self.indexes,
+                            &self.tables,
+                            &self.entities,
+                            entity,
+                        );
+                    }
+                }
+                Some(Err(archetype)) => {
+                    for (entity, op) in ops {
+                        let entity_location = self.entities.get_location(entity).unwrap();
+                        if !op
+                            .unique_conflicts(bundle_info, archetype, &self.indexes, entity)
+                            .is_empty()
+                        {
+                            deferred.push((entity, op));
+                            continue;
+                        }
+
+                        let op_hooks = op.hooks();
+                        queue_hooks(
+                            op_hooks,
+                            bundle_info,
+                            archetype,
+                            &self.components,
+                            entity,
+                            &mut self.pending_hooks,
+                        );
+                        unindex_components(
+                            bundle_info,
+                            &mut self.indexes,
+                            &self.tables,
+                            entity,
+                            entity_location,
+                        );
+                        write_in_place(
+                            op,
+                            bundle_info,
+                            archetype,
+                            &mut self.tables,
+                            entity_location,
+                        );
+                        index_components(
+                            op_hooks,
+                            bundle_info,
+                            archetype,
+                            &mut self.indexes,
+                            &self.tables,
+                            &self.entities,
+                            entity,
+                        );
+                    }
+                }
+                None => {}
+            }
+        }
+
+        // fix up the table rows stored in the archetypes
+        for entity in table_row_changed {
+            update_table_row(entity, &self.entities, &mut self.archetypes);
+        }
+
+        self.run_hooks();
+
+        for (entity, op) in deferred {
+            if let Some(mut entity) = self.get_entity_world_mut(entity) {
+                if let Err(error @ QuasarError::NotUnique { .. }) =
+                    entity.insert_remove_take_inner(op)
+                {
+                    panic!("{error}");
+                }
+            }
+        }
+    }
+
     pub fn get_entity(&self, entity: Entity) -> Option<EntityRef> {
-        let entity_location = self.entities.get_location(entity)?;
-        Some(EntityRef {
+        self.try_get_entity(entity).ok()
+    }
+
+    pub fn try_get_entity(&self, entity: Entity) -> Result<EntityRef<'_>, EntityError> {
+        let entity_location = self.entities.check(entity)?;
+        Ok(EntityRef {
             components: &self.components,
+            entities: &self.entities,
             archetypes: &self.archetypes,
             tables: &self.tables,
             entity,
@@ -143,723 +826,4885 @@ impl World {
     }
 
     pub fn get_entity_mut(&mut self, entity: Entity) -> Option<EntityMut> {
-        let entity_location = self.entities.get_location(entity)?;
-        Some(EntityMut {
+        self.try_get_entity_mut(entity).ok()
+    }
+
+    pub fn try_get_entity_mut(&mut self, entity: Entity) -> Result<EntityMut<'_>, EntityError> {
+        let entity_location = self.entities.check(entity)?;
+        Ok(EntityMut {
             components: &self.components,
+            entities: &self.entities,
             archetypes: &self.archetypes,
-            tables: &mut self.tables,
+            tables: &self.tables,
             entity,
             entity_location,
         })
     }
 
+    /// Returns mutable access to several entities at once.
+    ///
+    /// Fails if any of the entities doesn't exist, with
+    /// [`EntityError::Duplicate`] if an entity is passed more than once, or
+    /// with [`EntityError::Inherits`] if an entity inherits components from
+    /// another one, since the components could then be borrowed through both.
+    pub fn get_many_entities_mut<const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Result<[EntityMut<'_>; N], EntityError> {
+        for (i, entity) in entities.iter().enumerate() {
+            if entities[..i].contains(entity) {
+                return Err(EntityError::Duplicate(*entity));
+            }
+        }
+
+        for entity in entities {
+            let entity_location = self.entities.check(entity)?;
+            if let Some(prefab) = prefab::prefabs(
+                entity_location,
+                &self.components,
+                &self.entities,
+                &self.tables,
+            )
+            .find(|prefab| entities.contains(prefab))
+            {
+                return Err(EntityError::Inherits { entity, prefab });
+            }
+        }
+
+        let world = self.as_unsafe_world_cell();
+        let mut entity_muts = Vec::with_capacity(N);
+        for entity in entities {
+            entity_muts.push(unsafe {
+                // SAFETY: We have exclusive access to the world, and the entities are
+                // distinct, so each `EntityMut` has exclusive access to the components of
+                // its entity, even if they share a table.
+                world.get_entity_mut(entity)?
+            });
+        }
+
+        Ok(entity_muts
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("one EntityMut per entity")))
+    }
+
     pub fn get_entity_world_mut(&mut self, entity: Entity) -> Option<EntityWorldMut> {
-        let entity_location = self.entities.get_location(entity)?;
-        Some(EntityWorldMut {
+        self.try_get_entity_world_mut(entity).ok()
+    }
+
+    pub fn try_get_entity_world_mut(
+        &mut self,
+        entity: Entity,
+    ) -> Result<EntityWorldMut<'_>, EntityError> {
+        let entity_location = self.entities.check(entity)?;
+        Ok(EntityWorldMut {
             world: self,
             entity,
             entity_location,
         })
     }
 
+    /// Iterates over all entities, except [prefabs](Prefab) and
+    /// [disabled](EntityWorldMut::disable) entities, which are skipped like in
+    /// queries. Query with [`Allow`](crate::Allow) to include them.
     pub fn iter_entities(&self) -> EntityIter {
         EntityIter {
             components: &self.components,
+            entities: &self.entities,
             archetypes: &self.archetypes,
             tables: &self.tables,
+            default_query_filters: &self.default_query_filters,
             iter: self.entities.iter(),
         }
     }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct WorldId(NonZeroUsize);
 
-impl WorldId {
-    fn new() -> Self {
-        static IDS: AtomicUsize = AtomicUsize::new(1);
-        Self(NonZeroUsize::new(IDS.fetch_add(1, Ordering::Relaxed)).expect("WorldId overflow"))
+    /// Iterates over all entities that match the query `D`, e.g.
+    /// `world.query::<(Entity, &Position)>()`.
+    ///
+    /// Components that are inherited from a prefab are matched too. Prefabs
+    /// themselves are skipped, unless the query mentions [`Prefab`], and so are
+    /// [disabled](EntityWorldMut::disable) entities, unless it mentions
+    /// [`Disabled`].
+    pub fn query<D: ReadOnlyQueryData>(&self) -> QueryIter<'_, D, ()> {
+        self.query_filtered::<D, ()>()
     }
-}
 
-pub struct EntityRef<'world> {
-    components: &'world Components,
-    archetypes: &'world Archetypes,
-    tables: &'world Tables,
-    entity: Entity,
-    entity_location: EntityLocation,
-}
+    pub fn query_filtered<D: ReadOnlyQueryData, F: QueryFilter>(&self) -> QueryIter<'_, D, F> {
+        unsafe {
+            // SAFETY: The query only reads components.
+            QueryIter::new(self)
+        }
+    }
 
-impl<'a> EntityRef<'a> {
-    pub fn id(&self) -> Entity {
-        self.entity
+    /// Iterates over all entities that match the query `D`, which may access
+    /// components mutably, e.g. `world.query_mut::<&mut Position>()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the query accesses a component mutably more than once.
+    pub fn query_mut<D: QueryData>(&mut self) -> QueryIter<'_, D, ()> {
+        self.query_filtered_mut::<D, ()>()
     }
 
-    pub fn contains<C: Component>(&self) -> bool {
-        contains_component::<C>(self.entity_location, self.components, self.archetypes)
+    pub fn query_filtered_mut<D: QueryData, F: QueryFilter>(&mut self) -> QueryIter<'_, D, F> {
+        unsafe {
+            // SAFETY: We have exclusive access to the world.
+            QueryIter::new(self)
+        }
     }
 
-    pub fn get<C: Component>(&self) -> Option<&C> {
-        get_component(self.entity_location, self.components, self.tables)
+    /// Iterates over the entities that have the value `value` of the shared
+    /// component `C`. Together with [`shared_values`](Self::shared_values),
+    /// this can be used to process entities grouped by value, e.g. to batch
+    /// draw calls by material:
+    ///
+    /// ```ignore
+    /// for material in world.shared_values::<Material>() {
+    ///     bind(material);
+    ///     for transform in world.query_shared::<&Transform, _>(material) {
+    ///         draw(transform);
+    ///     }
+    /// }
+    /// ```
+    pub fn query_shared<D: ReadOnlyQueryData, C: SharedComponent>(
+        &self,
+        value: &C,
+    ) -> QueryIter<'_, D, ()> {
+        let filter = self.shared_filter(value);
+        unsafe {
+            // SAFETY: The query only reads components.
+            QueryIter::with_archetype_filter(self, filter)
+        }
     }
-}
 
-#[derive(Debug)]
-pub struct EntityMut<'world> {
-    components: &'world Components,
-    archetypes: &'world Archetypes,
-    tables: &'world mut Tables,
-    entity: Entity,
-    entity_location: EntityLocation,
-}
+    /// Like [`query_shared`](Self::query_shared), but the query may access
+    /// components mutably.
+    pub fn query_shared_mut<D: QueryData, C: SharedComponent>(
+        &mut self,
+        value: &C,
+    ) -> QueryIter<'_, D, ()> {
+        let filter = self.shared_filter(value);
+        unsafe {
+            // SAFETY: We have exclusive access to the world.
+            QueryIter::with_archetype_filter(self, filter)
+        }
+    }
 
-impl<'a> EntityMut<'a> {
-    pub fn id(&self) -> Entity {
-        self.entity
+    /// Returns a filter that matches the archetypes of the entities that have
+    /// the value `value` of the shared component `C`.
+    fn shared_filter<C: SharedComponent>(&self, value: &C) -> impl Fn(&Archetype) -> bool {
+        let ids = self
+            .components
+            .get_component_id::<Shared<C>>()
+            .and_then(|shared_id| Some((shared_id, self.shared.get_id(shared_id, value)?)));
+        move |archetype| {
+            ids.is_some_and(|(shared_id, value_id)| {
+                archetype.shared_value(shared_id) == Some(value_id)
+            })
+        }
     }
 
-    pub fn contains<C: Component>(&self) -> bool {
-        contains_component::<C>(self.entity_location, self.components, self.archetypes)
+    /// Iterates over the entities that have the component `C`, and their
+    /// components, one chunk of a table at a time. With
+    /// [`TableLayout::Chunked`], each chunk is a fixed-size block of memory;
+    /// otherwise each table is a single chunk.
+    ///
+    /// Like queries, this skips prefabs and disabled entities, unless `C` is
+    /// [`Prefab`] or [`Disabled`]. Unlike queries, components inherited from a
+    /// prefab are not included, and components that were
+    /// [disabled](Self::disable_component) are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `C` uses [`StorageType::Stable`] or a
+    /// [custom storage](Self::set_component_storage), since those components
+    /// aren't stored next to each other.
+    pub fn chunks<C: Component>(&self) -> impl Iterator<Item = (&[Entity], &[C])> {
+        self.assert_chunkable::<C>();
+        self.chunk_tables::<C>().flat_map(|(component_id, table)| {
+            table.chunks().map(move |chunk| {
+                let components = unsafe {
+                    // SAFETY: The column with `component_id` stores values of type `C`.
+                    chunk.get_slice(component_id).unwrap()
+                };
+                (chunk.entities(), components)
+            })
+        })
     }
 
-    pub fn get<C: Component>(&self) -> Option<&C> {
-        get_component(self.entity_location, self.components, self.tables)
+    /// Like [`chunks`](Self::chunks), but the components can be mutated.
+    pub fn chunks_mut<C: MutableComponent>(
+        &mut self,
+    ) -> impl Iterator<Item = (&[Entity], &mut [C])> {
+        self.assert_chunkable::<C>();
+        self.chunk_tables::<C>().flat_map(|(component_id, table)| {
+            table.chunks().map(move |chunk| {
+                let components = unsafe {
+                    // SAFETY: The column with `component_id` stores values of type `C`. We
+                    // have exclusive access to the world, and each chunk is only visited
+                    // once, so no two slices alias.
+                    let cells = chunk.get_slice_unsafe::<C>(component_id).unwrap();
+                    std::slice::from_raw_parts_mut(UnsafeCell::raw_get(cells.as_ptr()), cells.len())
+                };
+                (chunk.entities(), components)
+            })
+        })
     }
 
-    pub fn get_mut<C: Component>(&mut self) -> Option<&mut C> {
-        get_component_mut(self.entity_location, self.components, self.tables)
+    fn assert_chunkable<C: Component>(&self) {
+        assert!(
+            C::STORAGE_TYPE != StorageType::Stable,
+            "{} uses stable storage, and can't be iterated in chunks",
+            type_name::<C>()
+        );
+        assert!(
+            self.components
+                .get_component_id::<C>()
+                .and_then(|component_id| self.tables.get_storage(component_id))
+                .is_none(),
+            "{} uses a custom storage, and can't be iterated in chunks",
+            type_name::<C>()
+        );
     }
 
-    pub fn as_readonly(&self) -> EntityRef {
-        EntityRef {
-            components: self.components,
-            archetypes: self.archetypes,
-            tables: self.tables,
-            entity: self.entity,
-            entity_location: self.entity_location,
+    /// Returns the tables with a column for `C`, whose archetypes aren't
+    /// excluded by the [`DefaultQueryFilters`].
+    fn chunk_tables<C: Component>(&self) -> impl Iterator<Item = (ComponentId, &Table)> {
+        let component_id = self.components.get_component_id::<C>();
+        let mut access = Access::default();
+        if let Some(component_id) = component_id {
+            access.add_read(component_id);
         }
+        self.archetypes.iter().filter_map(move |archetype| {
+            let component_id = component_id?;
+            (archetype.contains_component(component_id)
+                && self
+                    .default_query_filters
+                    .matches_archetype(&access, archetype))
+            .then(|| (component_id, self.tables.get(archetype.table_id())))
+        })
     }
-}
-
-#[derive(Debug)]
-pub struct EntityWorldMut<'world> {
-    world: &'world mut World,
-    entity: Entity,
-    entity_location: EntityLocation,
-}
 
-impl<'a> EntityWorldMut<'a> {
-    pub fn id(&self) -> Entity {
-        self.entity
+    /// Returns all distinct values of the shared component `C` that entities
+    /// have. This might include values that entities don't have anymore, until
+    /// they're forgotten.
+    pub fn shared_values<C: SharedComponent>(&self) -> impl Iterator<Item = &C> {
+        self.components
+            .get_component_id::<Shared<C>>()
+            .into_iter()
+            .flat_map(|shared_id| self.shared.iter(shared_id))
     }
 
-    pub fn contains<C: Component>(&self) -> bool {
-        contains_component::<C>(
-            self.entity_location,
-            &self.world.components,
-            &self.world.archetypes,
-        )
+    /// Creates a hash index for the component `C`, so that entities can be
+    /// looked up by its value with [`lookup`](Self::lookup) in O(1).
+    ///
+    /// Only immutable components can be indexed. The index is kept up to date
+    /// as the component is inserted, replaced and removed. If `C` already has
+    /// an index, it's replaced.
+    pub fn add_hash_index<C: Component + Clone + Eq + Hash>(&mut self) {
+        const {
+            assert!(!C::MUTABLE, "only immutable components can be indexed");
+        }
+        let component_id = self.register_component::<C>();
+        self.indexes.insert_hash_index::<C>(component_id);
+        self.index_existing(component_id);
     }
 
-    pub fn get<C: Component>(&self) -> Option<&C> {
-        get_component(
-            self.entity_location,
-            &self.world.components,
-            &self.world.tables,
-        )
+    /// Creates an ordered index for the component `C`, so that entities can be
+    /// looked up by its value with [`lookup`](Self::lookup) in O(log n), and
+    /// by a range of values with [`lookup_range`](Self::lookup_range).
+    ///
+    /// Only immutable components can be indexed. The index is kept up to date
+    /// as the component is inserted, replaced and removed. If `C` already has
+    /// an index, it's replaced.
+    pub fn add_ordered_index<C: Component + Clone + Ord>(&mut self) {
+        const {
+            assert!(!C::MUTABLE, "only immutable components can be indexed");
+        }
+        let component_id = self.register_component::<C>();
+        self.indexes.insert_ordered_index::<C>(component_id);
+        self.index_existing(component_id);
     }
 
-    pub fn get_mut<C: Component>(&mut self) -> Option<&mut C> {
-        get_component_mut(
-            self.entity_location,
-            &self.world.components,
-            &mut self.world.tables,
-        )
+    /// Adds the entities that already have a component to its new index.
+    fn index_existing(&mut self, component_id: ComponentId) {
+        for archetype in self.archetypes.iter() {
+            if !archetype.contains_component(component_id) {
+                continue;
+            }
+            let table = self.tables.get(archetype.table_id());
+            for archetype_entity in archetype.entities() {
+                self.indexes.insert_entity(
+                    archetype_entity.entity,
+                    [component_id],
+                    table,
+                    archetype_entity.table_row,
+                );
+            }
+        }
     }
 
-    pub fn despawn(self) {
-        todo!();
+    /// Makes the values of the indexed component `C` unique, so that no two
+    /// entities can have the same value. `policy` decides what happens when an
+    /// entity gets a value that another entity already has.
+    ///
+    /// Fails with [`QuasarError::NotUnique`] if entities already share a value,
+    /// in which case `C` isn't made unique.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `C` doesn't have an index. See
+    /// [`add_hash_index`](Self::add_hash_index) and
+    /// [`add_ordered_index`](Self::add_ordered_index).
+    pub fn set_unique<C: Component>(&mut self, policy: UniquePolicy) -> Result<(), QuasarError> {
+        self.components
+            .get_component_id::<C>()
+            .and_then(|component_id| self.indexes.set_unique(component_id, policy))
+            .unwrap_or_else(|| panic!("component {} has no index", type_name::<C>()))
+            .map_err(|entity| {
+                QuasarError::NotUnique {
+                    component: type_name::<C>(),
+                    entity,
+                }
+            })
     }
 
-    pub fn insert(&mut self, bundle: impl DynamicBundle) -> &mut Self {
-        self.insert_remove_take_inner(InsertOp { bundle });
-        self
+    /// Returns the entities that have the component `C` with the value
+    /// `value`. Entities that only inherit the component from a prefab are not
+    /// included.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `C` doesn't have an index. See
+    /// [`add_hash_index`](Self::add_hash_index) and
+    /// [`add_ordered_index`](Self::add_ordered_index).
+    pub fn lookup<C: Component>(&self, value: &C) -> &[Entity] {
+        self.components
+            .get_component_id::<C>()
+            .and_then(|component_id| {
+                unsafe {
+                    // SAFETY: `value` is a value of the component with `component_id`.
+                    self.indexes.lookup(component_id, Ptr::from(value))
+                }
+            })
+            .unwrap_or_else(|| panic!("component {} has no index", type_name::<C>()))
     }
 
-    pub fn remove<B: Bundle>(&mut self) {
-        self.insert_remove_take_inner(RemoveOp::<B> {
-            _bundle: PhantomData,
-        });
+    /// Returns the entities that have the component `C` with a value in
+    /// `range`, ordered by their values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `C` doesn't have an ordered index. See
+    /// [`add_ordered_index`](Self::add_ordered_index).
+    pub fn lookup_range<C: Component + Ord>(
+        &self,
+        range: impl RangeBounds<C>,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        self.components
+            .get_component_id::<C>()
+            .and_then(|component_id| self.indexes.lookup_range(component_id, range))
+            .unwrap_or_else(|| panic!("component {} has no ordered index", type_name::<C>()))
     }
 
-    #[must_use]
-    pub fn take<B: Bundle>(&mut self) -> Option<B> {
-        self.insert_remove_take_inner(TakeOp::<B> {
-            _bundle: PhantomData,
-        })
+    /// Returns a handle through which parts of the world can be borrowed
+    /// independently. See [`UnsafeWorldCell`].
+    pub fn as_unsafe_world_cell(&mut self) -> UnsafeWorldCell<'_> {
+        UnsafeWorldCell::new_mutable(self)
     }
 
-    /// Helper method to perform [`insert`], [`remove`] and [`take`].
-    ///
-    /// [`insert`], [`remove`] and [`take`] are very similar since they all move
-    /// an entity from one archetype to another, moving its data from one table
-    /// to another. More specifically, sometimes they don't actually need to
-    /// move between archetypes or tables (e.g. inserting `()`). All these
-    /// operations are done using this general method, and are specialized
-    /// via the `op` parameter and the [`InsertRemoveTakeOp`] trait.
-    fn insert_remove_take_inner<O: InsertRemoveTakeOp>(&mut self, op: O) -> Option<O::Output> {
-        // if this op produces an output (i.e. take), it might store it here. this might
-        // also stay None, if the operation fails (e.g. the entity doesn't contain the
-        // full bundle)
-        let mut output = None;
-
-        // get info for this bundle
-        let bundle_info = op.get_bundle_info(&mut self.world.bundles, &mut self.world.components);
-
-        // add/remove bundle to the archetype graph. this creates an
-        // AddBundle/RemoveBundle edge from `self.entity_location.archetype_id`
-        // to whatever archetype we get after the insertion.
-        //
-        // this also creates the resulting archetype (and table) if necessary, by
-        // calling the provided closure.
-        //
-        // if adding/removing this bundle doesn't change the archetype (e.g. adding
-        // `()`), this returns `None`. if it does change the entity's archetype,
-        // this returns a mutable borrow for the old and new archetype.
-
-        if let Some((from_archetype, to_archetype)) = op.get_bundle_edge(
-            &mut self.world.archetypes,
-            self.entity_location.archetype_id,
-            bundle_info,
-            |archetype_id, component_ids| {
-                create_archetype(
-                    archetype_id,
-                    component_ids,
-                    &self.world.components,
-                    &mut self.world.tables,
-                )
-            },
-        ) {
-            // create a new location for our entity. we'll populate it as we get the
-            // information.
-            let mut new_entity_location = self.entity_location.clone();
-            new_entity_location.archetype_id = to_archetype.id();
-            new_entity_location.table_id = to_archetype.table_id();
-
-            // `Table::get_mut_pair` either returns a pair of mutable borrows of tables for
-            // the supplied table IDs, if they're not identical, or a single
-            // mutable borrow for the table
-            match self
-                .world
-                .tables
-                .get_mut_pair(from_archetype.table_id(), to_archetype.table_id())
-            {
-                Ok((from_table, to_table)) => {
-                    // moving our entity actually involves moving from a table to another table.
-
-                    // first take out anything we want to return
-                    // note: if the op takes out anything it must make sure it's only components
-                    // that are not moved to the new table, and those are forgotten when
-                    // `from_table.move_row` handles them as unmatched.
-                    output = Some(op.take(bundle_info, from_table, self.entity_location.table_row));
-
-                    // `Table::move_row` will move our entity's row from `from_table` to `to_table`,
-                    // moving all the data in the columns. Note that this will
-                    // only populate columns in `to_table` that exist in both tables. In our case
-                    // we'll still need to add some components from the bundle.
-                    //
-                    // `Table::move_row` handily also returns a `InsertIntoTable`, with which we can
-                    // insert the remaining components later.
-
-                    let mut move_result = unsafe {
-                        from_table.move_row(
-                            self.entity_location.table_row,
-                            to_table,
-                            self.entity,
-                            op.handle_unmatched(),
-                        )
-                    };
-
-                    new_entity_location.table_row = move_result.to_row();
-
-                    // while removing our entity from `from_table`, another row was swapped into its
-                    // place. we need to update its information
-                    if let Some(changed_location) = move_result.swapped {
-                        changed_location.apply(&mut self.world.entities);
-                    }
-
-                    // insert the remaining components from the bundle
-                    op.insert(bundle_info, &mut move_result.insert, from_archetype);
-                }
-                Err(_table) => {
-                    // either both archetypes have the same table, or `from_row`
-                    // is invalid, so there's nothing to do.
-                    // the bundle also can't add any components we don't
-                    // already have, or remove any components.
-                }
-            };
-
-            // remove our entity from `from_archetype`. this might again involve updating
-            // metadata from another entity due to swapping.
-            if let Some(changed_location) =
-                from_archetype.remove_entity(self.entity_location.archetype_row)
-            {
-                changed_location.apply(&mut self.world.entities);
-            }
-
-            // we can finally insert our entity into the new archetype
-            new_entity_location.archetype_row = to_archetype.insert_entity(ArchetypeEntity {
-                entity: self.entity,
-                table_row: new_entity_location.table_row,
-            });
-
-            // update our entity's location metadata
-            ChangedLocation {
-                entity: self.entity,
-                changed_value: new_entity_location,
-            }
-            .apply(&mut self.world.entities);
+    /// Like [`as_unsafe_world_cell`](Self::as_unsafe_world_cell), but the
+    /// handle can only be used for shared access.
+    pub fn as_unsafe_world_cell_readonly(&self) -> UnsafeWorldCell<'_> {
+        UnsafeWorldCell::new_readonly(self)
+    }
 
-            // update the cached `EntityLocation`
-            self.entity_location = new_entity_location;
-        }
+    pub(crate) fn components(&self) -> &Components {
+        &self.components
+    }
 
-        output
+    pub(crate) fn entities(&self) -> &Entities {
+        &self.entities
     }
 
-    pub fn world(&self) -> &World {
-        self.world
+    pub(crate) fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
     }
 
-    pub fn world_mut(&mut self) -> &mut World {
-        self.world
+    pub(crate) fn tables(&self) -> &Tables {
+        &self.tables
     }
 
-    pub fn into_world_mut(self) -> &'a mut World {
-        self.world
+    pub(crate) fn default_query_filters(&self) -> &DefaultQueryFilters {
+        &self.default_query_filters
     }
-}
 
-pub struct EntityIter<'a> {
-    components: &'a Components,
-    archetypes: &'a Archetypes,
-    tables: &'a Tables,
-    iter: EntitiesIter<'a>,
+    pub(crate) fn shared(&self) -> &SharedComponents {
+        &self.shared
+    }
 }
 
-impl<'a> Iterator for EntityIter<'a> {
-    type Item = EntityRef<'a>;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WorldId(NonZeroUsize);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let (entity, entity_location) = self.iter.next()?;
-        Some(EntityRef {
-            components: self.components,
-            archetypes: self.archetypes,
-            tables: self.tables,
-            entity,
-            entity_location,
-        })
+impl WorldId {
+    fn new() -> Self {
+        static IDS: AtomicUsize = AtomicUsize::new(1);
+        Self(NonZeroUsize::new(IDS.fetch_add(1, Ordering::Relaxed)).expect("WorldId overflow"))
     }
 }
 
-fn contains_component<C: Component>(
+pub struct EntityRef<'world> {
+    components: &'world Components,
+    entities: &'world Entities,
+    archetypes: &'world Archetypes,
+    tables: &'world Tables,
+    entity: Entity,
     entity_location: EntityLocation,
-    components: &Components,
-    archetypes: &Archetypes,
-) -> bool {
-    let Some(component_id) = components.get_component_id::<C>()
-    else {
-        return false;
-    };
-    let archetype = archetypes.get(entity_location.archetype_id);
-    archetype.contains_component(component_id)
 }
 
-fn get_component<'a, C: Component>(
-    entity_location: EntityLocation,
-    components: &Components,
-    tables: &'a Tables,
-) -> Option<&'a C> {
-    dbg!(&entity_location);
-
-    let component_id = components.get_component_id::<C>()?;
-    match C::STORAGE_TYPE {
-        StorageType::Table => {
-            let table = tables.get(entity_location.table_id);
-            unsafe {
-                // SAFETY: The type `C` is the type stored in the column with `component_id`.
-                table.get_component(component_id, entity_location.table_row)
-            }
-        }
-        _ => todo!(),
+impl<'a> EntityRef<'a> {
+    pub fn id(&self) -> Entity {
+        self.entity
     }
-}
 
-fn get_component_mut<'a, C: Component>(
-    entity_location: EntityLocation,
-    components: &Components,
-    tables: &'a mut Tables,
-) -> Option<&'a mut C> {
-    let component_id = components.get_component_id::<C>()?;
-    match C::STORAGE_TYPE {
-        StorageType::Table => {
-            let table = tables.get_mut(entity_location.table_id);
-            unsafe {
-                // SAFETY: The type `C` is the type stored in the column with `component_id`.
-                table.get_component_mut(component_id, entity_location.table_row)
-            }
-        }
-        _ => todo!(),
+    /// Returns whether the entity has the component, either itself or
+    /// inherited from a prefab.
+    pub fn contains<C: Component>(&self) -> bool {
+        contains_component::<C>(
+            self.entity_location,
+            self.components,
+            self.entities,
+            self.archetypes,
+            self.tables,
+        )
     }
-}
-
-unsafe trait InsertRemoveTakeOp {
-    type Output;
-
-    fn get_bundle_info<'a>(
-        &self,
-        bundles: &'a mut Bundles,
-        components: &mut Components,
-    ) -> &'a BundleInfo;
 
-    fn get_bundle_edge<'a>(
-        &self,
-        archetypes: &'a mut Archetypes,
-        archetype_id: ArchetypeId,
-        bundle_info: &BundleInfo,
-        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&'a mut Archetype, &'a mut Archetype)>;
+    /// Returns whether the entity itself has the component.
+    pub fn owns<C: Component>(&self) -> bool {
+        owns_component::<C>(self.entity_location, self.components, self.archetypes)
+    }
 
-    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched;
+    /// Returns whether the entity itself has the component, and it's not
+    /// disabled.
+    pub fn is_component_enabled<C: Component>(&self) -> bool {
+        is_component_enabled::<C>(self.entity_location, self.components, self.tables)
+    }
 
-    fn insert(
-        self,
-        bundle_info: &BundleInfo,
-        insert_into_table: &mut InsertIntoTable,
-        from_archetype: &Archetype,
-    );
+    /// Returns the component, either the entity's own or inherited from a
+    /// prefab. See [`EntityWorldMut::get`] for when its address can change.
+    pub fn get<C: Component>(&self) -> Option<&C> {
+        get_component(
+            self.entity_location,
+            self.components,
+            self.entities,
+            self.archetypes,
+            self.tables,
+        )
+    }
 
-    fn take(
-        &self,
-        bundle_info: &BundleInfo,
-        table: &mut Table,
-        table_row: TableRow,
-    ) -> Self::Output;
+    /// Like [`get`](Self::get), but fails with
+    /// [`EntityError::MissingComponent`] if the entity doesn't have the
+    /// component.
+    pub fn try_get<C: Component>(&self) -> Result<&C, EntityError> {
+        self.get::<C>()
+            .ok_or_else(|| missing_component::<C>(self.entity))
+    }
 }
 
-struct InsertOp<B> {
-    bundle: B,
+/// Mutable access to the components of an entity.
+///
+/// The tables are only borrowed shared, so that several `EntityMut`s can exist
+/// at once (see [`World::get_many_entities_mut`]). Each `EntityMut` has
+/// exclusive access to the components of its own entity, and no two of them
+/// refer to the same entity.
+#[derive(Debug)]
+pub struct EntityMut<'world> {
+    components: &'world Components,
+    entities: &'world Entities,
+    archetypes: &'world Archetypes,
+    tables: &'world Tables,
+    entity: Entity,
+    entity_location: EntityLocation,
 }
 
-unsafe impl<B: DynamicBundle> InsertRemoveTakeOp for InsertOp<B> {
-    type Output = ();
-
-    fn get_bundle_info<'a>(
-        &self,
-        bundles: &'a mut Bundles,
-        components: &mut Components,
-    ) -> &'a BundleInfo {
-        bundles.get_mut_or_insert_dynamic(&self.bundle, components)
+impl<'a> EntityMut<'a> {
+    pub fn id(&self) -> Entity {
+        self.entity
     }
 
-    fn get_bundle_edge<'a>(
-        &self,
-        archetypes: &'a mut Archetypes,
-        archetype_id: ArchetypeId,
-        bundle_info: &BundleInfo,
-        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&'a mut Archetype, &'a mut Archetype)> {
-        archetypes.add_bundle(archetype_id, bundle_info, create_archetype)
+    /// Returns whether the entity has the component, either itself or
+    /// inherited from a prefab.
+    pub fn contains<C: Component>(&self) -> bool {
+        contains_component::<C>(
+            self.entity_location,
+            self.components,
+            self.entities,
+            self.archetypes,
+            self.tables,
+        )
     }
 
-    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
-        MoveRowPanicUnmatched
+    /// Returns whether the entity itself has the component.
+    pub fn owns<C: Component>(&self) -> bool {
+        owns_component::<C>(self.entity_location, self.components, self.archetypes)
     }
 
-    fn insert(
-        self,
-        bundle_info: &BundleInfo,
-        insert_into_table: &mut InsertIntoTable,
-        from_archetype: &Archetype,
-    ) {
-        // get the AddBundle edge. we need its metadata about duplicate components to
-        // not add components from the bundle that were also moved over from
-        // `from_table`.
-        let add_bundle = from_archetype.add_bundle(bundle_info.id()).unwrap();
+    /// Returns whether the entity itself has the component, and it's not
+    /// disabled.
+    pub fn is_component_enabled<C: Component>(&self) -> bool {
+        is_component_enabled::<C>(self.entity_location, self.components, self.tables)
+    }
 
-        // insert the remaining components from the bundle
-        self.bundle.into_components(InsertComponentsIntoTable::new(
-            bundle_info,
-            |component_id| !add_bundle.duplicate.contains(&component_id),
-            insert_into_table,
-        ));
+    /// Returns the component, either the entity's own or inherited from a
+    /// prefab. See [`EntityWorldMut::get`] for when its address can change.
+    pub fn get<C: Component>(&self) -> Option<&C> {
+        get_component(
+            self.entity_location,
+            self.components,
+            self.entities,
+            self.archetypes,
+            self.tables,
+        )
     }
 
-    fn take(
-        &self,
-        _bundle_info: &BundleInfo,
-        _table: &mut Table,
-        _table_row: TableRow,
-    ) -> Self::Output {
-        ()
+    /// Like [`get`](Self::get), but fails with
+    /// [`EntityError::MissingComponent`] if the entity doesn't have the
+    /// component.
+    pub fn try_get<C: Component>(&self) -> Result<&C, EntityError> {
+        self.get::<C>()
+            .ok_or_else(|| missing_component::<C>(self.entity))
+    }
+
+    /// Returns the component mutably, if the entity itself has it. Components
+    /// inherited from a prefab can only be mutated through
+    /// [`EntityWorldMut::get_mut`].
+    pub fn get_mut<C: MutableComponent>(&mut self) -> Option<&mut C> {
+        unsafe {
+            // SAFETY: This `EntityMut` has exclusive access to the components of its
+            // entity, and the returned reference borrows it mutably.
+            let cell = get_component_unsafe(self.entity_location, self.components, self.tables)?;
+            Some(&mut *cell.get())
+        }
+    }
+
+    pub fn as_readonly(&self) -> EntityRef {
+        EntityRef {
+            components: self.components,
+            entities: self.entities,
+            archetypes: self.archetypes,
+            tables: self.tables,
+            entity: self.entity,
+            entity_location: self.entity_location,
+        }
     }
 }
 
-struct RemoveOp<B> {
-    _bundle: PhantomData<B>,
+#[derive(Debug)]
+pub struct EntityWorldMut<'world> {
+    world: &'world mut World,
+    entity: Entity,
+    entity_location: EntityLocation,
 }
 
-unsafe impl<B: Bundle> InsertRemoveTakeOp for RemoveOp<B> {
-    type Output = ();
+impl<'a> EntityWorldMut<'a> {
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
 
-    fn get_bundle_info<'a>(
-        &self,
-        bundles: &'a mut Bundles,
-        components: &mut Components,
-    ) -> &'a BundleInfo {
-        bundles.get_mut_or_insert_static::<B>(components)
+    /// Returns whether a hook despawned the entity. If it did, the other
+    /// methods panic, and the fallible ones fail with
+    /// [`EntityError::Despawned`].
+    pub fn is_despawned(&self) -> bool {
+        self.entity_location.is_invalid()
+    }
+
+    /// Returns whether the entity has the component, either itself or
+    /// inherited from a prefab.
+    pub fn contains<C: Component>(&self) -> bool {
+        contains_component::<C>(
+            self.location(),
+            &self.world.components,
+            &self.world.entities,
+            &self.world.archetypes,
+            &self.world.tables,
+        )
+    }
+
+    /// Returns whether the entity itself has the component.
+    pub fn owns<C: Component>(&self) -> bool {
+        owns_component::<C>(
+            self.location(),
+            &self.world.components,
+            &self.world.archetypes,
+        )
+    }
+
+    /// Returns the component, either the entity's own or inherited from a
+    /// prefab.
+    ///
+    /// If `C` uses [`StorageType::Stable`], the component stays at the
+    /// returned address for as long as it's attached to the entity that owns
+    /// it, so a pointer derived from the reference may be kept after the
+    /// borrow ends, e.g. by native code. It may only be dereferenced while no
+    /// conflicting reference to the component exists. For other storage
+    /// types, the address can change whenever the entity or another entity in
+    /// the same table gains or loses components. Components in a
+    /// [custom storage](World::set_component_storage) stay where the storage
+    /// puts them.
+    pub fn get<C: Component>(&self) -> Option<&C> {
+        get_component(
+            self.location(),
+            &self.world.components,
+            &self.world.entities,
+            &self.world.archetypes,
+            &self.world.tables,
+        )
+    }
+
+    /// Like [`get`](Self::get), but fails with
+    /// [`EntityError::MissingComponent`] if the entity doesn't have the
+    /// component.
+    pub fn try_get<C: Component>(&self) -> Result<&C, EntityError> {
+        self.get::<C>()
+            .ok_or_else(|| missing_component::<C>(self.entity))
+    }
+
+    /// Returns the component mutably.
+    ///
+    /// If the component is inherited from a prefab, it's first cloned into
+    /// this entity, so that the prefab and its other instances are not
+    /// affected. If the inherited component can't be cloned, this returns
+    /// `None`.
+    ///
+    /// Immutable components can't be accessed mutably. Use
+    /// [`insert`](Self::insert) to replace them instead. See
+    /// [`get`](Self::get) for when the component's address can change.
+    pub fn get_mut<C: MutableComponent>(&mut self) -> Option<&mut C> {
+        match self.find_component::<C>() {
+            FoundComponent::Missing => return None,
+            FoundComponent::Owned => {}
+            FoundComponent::Inherited(owner_location) => {
+                let value = self.clone_inherited::<C>(owner_location)?;
+                self.insert(value);
+            }
+        }
+
+        get_component_mut(
+            self.location(),
+            &self.world.components,
+            &mut self.world.tables,
+        )
+    }
+
+    pub fn despawn(self) {
+        self.despawn_without_hooks().run_hooks();
+    }
+
+    /// Despawns the entity, but only queues its `on_remove` hooks. Returns the
+    /// world, so that the caller can run them.
+    fn despawn_without_hooks(self) -> &'a mut World {
+        let entity_location = self.location();
+        let world = self.world;
+
+        let archetype = world.archetypes.get_mut(entity_location.archetype_id);
+        for (hook, component_id) in on_remove_hooks(archetype, &world.components) {
+            world.pending_hooks.push(PendingHook {
+                hook,
+                entity: self.entity,
+                component_id,
+            });
+        }
+
+        world.indexes.remove_entity(
+            self.entity,
+            archetype.component_ids(),
+            world.tables.get(entity_location.table_id),
+            entity_location.table_row,
+        );
+
+        if let Some(changed_location) = archetype.remove_entity(entity_location.archetype_row) {
+            changed_location.apply(&mut world.entities);
+        }
+
+        let table = world.tables.get_mut(entity_location.table_id);
+        let swapped = unsafe {
+            // SAFETY: The row belongs to this entity, and is not accessed afterwards.
+            table.remove_row(entity_location.table_row)
+        };
+        if let Some(changed_location) = swapped {
+            changed_location.apply(&mut world.entities);
+            update_table_row(
+                changed_location.entity,
+                &world.entities,
+                &mut world.archetypes,
+            );
+        }
+
+        world.entities.free(self.entity);
+        world
+    }
+
+    pub fn insert(&mut self, bundle: impl DynamicBundle) -> &mut Self {
+        self.try_insert(bundle)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Inserts the components in `bundle`, replacing any the entity already
+    /// has.
+    ///
+    /// Fails if the bundle contains duplicate components.
+    pub fn try_insert(&mut self, bundle: impl DynamicBundle) -> Result<&mut Self, QuasarError> {
+        self.insert_remove_take_inner(InsertOp::replace(bundle))?;
+        Ok(self)
+    }
+
+    /// Inserts the components in `bundle`, and returns the values they
+    /// replaced. See [`Replaced`].
+    pub fn insert_replacing<B: Bundle>(&mut self, bundle: B) -> Replaced<B> {
+        self.try_insert_replacing(bundle)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like [`insert_replacing`](Self::insert_replacing), but fails if the
+    /// bundle contains duplicate components.
+    pub fn try_insert_replacing<B: Bundle>(
+        &mut self,
+        bundle: B,
+    ) -> Result<Replaced<B>, QuasarError> {
+        self.insert_remove_take_inner(InsertReplacingOp {
+            bundle,
+            taken: Cell::new(false),
+        })
+    }
+
+    /// Inserts the components in `bundle` that the entity doesn't have yet.
+    /// Components that the entity already has are kept.
+    pub fn insert_if_new(&mut self, bundle: impl DynamicBundle) -> &mut Self {
+        self.try_insert_if_new(bundle)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like [`insert_if_new`](Self::insert_if_new), but fails if the bundle
+    /// contains duplicate components.
+    pub fn try_insert_if_new(
+        &mut self,
+        bundle: impl DynamicBundle,
+    ) -> Result<&mut Self, QuasarError> {
+        self.insert_remove_take_inner(InsertOp::keep(bundle))?;
+        Ok(self)
+    }
+
+    /// Disables the entity, by inserting the [`Disabled`] marker. Queries skip
+    /// it, unless they mention [`Disabled`], e.g. with `Allow<Disabled>`. The
+    /// entity keeps its components.
+    pub fn disable(&mut self) -> &mut Self {
+        self.insert(Disabled)
+    }
+
+    /// Enables the entity again. See [`disable`](Self::disable).
+    pub fn enable(&mut self) -> &mut Self {
+        self.remove::<Disabled>();
+        self
+    }
+
+    /// Returns whether the entity is [disabled](Self::disable).
+    pub fn is_disabled(&self) -> bool {
+        self.owns::<Disabled>()
+    }
+
+    /// Disables the component `C`. See [`World::disable_component`].
+    pub fn disable_component<C: Component>(&mut self) -> &mut Self {
+        self.set_enabled::<C>(false)
+    }
+
+    /// Enables the component `C` again. See [`World::disable_component`].
+    pub fn enable_component<C: Component>(&mut self) -> &mut Self {
+        self.set_enabled::<C>(true)
+    }
+
+    fn set_enabled<C: Component>(&mut self, enabled: bool) -> &mut Self {
+        let entity_location = self.location();
+        if let Some(component_id) = self.world.components.get_component_id::<C>() {
+            self.world
+                .tables
+                .get_mut(entity_location.table_id)
+                .set_enabled(component_id, entity_location.table_row, enabled);
+        }
+        self
+    }
+
+    /// Returns whether the entity has the component `C` itself, and it's not
+    /// disabled.
+    pub fn is_component_enabled<C: Component>(&self) -> bool {
+        is_component_enabled::<C>(self.location(), &self.world.components, &self.world.tables)
+    }
+
+    /// Inserts a value of the shared component `C`, replacing the value the
+    /// entity had. Entities with equal values share a single copy of it. See
+    /// [`SharedComponent`].
+    pub fn insert_shared<C: SharedComponent>(&mut self, value: C) -> &mut Self {
+        let entity_location = self.location();
+        let world = &mut *self.world;
+        let shared_id = world.components.register::<Shared<C>>().id();
+        let value_id =
+            world
+                .shared
+                .get_or_insert(shared_id, value, &mut world.components, &world.archetypes);
+
+        if world
+            .archetypes
+            .get(entity_location.archetype_id)
+            .shared_value(shared_id)
+            == Some(value_id)
+        {
+            return self;
+        }
+
+        // the `AddBundle` edge replaces the value the entity had, if any.
+        self.insert_remove_take_inner(InsertSharedOp::<C> {
+            shared_id,
+            value_id,
+            _component: PhantomData,
+        })
+        .unwrap_or_else(|error| panic!("{error}"));
+        self
+    }
+
+    /// Removes the value of the shared component `C`. Does nothing if the
+    /// entity doesn't have one.
+    pub fn remove_shared<C: SharedComponent>(&mut self) {
+        self.remove::<Shared<C>>();
+    }
+
+    /// Returns the entity's value of the shared component `C`.
+    pub fn get_shared<C: SharedComponent>(&self) -> Option<&C> {
+        let shared_id = self.world.components.get_component_id::<Shared<C>>()?;
+        let value_id = self
+            .world
+            .archetypes
+            .get(self.location().archetype_id)
+            .shared_value(shared_id)?;
+        self.world.shared.get(shared_id, value_id)
+    }
+
+    /// Returns an entry for the component `C`, through which it can be
+    /// inspected, modified or inserted.
+    ///
+    /// A component inherited from a prefab is
+    /// [`Inherited`](ComponentEntry::Inherited). It's only cloned into this
+    /// entity when it's modified through the entry, like with
+    /// [`get_mut`](Self::get_mut).
+    pub fn entry<C: Component>(&mut self) -> ComponentEntry<'_, 'a, C> {
+        ComponentEntry::new(self)
+    }
+
+    /// Removes the components in `B`. Does nothing if the entity doesn't have
+    /// all of them.
+    pub fn remove<B: Bundle>(&mut self) {
+        match self.try_remove::<B>() {
+            Ok(()) | Err(QuasarError::Entity(EntityError::MissingComponents { .. })) => {}
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    /// Removes the components in `B`.
+    ///
+    /// Fails if the entity doesn't have all of them, or if the bundle contains
+    /// duplicate components.
+    pub fn try_remove<B: Bundle>(&mut self) -> Result<(), QuasarError> {
+        self.insert_remove_take_inner(RemoveOp::<B> {
+            _bundle: PhantomData,
+        })
+    }
+
+    /// Removes the components in `B` and returns them. Returns `None` if the
+    /// entity doesn't have all of them.
+    #[must_use]
+    pub fn take<B: Bundle>(&mut self) -> Option<B> {
+        match self.try_take::<B>() {
+            Ok(bundle) => Some(bundle),
+            Err(QuasarError::Entity(EntityError::MissingComponents { .. })) => None,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    /// Removes the components in `B` and returns them.
+    ///
+    /// Fails if the entity doesn't have all of them, or if the bundle contains
+    /// duplicate components.
+    pub fn try_take<B: Bundle>(&mut self) -> Result<B, QuasarError> {
+        self.insert_remove_take_inner(TakeOp::<B> {
+            _bundle: PhantomData,
+        })
+    }
+
+    /// Inserts clones of all components of this entity into `target`. Any
+    /// components `target` already has are replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` doesn't exist, or if this entity has a component that
+    /// can't be cloned and the [`UncloneablePolicy`] is
+    /// [`Error`](UncloneablePolicy::Error).
+    pub fn clone_components_to(&mut self, target: Entity) -> &mut Self {
+        if let Err(error) = self.try_clone_components_to(target) {
+            panic!("{error}");
+        }
+        self
+    }
+
+    /// Inserts clones of all components of this entity into `target`. Any
+    /// components `target` already has are replaced.
+    pub fn try_clone_components_to(&mut self, target: Entity) -> Result<(), QuasarError> {
+        let cloned = self.world.clone_components(self.entity)?;
+        let mut target = self.world.try_get_entity_world_mut(target)?;
+        target.insert_remove_take_inner(InsertClonedOp { cloned })?;
+
+        // moving `target` to another table might have moved us too.
+        self.update_location();
+
+        Ok(())
+    }
+
+    /// Finds out whether the entity has the component itself, or inherits
+    /// it. The prefab chain is only walked once.
+    fn find_component<C: Component>(&self) -> FoundComponent {
+        let Some(component_id) = self.world.components.get_component_id::<C>()
+        else {
+            return FoundComponent::Missing;
+        };
+        let entity_location = self.location();
+        match prefab::find_component_owner(
+            entity_location,
+            component_id,
+            &self.world.components,
+            &self.world.entities,
+            &self.world.archetypes,
+            &self.world.tables,
+        ) {
+            None => FoundComponent::Missing,
+            Some(owner_location) if owner_location == entity_location => FoundComponent::Owned,
+            Some(owner_location) => FoundComponent::Inherited(owner_location),
+        }
+    }
+
+    /// Clones the component `C` of the prefab at `owner_location`, or returns
+    /// `None` if it can't be cloned.
+    fn clone_inherited<C: Component>(&self, owner_location: EntityLocation) -> Option<C> {
+        let component_id = self.world.components.get_component_id::<C>()?;
+        let clone_fn = self
+            .world
+            .components
+            .get_component_info(component_id)
+            .descriptor()
+            .clone_fn()?;
+        let value = self
+            .world
+            .tables
+            .get(owner_location.table_id)
+            .get_component_ptr(component_id, owner_location.table_row)?;
+
+        let mut clone = MaybeUninit::<C>::uninit();
+        unsafe {
+            // SAFETY: `value` points to a `C`, and `clone_fn` belongs to `C`. It
+            // writes a valid `C` into `clone`.
+            clone_fn(value, PtrMut::new(NonNull::from(&mut clone).cast()));
+            Some(clone.assume_init())
+        }
+    }
+
+    /// Returns the location of the entity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a hook despawned the entity.
+    fn location(&self) -> EntityLocation {
+        assert!(
+            !self.entity_location.is_invalid(),
+            "Entity {:?} was despawned by a hook",
+            self.entity
+        );
+        self.entity_location
+    }
+
+    fn update_location(&mut self) {
+        // a hook might have despawned the entity, in which case only `id` and
+        // `is_despawned` can be used anymore.
+        self.entity_location = self
+            .world
+            .entities
+            .get_location(self.entity)
+            .unwrap_or(EntityLocation::INVALID);
+    }
+
+    /// Checks that the op doesn't insert values of unique components that
+    /// other entities already have. Depending on the [`UniquePolicy`], this
+    /// either fails before anything is changed, or removes the components from
+    /// the other entities.
+    fn resolve_unique_conflicts<O: InsertRemoveTakeOp>(
+        &mut self,
+        op: &O,
+        bundle_id: BundleId,
+    ) -> Result<(), QuasarError> {
+        let conflicts = op.unique_conflicts(
+            self.world.bundles.get_by_id(bundle_id),
+            self.world.archetypes.get(self.entity_location.archetype_id),
+            &self.world.indexes,
+            self.entity,
+        );
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(conflict) = conflicts
+            .iter()
+            .find(|conflict| conflict.policy == UniquePolicy::Error)
+        {
+            return Err(QuasarError::NotUnique {
+                component: self
+                    .world
+                    .components
+                    .get_component_info(conflict.component_id)
+                    .descriptor()
+                    .name(),
+                entity: conflict.entity,
+            });
+        }
+
+        for conflict in conflicts {
+            if let Some(mut other) = self.world.get_entity_world_mut(conflict.entity) {
+                other.insert_remove_take_inner(RemoveByIdOp {
+                    component_id: conflict.component_id,
+                })?;
+            }
+        }
+
+        // the `on_remove` hooks might have moved or despawned our entity.
+        self.update_location();
+        self.world.entities.check(self.entity)?;
+        Ok(())
+    }
+
+    /// Helper method to perform [`insert`], [`remove`] and [`take`].
+    ///
+    /// [`insert`], [`remove`] and [`take`] are very similar since they all move
+    /// an entity from one archetype to another, moving its data from one table
+    /// to another. More specifically, sometimes they don't actually need to
+    /// move between archetypes or tables (e.g. inserting `()`). All these
+    /// operations are done using this general method, and are specialized
+    /// via the `op` parameter and the [`InsertRemoveTakeOp`] trait.
+    fn insert_remove_take_inner<O: InsertRemoveTakeOp>(
+        &mut self,
+        op: O,
+    ) -> Result<O::Output, QuasarError> {
+        if self.entity_location.is_invalid() {
+            return Err(EntityError::Despawned(self.entity).into());
+        }
+
+        // if our entity is moved to another table, another entity might be swapped into
+        // its old row.
+        let mut table_row_changed = None;
+
+        // get info for this bundle
+        let bundle_id = op
+            .get_bundle_info(&mut self.world.bundles, &mut self.world.components)?
+            .id();
+        self.resolve_unique_conflicts(&op, bundle_id)?;
+        let bundle_info = self.world.bundles.get_by_id(bundle_id);
+        let op_hooks = op.hooks();
+
+        // add/remove bundle to the archetype graph. this creates an
+        // AddBundle/RemoveBundle edge from `self.entity_location.archetype_id`
+        // to whatever archetype we get after the insertion.
+        //
+        // this also creates the resulting archetype (and table) if necessary, by
+        // calling the provided closure.
+        //
+        // if the bundle is empty (e.g. adding `()`), or can't be removed, this
+        // returns `None`. if it does change the entity's archetype, this returns a
+        // mutable borrow for the old and new archetype. otherwise it returns the
+        // entity's current archetype.
+
+        let output = match op.get_bundle_edge(
+            &mut self.world.archetypes,
+            self.entity_location.archetype_id,
+            bundle_info,
+            |archetype_id, component_ids| {
+                create_archetype(
+                    archetype_id,
+                    component_ids,
+                    &self.world.components,
+                    &mut self.world.tables,
+                )
+            },
+        ) {
+            Some(Ok((from_archetype, to_archetype))) => {
+                queue_hooks(
+                    op_hooks,
+                    bundle_info,
+                    from_archetype,
+                    &self.world.components,
+                    self.entity,
+                    &mut self.world.pending_hooks,
+                );
+                unindex_components(
+                    bundle_info,
+                    &mut self.world.indexes,
+                    &self.world.tables,
+                    self.entity,
+                    self.entity_location,
+                );
+                let output;
+                (output, table_row_changed) = move_entity(
+                    op,
+                    bundle_info,
+                    from_archetype,
+                    to_archetype,
+                    &mut self.world.tables,
+                    &mut self.world.entities,
+                    self.entity,
+                );
+                output
+            }
+            Some(Err(archetype)) => {
+                queue_hooks(
+                    op_hooks,
+                    bundle_info,
+                    archetype,
+                    &self.world.components,
+                    self.entity,
+                    &mut self.world.pending_hooks,
+                );
+                unindex_components(
+                    bundle_info,
+                    &mut self.world.indexes,
+                    &self.world.tables,
+                    self.entity,
+                    self.entity_location,
+                );
+                write_in_place(
+                    op,
+                    bundle_info,
+                    archetype,
+                    &mut self.world.tables,
+                    self.entity_location,
+                )
+            }
+            None => {
+                // either the bundle is empty, or the entity doesn't have all of its
+                // components.
+                let archetype = self.world.archetypes.get(self.entity_location.archetype_id);
+                let missing = bundle_info
+                    .component_ids()
+                    .iter()
+                    .filter(|component_id| !archetype.contains_component(**component_id))
+                    .map(|component_id| {
+                        self.world
+                            .components
+                            .get_component_info(*component_id)
+                            .descriptor()
+                            .name()
+                    })
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    return Err(EntityError::MissingComponents {
+                        entity: self.entity,
+                        bundle: bundle_info.name(),
+                        components: missing,
+                    }
+                    .into());
+                }
+
+                let table = self.world.tables.get_mut(self.entity_location.table_id);
+                op.take(bundle_info, table, self.entity_location.table_row)
+            }
+        };
+
+        if let Some(entity) = table_row_changed {
+            update_table_row(entity, &self.world.entities, &mut self.world.archetypes);
+        }
+
+        index_components(
+            op_hooks,
+            bundle_info,
+            self.world.archetypes.get(self.entity_location.archetype_id),
+            &mut self.world.indexes,
+            &self.world.tables,
+            &self.world.entities,
+            self.entity,
+        );
+
+        self.world.run_hooks();
+
+        // update the cached `EntityLocation`
+        self.update_location();
+
+        Ok(output)
+    }
+
+    pub fn world(&self) -> &World {
+        self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.world
+    }
+
+    pub fn into_world_mut(self) -> &'a mut World {
+        self.world
+    }
+}
+
+/// The values of a bundle's components that
+/// [`EntityWorldMut::insert_replacing`] replaced.
+#[derive(Debug)]
+pub enum Replaced<B> {
+    /// The entity had none of the bundle's components.
+    None,
+    /// The entity had all of the bundle's components.
+    All(B),
+    /// The entity had only some of the bundle's components. These are their
+    /// old values, in the order in which the bundle lists them.
+    Partial(Vec<(ComponentId, Box<dyn Any>)>),
+}
+
+impl<B> Replaced<B> {
+    /// Returns the old values, if the entity had all of the bundle's
+    /// components.
+    pub fn all(self) -> Option<B> {
+        match self {
+            Self::All(bundle) => Some(bundle),
+            Self::None | Self::Partial(_) => None,
+        }
+    }
+}
+
+/// How an entity has a component. See [`EntityWorldMut::find_component`].
+enum FoundComponent {
+    /// The entity neither has nor inherits the component.
+    Missing,
+    /// The entity has the component itself.
+    Owned,
+    /// The entity inherits the component from the prefab at this location.
+    Inherited(EntityLocation),
+}
+
+/// Iterator returned by [`World::spawn_batch`].
+#[derive(Debug)]
+pub struct SpawnBatchIter<'w, I>
+where
+    I: Iterator,
+    I::Item: Bundle,
+{
+    world: &'w mut World,
+    bundles: I,
+    bundle_id: BundleId,
+    /// Archetype and table the entities are spawned into.
+    archetype_id: ArchetypeId,
+    table_id: TableId,
+    /// Entities that were allocated up-front, for the bundles that the
+    /// iterator's size hint promised.
+    reserved: std::vec::IntoIter<Entity>,
+    /// Whether the bundle contains unique components, so that the entities
+    /// have to be spawned one by one.
+    any_unique: bool,
+}
+
+impl<'w, I> SpawnBatchIter<'w, I>
+where
+    I: Iterator,
+    I::Item: Bundle,
+{
+    fn new(world: &'w mut World, bundles: I) -> Self {
+        let (additional, _) = bundles.size_hint();
+
+        let bundle_info = world
+            .bundles
+            .get_mut_or_insert_static::<I::Item>(&mut world.components)
+            .unwrap_or_else(|error| panic!("{error}"));
+        let bundle_id = bundle_info.id();
+
+        let (archetype_id, table_id) = match world.archetypes.add_bundle(
+            ArchetypeId::EMPTY,
+            bundle_info,
+            |archetype_id, component_ids| {
+                create_archetype(
+                    archetype_id,
+                    component_ids,
+                    &world.components,
+                    &mut world.tables,
+                )
+            },
+        ) {
+            Some(Ok((_, archetype))) => (archetype.id(), archetype.table_id()),
+            // the bundle is empty
+            Some(Err(_)) | None => (ArchetypeId::EMPTY, TableId::EMPTY),
+        };
+
+        world.archetypes.get_mut(archetype_id).reserve(additional);
+        world.tables.get_mut(table_id).reserve(additional);
+
+        // bundles with unique components are spawned one by one, see `next`. no hooks
+        // run until the iterator is dropped, so no index can be created in between.
+        let bundle_info = world.bundles.get_by_id(bundle_id);
+        let any_unique = world.indexes.any_unique(bundle_component_ids(bundle_info));
+        let reserved = if any_unique {
+            vec![]
+        }
+        else {
+            world.entities.allocate_many(additional)
+        };
+
+        Self {
+            world,
+            bundles,
+            bundle_id,
+            archetype_id,
+            table_id,
+            reserved: reserved.into_iter(),
+            any_unique,
+        }
+    }
+}
+
+impl<'w, I> Iterator for SpawnBatchIter<'w, I>
+where
+    I: Iterator,
+    I::Item: Bundle,
+{
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bundle = self.bundles.next()?;
+        if self.any_unique {
+            // values of unique components must be checked against the other entities.
+            // this runs the hooks right away, since resolving conflicts can run hooks
+            // too.
+            let entity = self
+                .world
+                .try_spawn(bundle)
+                .unwrap_or_else(|error| panic!("{error}"));
+            return Some(entity.id());
+        }
+        let bundle_info = self.world.bundles.get_by_id(self.bundle_id);
+
+        let entity = match self.reserved.next() {
+            Some(entity) => entity,
+            None => {
+                self.world
+                    .entities
+                    .allocate()
+                    .unwrap_or_else(|error| panic!("{error}"))
+            }
+        };
+
+        let mut insert_into_table = self.world.tables.get_mut(self.table_id).insert(entity);
+        Bundle::into_components(
+            bundle,
+            InsertComponentsIntoTable::new(
+                bundle_info,
+                |_component_id| true,
+                &mut insert_into_table,
+            ),
+        );
+        insert_required_components(
+            bundle_info,
+            self.world.archetypes.get(ArchetypeId::EMPTY),
+            &mut insert_into_table,
+        );
+        let table_row = insert_into_table.table_row();
+
+        let archetype_row = self
+            .world
+            .archetypes
+            .get_mut(self.archetype_id)
+            .insert_entity(ArchetypeEntity { entity, table_row });
+
+        self.world.entities.set_location(
+            entity,
+            EntityLocation {
+                archetype_id: self.archetype_id,
+                archetype_row,
+                table_id: self.table_id,
+                table_row,
+            },
+        );
+
+        queue_hooks(
+            OpHooks::Insert { replace: true },
+            bundle_info,
+            self.world.archetypes.get(ArchetypeId::EMPTY),
+            &self.world.components,
+            entity,
+            &mut self.world.pending_hooks,
+        );
+        index_components(
+            OpHooks::Insert { replace: true },
+            bundle_info,
+            self.world.archetypes.get(ArchetypeId::EMPTY),
+            &mut self.world.indexes,
+            &self.world.tables,
+            &self.world.entities,
+            entity,
+        );
+
+        // the hooks are queued until the iterator is dropped, so that they can't
+        // invalidate the archetype, table or the reserved entities.
+        Some(entity)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bundles.size_hint()
+    }
+}
+
+impl<'w, I> ExactSizeIterator for SpawnBatchIter<'w, I>
+where
+    I: ExactSizeIterator,
+    I::Item: Bundle,
+{
+}
+
+impl<'w, I> Drop for SpawnBatchIter<'w, I>
+where
+    I: Iterator,
+    I::Item: Bundle,
+{
+    fn drop(&mut self) {
+        // spawn the remaining entities
+        for _ in self.by_ref() {}
+
+        // the size hint promised more bundles than there were.
+        for entity in self.reserved.by_ref() {
+            self.world.entities.free(entity);
+        }
+
+        self.world.run_hooks();
+    }
+}
+
+pub struct EntityIter<'a> {
+    components: &'a Components,
+    entities: &'a Entities,
+    archetypes: &'a Archetypes,
+    tables: &'a Tables,
+    default_query_filters: &'a DefaultQueryFilters,
+    iter: EntitiesIter<'a>,
+}
+
+impl<'a> Iterator for EntityIter<'a> {
+    type Item = EntityRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entity, entity_location) = self.iter.find(|(_, entity_location)| {
+            self.default_query_filters.matches_archetype(
+                &Access::default(),
+                self.archetypes.get(entity_location.archetype_id),
+            )
+        })?;
+        Some(EntityRef {
+            components: self.components,
+            entities: self.entities,
+            archetypes: self.archetypes,
+            tables: self.tables,
+            entity,
+            entity_location,
+        })
+    }
+}
+
+fn owns_component<C: Component>(
+    entity_location: EntityLocation,
+    components: &Components,
+    archetypes: &Archetypes,
+) -> bool {
+    let Some(component_id) = components.get_component_id::<C>()
+    else {
+        return false;
+    };
+    let archetype = archetypes.get(entity_location.archetype_id);
+    archetype.contains_component(component_id)
+}
+
+fn contains_component<C: Component>(
+    entity_location: EntityLocation,
+    components: &Components,
+    entities: &Entities,
+    archetypes: &Archetypes,
+    tables: &Tables,
+) -> bool {
+    let Some(component_id) = components.get_component_id::<C>()
+    else {
+        return false;
+    };
+    prefab::find_component(
+        entity_location,
+        component_id,
+        components,
+        entities,
+        archetypes,
+        tables,
+    )
+    .is_some()
+}
+
+fn is_component_enabled<C: Component>(
+    entity_location: EntityLocation,
+    components: &Components,
+    tables: &Tables,
+) -> bool {
+    components
+        .get_component_id::<C>()
+        .is_some_and(|component_id| {
+            tables
+                .get(entity_location.table_id)
+                .is_enabled(component_id, entity_location.table_row)
+        })
+}
+
+fn get_component<'a, C: Component>(
+    entity_location: EntityLocation,
+    components: &Components,
+    entities: &Entities,
+    archetypes: &Archetypes,
+    tables: &'a Tables,
+) -> Option<&'a C> {
+    // components of all storage types are currently stored in tables.
+    let component_id = components.get_component_id::<C>()?;
+    let (table, table_row) = prefab::find_component(
+        entity_location,
+        component_id,
+        components,
+        entities,
+        archetypes,
+        tables,
+    )?;
+    unsafe {
+        // SAFETY: The type `C` is the type stored in the column with `component_id`.
+        table.get_component(component_id, table_row)
+    }
+}
+
+fn get_component_mut<'a, C: MutableComponent>(
+    entity_location: EntityLocation,
+    components: &Components,
+    tables: &'a mut Tables,
+) -> Option<&'a mut C> {
+    let component_id = components.get_component_id::<C>()?;
+    let table = tables.get_mut(entity_location.table_id);
+    unsafe {
+        // SAFETY: The type `C` is the type stored in the column with `component_id`.
+        table.get_component_mut(component_id, entity_location.table_row)
+    }
+}
+
+/// Like [`get_component_mut`], but only borrows the tables shared. The
+/// component can be mutated through the returned [`UnsafeCell`].
+fn get_component_unsafe<'a, C: MutableComponent>(
+    entity_location: EntityLocation,
+    components: &Components,
+    tables: &'a Tables,
+) -> Option<&'a UnsafeCell<C>> {
+    let component_id = components.get_component_id::<C>()?;
+    let table = tables.get(entity_location.table_id);
+    unsafe {
+        // SAFETY: The type `C` is the type stored in the column with `component_id`.
+        table.get_component_unsafe::<C>(component_id, entity_location.table_row)
+    }
+}
+
+fn missing_component<C: Component>(entity: Entity) -> EntityError {
+    EntityError::MissingComponent {
+        entity,
+        component: std::any::type_name::<C>(),
+    }
+}
+
+/// Moves an entity from `from_archetype` to `to_archetype` as part of `op`,
+/// moving its row to the other table if necessary.
+///
+/// Returns the output of `op`, and the entity that was swapped into the
+/// entity's old table row, if any. The latter's entry in its archetype must be
+/// fixed with [`update_table_row`] once the archetypes are no longer borrowed.
+fn move_entity<O: InsertRemoveTakeOp>(
+    op: O,
+    bundle_info: &BundleInfo,
+    from_archetype: &mut Archetype,
+    to_archetype: &mut Archetype,
+    tables: &mut Tables,
+    entities: &mut Entities,
+    entity: Entity,
+) -> (O::Output, Option<Entity>) {
+    let output;
+    let mut table_row_changed = None;
+
+    let entity_location = entities.get_location(entity).expect("entity doesn't exist");
+
+    // create a new location for our entity. we'll populate it as we get the
+    // information.
+    let mut new_entity_location = entity_location;
+    new_entity_location.archetype_id = to_archetype.id();
+    new_entity_location.table_id = to_archetype.table_id();
+
+    // `Table::get_mut_pair` either returns a pair of mutable borrows of tables for
+    // the supplied table IDs, if they're not identical, or a single
+    // mutable borrow for the table
+    match tables.get_mut_pair(from_archetype.table_id(), to_archetype.table_id()) {
+        Ok((from_table, to_table)) => {
+            // moving our entity actually involves moving from a table to another table.
+
+            // first take out anything we want to return
+            // note: if the op takes out anything it must make sure it's only components
+            // that are not moved to the new table, and those are forgotten when
+            // `from_table.move_row` handles them as unmatched.
+            output = op.take(bundle_info, from_table, entity_location.table_row);
+
+            // `Table::move_row` will move our entity's row from `from_table` to
+            // `to_table`, moving all the data in the columns.
+            // Note that this will only populate columns in
+            // `to_table` that exist in both tables. In our case
+            // we'll still need to add some components from the bundle.
+            //
+            // `Table::move_row` handily also returns a `InsertIntoTable`, with which we
+            // can insert the remaining components later.
+
+            let mut move_result = unsafe {
+                from_table.move_row(
+                    entity_location.table_row,
+                    to_table,
+                    entity,
+                    op.handle_unmatched(),
+                )
+            };
+
+            new_entity_location.table_row = move_result.to_row();
+
+            // while removing our entity from `from_table`, another row was swapped into
+            // its place. we need to update its information
+            if let Some(changed_location) = move_result.swapped {
+                changed_location.apply(entities);
+                table_row_changed = Some(changed_location.entity);
+            }
+
+            // insert the remaining components from the bundle
+            op.insert(bundle_info, &mut move_result.insert, from_archetype);
+        }
+        Err(table) => {
+            // both archetypes have the same table, so there's nothing to move.
+            // the bundle also can't add any components to the table, or remove
+            // any components from it.
+            output = op.take(bundle_info, table, entity_location.table_row);
+        }
+    };
+
+    // remove our entity from `from_archetype`. this might again involve updating
+    // metadata from another entity due to swapping.
+    if let Some(changed_location) = from_archetype.remove_entity(entity_location.archetype_row) {
+        changed_location.apply(entities);
+    }
+
+    // we can finally insert our entity into the new archetype
+    new_entity_location.archetype_row = to_archetype.insert_entity(ArchetypeEntity {
+        entity,
+        table_row: new_entity_location.table_row,
+    });
+
+    // update our entity's location metadata
+    ChangedLocation {
+        entity,
+        changed_value: new_entity_location,
+    }
+    .apply(entities);
+
+    (output, table_row_changed)
+}
+
+/// Like [`move_entity`], but moves a group of entities from the same
+/// archetype at once. The destination rows are reserved once, and the table
+/// rows are moved column by column.
+///
+/// Returns the entities that had another entity swapped into their table row.
+fn move_entities<O: InsertRemoveTakeOp>(
+    ops: Vec<(Entity, O)>,
+    bundle_info: &BundleInfo,
+    from_archetype: &mut Archetype,
+    to_archetype: &mut Archetype,
+    tables: &mut Tables,
+    entities: &mut Entities,
+) -> Vec<Entity> {
+    let mut table_row_changed = vec![];
+
+    // move the rows from the back of the table, so that the rows that are swapped
+    // into the place of moved rows are never moved themselves.
+    let mut ops = ops
+        .into_iter()
+        .map(|(entity, op)| {
+            let entity_location = entities.get_location(entity).expect("entity doesn't exist");
+            (entity_location.table_row, entity, op)
+        })
+        .collect::<Vec<_>>();
+    ops.sort_unstable_by(|(a, ..), (b, ..)| b.cmp(a));
+
+    let Some((_, _, first_op)) = ops.first()
+    else {
+        return table_row_changed;
+    };
+    let moved = ops.iter().map(|(_, entity, _)| *entity).collect::<Vec<_>>();
+
+    let new_table_rows =
+        match tables.get_mut_pair(from_archetype.table_id(), to_archetype.table_id()) {
+            Ok((from_table, to_table)) => {
+                // see `move_entity`. the outputs are dropped, since batches don't return
+                // them.
+                for (table_row, _, op) in &ops {
+                    op.take(bundle_info, from_table, *table_row);
+                }
+
+                let from_rows = ops
+                    .iter()
+                    .map(|(table_row, ..)| *table_row)
+                    .collect::<Vec<_>>();
+                let handle_unmatched = first_op.handle_unmatched();
+                let move_result =
+                    unsafe { from_table.move_rows(&from_rows, to_table, handle_unmatched) };
+                for changed_location in move_result.swapped {
+                    changed_location.apply(entities);
+                    table_row_changed.push(changed_location.entity);
+                }
+
+                // the moved rows are appended in order, so the remaining components are
+                // written in order too.
+                for ((_, _, op), table_row) in ops.drain(..).zip(&move_result.to_rows) {
+                    op.insert(
+                        bundle_info,
+                        &mut to_table.write_row(*table_row),
+                        from_archetype,
+                    );
+                }
+                move_result.to_rows
+            }
+            Err(table) => {
+                // both archetypes have the same table, so there's nothing to move.
+                ops.drain(..)
+                    .map(|(table_row, _, op)| {
+                        op.take(bundle_info, table, table_row);
+                        table_row
+                    })
+                    .collect()
+            }
+        };
+
+    to_archetype.reserve(moved.len());
+    for (entity, table_row) in moved.into_iter().zip(new_table_rows) {
+        let entity_location = entities.get_location(entity).expect("entity doesn't exist");
+        if let Some(changed_location) = from_archetype.remove_entity(entity_location.archetype_row)
+        {
+            changed_location.apply(entities);
+        }
+
+        let archetype_row = to_archetype.insert_entity(ArchetypeEntity { entity, table_row });
+        ChangedLocation {
+            entity,
+            changed_value: EntityLocation {
+                archetype_id: to_archetype.id(),
+                archetype_row,
+                table_id: to_archetype.table_id(),
+                table_row,
+            },
+        }
+        .apply(entities);
+    }
+
+    table_row_changed
+}
+
+/// Performs `op` for an entity whose archetype doesn't change. The bundle only
+/// contains components the entity already has, so they're replaced in place.
+fn write_in_place<O: InsertRemoveTakeOp>(
+    op: O,
+    bundle_info: &BundleInfo,
+    archetype: &Archetype,
+    tables: &mut Tables,
+    entity_location: EntityLocation,
+) -> O::Output {
+    let table = tables.get_mut(archetype.table_id());
+    let output = op.take(bundle_info, table, entity_location.table_row);
+    let mut insert_into_table = table.write_row(entity_location.table_row);
+    op.insert(bundle_info, &mut insert_into_table, archetype);
+    output
+}
+
+/// Inserts the components that are required by a bundle, but that the entity
+/// didn't have in `from_archetype`. Their constructors are called to create
+/// them.
+fn insert_required_components(
+    bundle_info: &BundleInfo,
+    from_archetype: &Archetype,
+    insert_into_table: &mut InsertIntoTable,
+) {
+    let Some(add_bundle) = from_archetype.get_add_bundle(bundle_info.id())
+    else {
+        return;
+    };
+
+    for &index in &add_bundle.required {
+        let (component_id, required_component) = &bundle_info.required_components()[index];
+        required_component.construct(&mut |value| {
+            unsafe {
+                // SAFETY: The destination archetype contains the required components that
+                // the entity didn't have, and the constructor creates a value of the
+                // component's type.
+                insert_into_table.write_column_ptr(*component_id, value);
+            }
+        });
+    }
+}
+
+/// Returns the ids of the components in a bundle, and of the components it
+/// requires.
+fn bundle_component_ids(bundle_info: &BundleInfo) -> impl Iterator<Item = ComponentId> + '_ {
+    bundle_info.component_ids().iter().copied().chain(
+        bundle_info
+            .required_components()
+            .iter()
+            .map(|(component_id, _)| *component_id),
+    )
+}
+
+/// Updates the table row that the archetype of `entity` stores for it, after
+/// another row was swapped into its place.
+fn update_table_row(entity: Entity, entities: &Entities, archetypes: &mut Archetypes) {
+    let entity_location = entities.get_location(entity).expect("entity doesn't exist");
+    archetypes
+        .get_mut(entity_location.archetype_id)
+        .set_table_row(entity_location.archetype_row, entity_location.table_row);
+}
+
+unsafe trait InsertRemoveTakeOp {
+    type Output;
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError>;
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>>;
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched;
+
+    fn hooks(&self) -> OpHooks;
+
+    /// Finds the values of unique components that the op would insert, but
+    /// that other entities already have.
+    fn unique_conflicts(
+        &self,
+        _bundle_info: &BundleInfo,
+        _from_archetype: &Archetype,
+        _indexes: &Indexes,
+        _entity: Entity,
+    ) -> Vec<UniqueConflict> {
+        vec![]
+    }
+
+    fn insert(
+        self,
+        bundle_info: &BundleInfo,
+        insert_into_table: &mut InsertIntoTable,
+        from_archetype: &Archetype,
+    );
+
+    fn take(
+        &self,
+        bundle_info: &BundleInfo,
+        table: &mut Table,
+        table_row: TableRow,
+    ) -> Self::Output;
+}
+
+/// Which hooks an [`InsertRemoveTakeOp`] triggers.
+#[derive(Clone, Copy, Debug)]
+enum OpHooks {
+    /// Components that the entity doesn't have trigger `on_add`. Components
+    /// that it has trigger `on_replace`, if they're replaced.
+    Insert { replace: bool },
+    /// The components trigger `on_remove`.
+    Remove,
+}
+
+/// A hook that was triggered, and runs once the operation that triggered it is
+/// complete.
+#[derive(Clone, Copy, Debug)]
+struct PendingHook {
+    hook: ComponentHook,
+    entity: Entity,
+    component_id: ComponentId,
+}
+
+/// Queues the hooks triggered by applying a bundle to an entity in
+/// `from_archetype`. Inserting a bundle also triggers `on_add` for required
+/// components that the entity didn't have.
+fn queue_hooks(
+    op_hooks: OpHooks,
+    bundle_info: &BundleInfo,
+    from_archetype: &Archetype,
+    components: &Components,
+    entity: Entity,
+    pending_hooks: &mut Vec<PendingHook>,
+) {
+    for &component_id in bundle_info.component_ids() {
+        let hooks = components
+            .get_component_info(component_id)
+            .descriptor()
+            .hooks();
+        let hook = match op_hooks {
+            OpHooks::Insert { replace } => {
+                if !from_archetype.contains_component(component_id) {
+                    hooks.on_add
+                }
+                else if replace {
+                    hooks.on_replace
+                }
+                else {
+                    None
+                }
+            }
+            OpHooks::Remove => hooks.on_remove,
+        };
+        if let Some(hook) = hook {
+            pending_hooks.push(PendingHook {
+                hook,
+                entity,
+                component_id,
+            });
+        }
+    }
+
+    if let OpHooks::Insert { .. } = op_hooks {
+        for (component_id, _) in bundle_info.required_components() {
+            if from_archetype.contains_component(*component_id) {
+                continue;
+            }
+            let hook = components
+                .get_component_info(*component_id)
+                .descriptor()
+                .hooks()
+                .on_add;
+            if let Some(hook) = hook {
+                pending_hooks.push(PendingHook {
+                    hook,
+                    entity,
+                    component_id: *component_id,
+                });
+            }
+        }
+    }
+}
+
+/// Returns the `on_remove` hooks of the components in an archetype.
+fn on_remove_hooks(
+    archetype: &Archetype,
+    components: &Components,
+) -> Vec<(ComponentHook, ComponentId)> {
+    archetype
+        .component_ids()
+        .filter_map(|component_id| {
+            let hook = components
+                .get_component_info(component_id)
+                .descriptor()
+                .hooks()
+                .on_remove?;
+            Some((hook, component_id))
+        })
+        .collect()
+}
+
+/// Removes the values of the bundle's components that the entity has from
+/// their indexes, before they're replaced or removed.
+fn unindex_components(
+    bundle_info: &BundleInfo,
+    indexes: &mut Indexes,
+    tables: &Tables,
+    entity: Entity,
+    entity_location: EntityLocation,
+) {
+    indexes.remove_entity(
+        entity,
+        bundle_info.component_ids().iter().copied(),
+        tables.get(entity_location.table_id),
+        entity_location.table_row,
+    );
+}
+
+/// Adds the values of the bundle's components and the required components
+/// that were inserted along with it to their indexes, after the bundle was
+/// inserted into an entity in `from_archetype`.
+fn index_components(
+    op_hooks: OpHooks,
+    bundle_info: &BundleInfo,
+    from_archetype: &Archetype,
+    indexes: &mut Indexes,
+    tables: &Tables,
+    entities: &Entities,
+    entity: Entity,
+) {
+    let OpHooks::Insert { .. } = op_hooks
+    else {
+        return;
+    };
+    let entity_location = entities.get_location(entity).expect("entity doesn't exist");
+    // required components that the entity already had were kept, and are still
+    // indexed.
+    let required = from_archetype
+        .get_add_bundle(bundle_info.id())
+        .map_or(&[][..], |add_bundle| &add_bundle.required);
+    let component_ids = bundle_info.component_ids().iter().copied().chain(
+        required
+            .iter()
+            .map(|index| bundle_info.required_components()[*index].0),
+    );
+    indexes.insert_entity(
+        entity,
+        component_ids,
+        tables.get(entity_location.table_id),
+        entity_location.table_row,
+    );
+}
+
+/// A value of a unique component that an op would insert, but that another
+/// entity already has.
+#[derive(Clone, Copy, Debug)]
+struct UniqueConflict {
+    component_id: ComponentId,
+    /// The entity that already has the value.
+    entity: Entity,
+    policy: UniquePolicy,
+}
+
+/// Collects the [`UniqueConflict`]s of the components of a bundle.
+struct FindUniqueConflicts<'a, F> {
+    component_ids: std::slice::Iter<'a, ComponentId>,
+    /// Whether the op inserts a component.
+    filter: F,
+    indexes: &'a Indexes,
+    entity: Entity,
+    conflicts: Vec<UniqueConflict>,
+}
+
+impl<'a, F> FindUniqueConflicts<'a, F> {
+    fn new(bundle_info: &'a BundleInfo, filter: F, indexes: &'a Indexes, entity: Entity) -> Self {
+        Self {
+            component_ids: bundle_info.component_ids().iter(),
+            filter,
+            indexes,
+            entity,
+            conflicts: vec![],
+        }
+    }
+}
+
+impl<F> ComponentRefsCallback for FindUniqueConflicts<'_, F>
+where
+    F: Fn(ComponentId) -> bool,
+{
+    fn call<C: Component>(&mut self, component: &C) {
+        let component_id = *self
+            .component_ids
+            .next()
+            .expect("not enough component ids from bundle info");
+
+        if (self.filter)(component_id) {
+            let conflict = unsafe {
+                // SAFETY: The implementor of the Bundle trait must ensure that they only call
+                // this callback with components of the correct type.
+                self.indexes
+                    .find_conflict(component_id, Ptr::from(component), self.entity)
+            };
+            if let Some((entity, policy)) = conflict {
+                self.conflicts.push(UniqueConflict {
+                    component_id,
+                    entity,
+                    policy,
+                });
+            }
+        }
+    }
+}
+
+/// Finds the conflicts of the unique required components that an insert would
+/// add along with the bundle, i.e. the ones that the entity doesn't have yet.
+fn required_unique_conflicts(
+    bundle_info: &BundleInfo,
+    from_archetype: &Archetype,
+    indexes: &Indexes,
+    entity: Entity,
+    conflicts: &mut Vec<UniqueConflict>,
+) {
+    for (component_id, required_component) in bundle_info.required_components() {
+        let component_id = *component_id;
+        if from_archetype.contains_component(component_id)
+            || !indexes.any_unique(std::iter::once(component_id))
+        {
+            continue;
+        }
+        required_component.inspect(&mut |value| {
+            let conflict = unsafe {
+                // SAFETY: The constructor creates a value of the component's type.
+                indexes.find_conflict(component_id, value, entity)
+            };
+            if let Some((other, policy)) = conflict {
+                conflicts.push(UniqueConflict {
+                    component_id,
+                    entity: other,
+                    policy,
+                });
+            }
+        });
+    }
+}
+
+struct InsertOp<B> {
+    bundle: B,
+    /// Whether components that the entity already has are replaced. Otherwise
+    /// the ones from the bundle are dropped.
+    replace: bool,
+}
+
+impl<B> InsertOp<B> {
+    fn replace(bundle: B) -> Self {
+        Self {
+            bundle,
+            replace: true,
+        }
+    }
+
+    fn keep(bundle: B) -> Self {
+        Self {
+            bundle,
+            replace: false,
+        }
+    }
+}
+
+unsafe impl<B: DynamicBundle> InsertRemoveTakeOp for InsertOp<B> {
+    type Output = ();
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        bundles
+            .get_mut_or_insert_dynamic(&self.bundle, components)
+            .map(|bundle_info| &*bundle_info)
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.add_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        MoveRowPanicUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Insert {
+            replace: self.replace,
+        }
+    }
+
+    fn unique_conflicts(
+        &self,
+        bundle_info: &BundleInfo,
+        from_archetype: &Archetype,
+        indexes: &Indexes,
+        entity: Entity,
+    ) -> Vec<UniqueConflict> {
+        let mut conflicts = vec![];
+        if indexes.any_unique(bundle_info.component_ids().iter().copied()) {
+            // components that the entity already has are not inserted, unless we replace
+            // them.
+            let mut find_conflicts = FindUniqueConflicts::new(
+                bundle_info,
+                |component_id| self.replace || !from_archetype.contains_component(component_id),
+                indexes,
+                entity,
+            );
+            self.bundle.component_refs(&mut find_conflicts);
+            conflicts = find_conflicts.conflicts;
+        }
+        required_unique_conflicts(bundle_info, from_archetype, indexes, entity, &mut conflicts);
+        conflicts
+    }
+
+    fn insert(
+        self,
+        bundle_info: &BundleInfo,
+        insert_into_table: &mut InsertIntoTable,
+        from_archetype: &Archetype,
+    ) {
+        // insert the components from the bundle. components that the entity already
+        // had were moved over from `from_table`, and will be replaced, unless we keep
+        // them.
+        let duplicate = if self.replace {
+            None
+        }
+        else {
+            from_archetype
+                .get_add_bundle(bundle_info.id())
+                .map(|add_bundle| &add_bundle.duplicate)
+        };
+        self.bundle.into_components(InsertComponentsIntoTable::new(
+            bundle_info,
+            |component_id| duplicate.is_none_or(|duplicate| !duplicate.contains(&component_id)),
+            insert_into_table,
+        ));
+        insert_required_components(bundle_info, from_archetype, insert_into_table);
+    }
+
+    fn take(
+        &self,
+        _bundle_info: &BundleInfo,
+        _table: &mut Table,
+        _table_row: TableRow,
+    ) -> Self::Output {
+        ()
+    }
+}
+
+struct InsertReplacingOp<B> {
+    bundle: B,
+    /// Whether the old values were taken out of the entity's row, in which case
+    /// they must not be dropped when they're replaced.
+    taken: Cell<bool>,
+}
+
+unsafe impl<B: Bundle> InsertRemoveTakeOp for InsertReplacingOp<B> {
+    type Output = Replaced<B>;
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        bundles
+            .get_mut_or_insert_static::<B>(components)
+            .map(|bundle_info| &*bundle_info)
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.add_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        MoveRowPanicUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Insert { replace: true }
+    }
+
+    fn unique_conflicts(
+        &self,
+        bundle_info: &BundleInfo,
+        from_archetype: &Archetype,
+        indexes: &Indexes,
+        entity: Entity,
+    ) -> Vec<UniqueConflict> {
+        let mut conflicts = vec![];
+        if indexes.any_unique(bundle_info.component_ids().iter().copied()) {
+            let mut find_conflicts =
+                FindUniqueConflicts::new(bundle_info, |_component_id| true, indexes, entity);
+            self.bundle.component_refs(&mut find_conflicts);
+            conflicts = find_conflicts.conflicts;
+        }
+        required_unique_conflicts(bundle_info, from_archetype, indexes, entity, &mut conflicts);
+        conflicts
+    }
+
+    fn insert(
+        self,
+        bundle_info: &BundleInfo,
+        insert_into_table: &mut InsertIntoTable,
+        from_archetype: &Archetype,
+    ) {
+        insert_required_components(bundle_info, from_archetype, insert_into_table);
+        if self.taken.get() {
+            self.bundle.into_components(unsafe {
+                // SAFETY: `take` moved all components of the bundle out of the entity's
+                // row, and they were moved over from `from_table` as is.
+                InsertComponentsIntoTable::new_taken(
+                    bundle_info,
+                    |_component_id| true,
+                    insert_into_table,
+                )
+            });
+        }
+        else {
+            self.bundle.into_components(InsertComponentsIntoTable::new(
+                bundle_info,
+                |_component_id| true,
+                insert_into_table,
+            ));
+        }
+    }
+
+    fn take(
+        &self,
+        bundle_info: &BundleInfo,
+        table: &mut Table,
+        table_row: TableRow,
+    ) -> Self::Output {
+        let num_existing = bundle_info
+            .component_ids()
+            .iter()
+            .filter(|component_id| table.has_component(**component_id))
+            .count();
+        if num_existing == 0 {
+            return Replaced::None;
+        }
+
+        // the old values are moved out, so they must not be dropped when the new ones
+        // are written.
+        self.taken.set(true);
+        if num_existing == bundle_info.component_ids().len() {
+            Replaced::All(B::from_components(TakeComponentsFromTable::new(
+                bundle_info,
+                table,
+                table_row,
+            )))
+        }
+        else {
+            let mut take = TakeSomeComponentsFromTable::new(bundle_info, table, table_row);
+            B::component_types(&mut take);
+            Replaced::Partial(take.into_taken())
+        }
+    }
+}
+
+struct InsertClonedOp {
+    cloned: ClonedComponents,
+}
+
+unsafe impl InsertRemoveTakeOp for InsertClonedOp {
+    type Output = ();
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        Ok(bundles.get_mut_or_insert_by_component_ids(self.cloned.component_ids(), components))
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.add_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        // the value a shared component had before is replaced.
+        MoveRowDropUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Insert { replace: true }
+    }
+
+    fn unique_conflicts(
+        &self,
+        bundle_info: &BundleInfo,
+        from_archetype: &Archetype,
+        indexes: &Indexes,
+        entity: Entity,
+    ) -> Vec<UniqueConflict> {
+        let mut conflicts = vec![];
+        if indexes.any_unique(self.cloned.component_ids().iter().copied()) {
+            conflicts.extend(self.cloned.iter().filter_map(|(component_id, value)| {
+                let (other, policy) = unsafe {
+                    // SAFETY: The value is a clone of the component with `component_id`.
+                    indexes.find_conflict(component_id, value, entity)?
+                };
+                Some(UniqueConflict {
+                    component_id,
+                    entity: other,
+                    policy,
+                })
+            }));
+        }
+        required_unique_conflicts(bundle_info, from_archetype, indexes, entity, &mut conflicts);
+        conflicts
+    }
+
+    fn insert(
+        self,
+        bundle_info: &BundleInfo,
+        insert_into_table: &mut InsertIntoTable,
+        from_archetype: &Archetype,
+    ) {
+        unsafe {
+            // SAFETY: The destination archetype contains all components of the bundle,
+            // so its table has columns for them.
+            self.cloned.write(insert_into_table);
+        }
+        insert_required_components(bundle_info, from_archetype, insert_into_table);
+    }
+
+    fn take(
+        &self,
+        _bundle_info: &BundleInfo,
+        _table: &mut Table,
+        _table_row: TableRow,
+    ) -> Self::Output {
+    }
+}
+
+struct RemoveOp<B> {
+    _bundle: PhantomData<B>,
+}
+
+unsafe impl<B: Bundle> InsertRemoveTakeOp for RemoveOp<B> {
+    type Output = ();
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        bundles
+            .get_mut_or_insert_static::<B>(components)
+            .map(|bundle_info| &*bundle_info)
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.remove_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        MoveRowDropUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Remove
+    }
+
+    fn insert(
+        self,
+        _bundle_info: &BundleInfo,
+        _insert_into_table: &mut InsertIntoTable,
+        _from_archetype: &Archetype,
+    ) {
+    }
+
+    fn take(
+        &self,
+        _bundle_info: &BundleInfo,
+        _table: &mut Table,
+        _table_row: TableRow,
+    ) -> Self::Output {
+        ()
+    }
+}
+
+/// Removes a single component, e.g. to resolve a [`UniqueConflict`].
+struct RemoveByIdOp {
+    component_id: ComponentId,
+}
+
+unsafe impl InsertRemoveTakeOp for RemoveByIdOp {
+    type Output = ();
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        Ok(bundles.get_mut_or_insert_by_component_ids(&[self.component_id], components))
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.remove_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        MoveRowDropUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Remove
+    }
+
+    fn insert(
+        self,
+        _bundle_info: &BundleInfo,
+        _insert_into_table: &mut InsertIntoTable,
+        _from_archetype: &Archetype,
+    ) {
+    }
+
+    fn take(
+        &self,
+        _bundle_info: &BundleInfo,
+        _table: &mut Table,
+        _table_row: TableRow,
+    ) -> Self::Output {
+    }
+}
+
+/// Inserts the [`Shared<C>`] marker, and the component that represents a
+/// value of `C`.
+struct InsertSharedOp<C> {
+    shared_id: ComponentId,
+    value_id: ComponentId,
+    _component: PhantomData<C>,
+}
+
+unsafe impl<C: SharedComponent> InsertRemoveTakeOp for InsertSharedOp<C> {
+    type Output = ();
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        // the value was registered after the marker, so the ids are sorted.
+        Ok(
+            bundles
+                .get_mut_or_insert_by_component_ids(&[self.shared_id, self.value_id], components),
+        )
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.add_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        // the value the entity had before is replaced.
+        MoveRowDropUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Insert { replace: true }
+    }
+
+    fn insert(
+        self,
+        _bundle_info: &BundleInfo,
+        insert_into_table: &mut InsertIntoTable,
+        _from_archetype: &Archetype,
+    ) {
+        unsafe {
+            // SAFETY: Both the marker and the value are stored as `Shared<C>`.
+            insert_into_table.write_column(self.shared_id, Shared::<C>::new());
+            insert_into_table.write_column(self.value_id, Shared::<C>::new());
+        }
+    }
+
+    fn take(
+        &self,
+        _bundle_info: &BundleInfo,
+        _table: &mut Table,
+        _table_row: TableRow,
+    ) -> Self::Output {
+    }
+}
+
+struct TakeOp<B> {
+    _bundle: PhantomData<B>,
+}
+
+unsafe impl<B: Bundle> InsertRemoveTakeOp for TakeOp<B> {
+    type Output = B;
+
+    fn get_bundle_info<'a>(
+        &self,
+        bundles: &'a mut Bundles,
+        components: &mut Components,
+    ) -> Result<&'a BundleInfo, BundleError> {
+        bundles
+            .get_mut_or_insert_static::<B>(components)
+            .map(|bundle_info| &*bundle_info)
+    }
+
+    fn get_bundle_edge<'a>(
+        &self,
+        archetypes: &'a mut Archetypes,
+        archetype_id: ArchetypeId,
+        bundle_info: &BundleInfo,
+        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
+    ) -> Option<ArchetypePair<'a>> {
+        archetypes.remove_bundle(archetype_id, bundle_info, create_archetype)
+    }
+
+    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
+        MoveRowForgetUnmatched
+    }
+
+    fn hooks(&self) -> OpHooks {
+        OpHooks::Remove
+    }
+
+    fn insert(
+        self,
+        _bundle_info: &BundleInfo,
+        _insert_into_table: &mut InsertIntoTable,
+        _from_archetype: &Archetype,
+    ) {
+    }
+
+    fn take(
+        &self,
+        bundle_info: &BundleInfo,
+        table: &mut Table,
+        table_row: TableRow,
+    ) -> Self::Output {
+        B::from_components(TakeComponentsFromTable::new(bundle_info, table, table_row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::TypeId,
+        cell::{
+            Cell,
+            UnsafeCell,
+        },
+        collections::HashMap,
+        ptr::NonNull,
+        sync::atomic::{
+            AtomicBool,
+            AtomicUsize,
+            Ordering,
+        },
+    };
+
+    use quasar_ecs_derive::Component;
+
+    use crate::{
+        entity::EntityGeneration,
+        Allow,
+        BundleError,
+        ComponentEntry,
+        ComponentId,
+        ComponentStorage,
+        Disabled,
+        Entity,
+        EntityError,
+        IsA,
+        Prefab,
+        QuasarError,
+        QueryFilter,
+        Replaced,
+        Shared,
+        SharedComponent,
+        TableLayout,
+        Tick,
+        UncloneablePolicy,
+        UniquePolicy,
+        With,
+        Without,
+        World,
+    };
+
+    #[test]
+    fn spawn_component() {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        struct MyComponent {
+            position: u32,
+            velocity: u32,
+        }
+
+        let mut world = World::new();
+
+        let component = MyComponent {
+            position: 42,
+            velocity: 1312,
+        };
+        let entity = world.spawn(component);
+
+        // test if we can access the component from the returned `EntityWorldMut`
+        let component2 = entity.get::<MyComponent>().unwrap();
+        assert_eq!(component, *component2);
+
+        // test if we can access the entity and component from the world
+        let entity = entity.id();
+        let entity = world.get_entity(entity).unwrap();
+        let component2 = entity.get::<MyComponent>().unwrap();
+        assert_eq!(component, *component2);
+    }
+
+    #[test]
+    fn spawn_empty() {
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+        world.get_entity(entity).unwrap();
+    }
+
+    #[test]
+    fn remove_component() {
+        #[derive(Component)]
+        struct MyComponent;
+
+        let mut world = World::new();
+        let mut entity = world.spawn(MyComponent);
+        entity.remove::<MyComponent>();
+
+        assert!(entity.get::<MyComponent>().is_none());
+    }
+
+    #[test]
+    fn it_doesnt_drop_inserted_components() {
+        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Component)]
+        struct MyComponent;
+
+        impl Drop for MyComponent {
+            fn drop(&mut self) {
+                WAS_DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::new();
+        let _ = world.spawn(MyComponent);
+
+        assert!(!WAS_DROPPED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn it_doesnt_drop_taken_components() {
+        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Component)]
+        struct MyComponent;
+
+        impl Drop for MyComponent {
+            fn drop(&mut self) {
+                WAS_DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::new();
+        let mut entity = world.spawn(MyComponent);
+        let _component = entity.take::<MyComponent>().unwrap();
+
+        assert!(!WAS_DROPPED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn it_does_drop_components_when_world_is_dropped() {
+        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Component)]
+        struct MyComponent;
+
+        impl Drop for MyComponent {
+            fn drop(&mut self) {
+                WAS_DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::new();
+        let _ = world.spawn(MyComponent);
+        drop(world);
+
+        assert!(WAS_DROPPED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn it_does_drop_components_when_theyre_removed() {
+        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Component)]
+        struct MyComponent;
+
+        impl Drop for MyComponent {
+            fn drop(&mut self) {
+                WAS_DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::new();
+        let mut entity = world.spawn(MyComponent);
+        entity.remove::<MyComponent>();
+
+        assert!(WAS_DROPPED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn take_component() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct MyComponent(u32);
+
+        let mut world = World::new();
+        let component = MyComponent(1312);
+        let mut entity = world.spawn(component);
+
+        let component2 = entity.take::<MyComponent>().unwrap();
+        assert_eq!(component, component2);
+    }
+
+    #[test]
+    fn taking_a_component_twice_fails() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct MyComponent(u32);
+
+        let mut world = World::new();
+        let component = MyComponent(1312);
+        let mut entity = world.spawn(component);
+
+        let component2 = entity.take::<MyComponent>().unwrap();
+        assert_eq!(component, component2);
+
+        assert!(entity.take::<MyComponent>().is_none());
+    }
+
+    #[test]
+    fn inserting_replaces_existing_component() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct MyComponent(u32);
+
+        let mut world = World::new();
+        let mut entity = world.spawn(MyComponent(1));
+        entity.insert(MyComponent(2));
+
+        assert_eq!(*entity.get::<MyComponent>().unwrap(), MyComponent(2));
+    }
+
+    #[test]
+    fn clone_entity() {
+        #[derive(Clone, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Name(String);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Health(u32);
+
+        let mut world = World::new();
+        world.register_cloneable_component::<Health>();
+
+        let mut entity = world.spawn(Name("Bob".to_owned()));
+        entity.insert(Health(42));
+        let entity = entity.id();
+
+        let clone = world.clone_entity(entity);
+        assert_ne!(entity, clone);
+
+        let clone = world.get_entity(clone).unwrap();
+        assert_eq!(clone.get::<Name>().unwrap(), &Name("Bob".to_owned()));
+        assert_eq!(clone.get::<Health>().unwrap(), &Health(42));
+
+        let entity = world.get_entity(entity).unwrap();
+        assert_eq!(entity.get::<Name>().unwrap(), &Name("Bob".to_owned()));
+    }
+
+    #[test]
+    fn clone_entity_with_uncloneable_component() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Cloneable(u32);
+
+        #[derive(Component)]
+        struct Uncloneable;
+
+        let mut world = World::new();
+        let mut entity = world.spawn(Cloneable(1312));
+        entity.insert(Uncloneable);
+        let entity = entity.id();
+
+        assert!(matches!(
+            world.try_clone_entity(entity),
+            Err(QuasarError::Uncloneable { .. })
+        ));
+
+        world.set_uncloneable_policy(UncloneablePolicy::Skip);
+        let clone = world.clone_entity(entity);
+        let clone = world.get_entity(clone).unwrap();
+        assert_eq!(clone.get::<Cloneable>().unwrap(), &Cloneable(1312));
+        assert!(!clone.contains::<Uncloneable>());
+    }
+
+    #[test]
+    fn clone_components_to_replaces_existing_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let target = world.spawn(A(1)).id();
+
+        let mut source = world.spawn(A(2));
+        source.insert(B(3));
+        source.clone_components_to(target);
+        assert_eq!(source.get::<A>().unwrap(), &A(2));
+
+        let target = world.get_entity(target).unwrap();
+        assert_eq!(target.get::<A>().unwrap(), &A(2));
+        assert_eq!(target.get::<B>().unwrap(), &B(3));
+    }
+
+    #[test]
+    fn query_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let e1 = world.spawn(A(1)).id();
+        let mut e2 = world.spawn(A(2));
+        e2.insert(B(3));
+        let e2 = e2.id();
+        world.spawn(B(4));
+
+        let mut found = world
+            .query::<(Entity, &A, Option<&B>)>()
+            .map(|(entity, a, b)| (entity, *a, b.copied()))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(_, a, _)| a.0);
+        assert_eq!(found, [(e1, A(1), None), (e2, A(2), Some(B(3)))]);
+
+        let found = world
+            .query_filtered::<Entity, (With<A>, Without<B>)>()
+            .collect::<Vec<_>>();
+        assert_eq!(found, [e1]);
+
+        for (a, b) in world.query_mut::<(&mut A, &B)>() {
+            a.0 += b.0;
+        }
+        assert_eq!(world.get_entity(e2).unwrap().get::<A>().unwrap(), &A(5));
+    }
+
+    #[test]
+    #[should_panic]
+    fn query_conflicting_access_panics() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        world.spawn(A(1));
+        let _ = world.query_mut::<(&mut A, &A)>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn query_inherited_and_mutable_is_a_panics() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let prefab = world.spawn_prefab(A(1)).id();
+        world.spawn_instance(prefab);
+        let _ = world.query_mut::<(&mut IsA, &A)>();
+    }
+
+    #[test]
+    fn instances_inherit_prefab_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Config(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Health(u32);
+
+        let mut world = World::new();
+        let prefab = world.spawn_prefab(Config(42)).id();
+
+        let mut instance = world.spawn_instance(prefab);
+        instance.insert(Health(100));
+        assert!(instance.contains::<Config>());
+        assert!(!instance.owns::<Config>());
+        assert_eq!(instance.get::<Config>().unwrap(), &Config(42));
+        assert!(!instance.contains::<Prefab>());
+        let instance = instance.id();
+
+        // neither are they iterated over.
+        let found = world
+            .iter_entities()
+            .map(|entity| entity.id())
+            .collect::<Vec<_>>();
+        assert_eq!(found, [instance]);
+
+        // prefabs are not matched by queries, unless they ask for them.
+        let found = world.query::<(Entity, &Config)>().collect::<Vec<_>>();
+        assert_eq!(found, [(instance, &Config(42))]);
+        let found = world
+            .query_filtered::<Entity, With<Prefab>>()
+            .collect::<Vec<_>>();
+        assert_eq!(found, [prefab]);
+    }
+
+    #[test]
+    fn inherited_components_are_copied_on_write() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Config(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Uncloneable(u32);
+
+        let mut world = World::new();
+        let mut prefab = world.spawn_prefab(Config(1));
+        prefab.insert(Uncloneable(2));
+        let prefab = prefab.id();
+        let other = world.spawn_instance(prefab).id();
+
+        let mut instance = world.spawn_instance(prefab);
+        instance.get_mut::<Config>().unwrap().0 = 10;
+        assert!(instance.owns::<Config>());
+        assert_eq!(instance.get::<Config>().unwrap(), &Config(10));
+        assert!(instance.get_mut::<Uncloneable>().is_none());
+        assert_eq!(instance.get::<Uncloneable>().unwrap(), &Uncloneable(2));
+
+        // an uncloneable component can only be overridden through its entry.
+        let ComponentEntry::Inherited(inherited_entry) = instance.entry::<Uncloneable>()
+        else {
+            panic!("expected an inherited entry");
+        };
+        assert_eq!(inherited_entry.get(), &Uncloneable(2));
+        let (old_value, occupied_entry) = instance.entry::<Uncloneable>().insert(Uncloneable(20));
+        assert_eq!(old_value, None);
+        assert_eq!(occupied_entry.get(), &Uncloneable(20));
+        assert!(instance.owns::<Uncloneable>());
+
+        let prefab = world.get_entity(prefab).unwrap();
+        assert_eq!(prefab.get::<Uncloneable>().unwrap(), &Uncloneable(2));
+        assert_eq!(prefab.get::<Config>().unwrap(), &Config(1));
+        let other = world.get_entity(other).unwrap();
+        assert_eq!(other.get::<Config>().unwrap(), &Config(1));
+    }
+
+    #[test]
+    fn nested_prefabs() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let base = world.spawn_prefab(A(1)).id();
+        let mut derived = world.spawn_instance(base);
+        derived.insert(B(2)).insert(Prefab);
+        let derived = derived.id();
+
+        let instance = world.spawn_instance(derived).id();
+        let found = world.query::<(Entity, &A, &B)>().collect::<Vec<_>>();
+        assert_eq!(found, [(instance, &A(1), &B(2))]);
+    }
+
+    #[test]
+    fn spawn_batch() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        world.spawn(A(0));
+
+        let entities = world.spawn_batch((1..=100).map(A)).collect::<Vec<_>>();
+        assert_eq!(entities.len(), 100);
+        for (i, entity) in entities.iter().enumerate() {
+            let entity = world.get_entity(*entity).unwrap();
+            assert_eq!(entity.get::<A>().unwrap(), &A(i as u32 + 1));
+        }
+
+        // dropping the iterator spawns the remaining entities
+        let mut batch = world.spawn_batch((101..=110).map(A));
+        batch.next().unwrap();
+        drop(batch);
+        assert_eq!(world.query::<&A>().count(), 111);
+
+        // entities without components are spawned too
+        let empty = world.spawn_batch([(), ()]).collect::<Vec<_>>();
+        assert!(empty
+            .iter()
+            .all(|entity| world.get_entity(*entity).is_some()));
+
+        // entities reserved for bundles that the size hint overstated are freed
+        struct Overstated(std::ops::Range<u32>);
+
+        impl Iterator for Overstated {
+            type Item = A;
+
+            fn next(&mut self) -> Option<A> {
+                self.0.next().map(A)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (10, None)
+            }
+        }
+
+        let count = world.iter_entities().count();
+        let spawned = world.spawn_batch(Overstated(0..2)).collect::<Vec<_>>();
+        assert_eq!(spawned.len(), 2);
+        assert_eq!(world.iter_entities().count(), count + 2);
+    }
+
+    #[test]
+    fn spawn_batch_runs_hooks_at_the_end() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(on_add = on_add)]
+        struct A(u32);
+
+        fn on_add(world: &mut World, _entity: Entity, _component_id: ComponentId) {
+            SEEN.fetch_max(world.query::<&A>().count(), Ordering::Relaxed);
+            // this would invalidate the entities that the batch reserved.
+            world.clear_entities();
+        }
+
+        let mut world = World::new();
+        let entities = world.spawn_batch((0..3).map(A)).collect::<Vec<_>>();
+        assert_eq!(entities.len(), 3);
+        assert_eq!(SEEN.load(Ordering::Relaxed), 3);
+        assert_eq!(world.query::<&A>().count(), 0);
+    }
+
+    #[test]
+    fn insert_and_remove_batch() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let mut entities = world.spawn_batch((0..10).map(A)).collect::<Vec<_>>();
+        let mut with_b = world.spawn(A(10));
+        with_b.insert(B(0));
+        entities.push(with_b.id());
+        entities.push(world.spawn_empty().id());
+
+        world.insert_batch(entities.iter().map(|entity| (*entity, B(1))));
+        for entity in &entities {
+            let entity = world.get_entity(*entity).unwrap();
+            assert_eq!(entity.get::<B>().unwrap(), &B(1));
+        }
+        assert_eq!(world.query::<(&A, &B)>().count(), 11);
+
+        world.remove_batch::<B>(entities.iter().copied().step_by(2));
+        for (i, entity) in entities.iter().enumerate() {
+            let entity = world.get_entity(*entity).unwrap();
+            assert_eq!(entity.contains::<B>(), i % 2 == 1);
+        }
+
+        // each entity still has its own `A`
+        let mut values = world.query::<&A>().map(|a| a.0).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (0..=10).collect::<Vec<_>>());
+        for (i, entity) in entities.iter().take(11).enumerate() {
+            let entity = world.get_entity(*entity).unwrap();
+            assert_eq!(entity.get::<A>(), Some(&A(i as u32)));
+        }
+
+        // later ops for the same entity are applied after the earlier ones
+        world.insert_batch([
+            (entities[0], B(2)),
+            (entities[2], B(2)),
+            (entities[0], B(3)),
+        ]);
+        assert_eq!(
+            world.get_entity(entities[0]).unwrap().get::<B>(),
+            Some(&B(3))
+        );
+        assert_eq!(
+            world.get_entity(entities[2]).unwrap().get::<B>(),
+            Some(&B(2))
+        );
+    }
+
+    #[test]
+    fn query_after_swap_remove() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let first = world.spawn(A(0)).id();
+        world.spawn(A(1));
+        world.spawn(A(2));
+
+        // moving `first` to another table swaps the last row into its place
+        world.get_entity_world_mut(first).unwrap().insert(B(0));
+
+        let mut found = world
+            .query_filtered::<(Entity, &A), Without<B>>()
+            .map(|(entity, a)| (world.get_entity(entity).unwrap().get::<A>().copied(), *a))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(_, a)| a.0);
+        assert_eq!(found, [(Some(A(1)), A(1)), (Some(A(2)), A(2))]);
+    }
+
+    #[test]
+    fn despawn() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let e1 = world.spawn(A(1)).id();
+        let e2 = world.spawn(A(2)).id();
+        let e3 = world.spawn(A(3)).id();
+        let empty = world.spawn_empty().id();
+
+        world.despawn(e1);
+        world.despawn(empty);
+        assert!(world.get_entity(e1).is_none());
+        assert!(world.get_entity(empty).is_none());
+        assert_eq!(world.get_entity(e2).unwrap().get::<A>().unwrap(), &A(2));
+        assert_eq!(world.get_entity(e3).unwrap().get::<A>().unwrap(), &A(3));
+
+        // the freed index is reused, but the old handle stays invalid
+        let e4 = world.spawn(A(4)).id();
+        assert_ne!(e1, e4);
+        assert!(world.get_entity(e1).is_none());
+
+        let mut found = world.query::<&A>().copied().collect::<Vec<_>>();
+        found.sort_by_key(|a| a.0);
+        assert_eq!(found, [A(2), A(3), A(4)]);
+    }
+
+    #[test]
+    fn despawn_drops_components() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Debug, Component)]
+        struct A;
+
+        impl Drop for A {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mut world = World::new();
+        let entity = world.spawn(A).id();
+        assert!(!DROPPED.load(Ordering::Relaxed));
+        world.despawn(entity);
+        assert!(DROPPED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn despawn_where() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Enemy;
+
+        let mut world = World::new();
+        let enemies = world
+            .spawn_batch((0..10).map(|_| Enemy))
+            .collect::<Vec<_>>();
+        let mut armed = world.spawn(Enemy);
+        armed.insert(A(0));
+        let armed = armed.id();
+        let friend = world.spawn(A(1)).id();
+        let prefab = world.spawn_prefab(Enemy).id();
+
+        world.despawn_where::<With<Enemy>>();
+        for entity in enemies.iter().chain([&armed]) {
+            assert!(world.get_entity(*entity).is_none());
+        }
+        assert!(world.get_entity(friend).is_some());
+        assert!(world.get_entity(prefab).is_some());
+
+        // the emptied archetypes can be reused
+        let enemy = world.spawn(Enemy).id();
+        assert_eq!(
+            world
+                .query_filtered::<Entity, With<Enemy>>()
+                .collect::<Vec<_>>(),
+            [enemy]
+        );
+    }
+
+    #[test]
+    fn retain() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B;
+
+        let mut world = World::new();
+        world.spawn_batch((0..10).map(A)).for_each(drop);
+        let b = world.spawn(B).id();
+
+        world.retain::<With<A>>(|entity| entity.get::<A>().unwrap().0 % 2 == 0);
+        let mut found = world.query::<&A>().map(|a| a.0).collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, [0, 2, 4, 6, 8]);
+        assert!(world.get_entity(b).is_some());
+
+        world.retain::<()>(|_| false);
+        assert_eq!(world.iter_entities().count(), 0);
+    }
+
+    #[test]
+    fn retain_runs_hooks_at_the_end() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(on_remove = replace_minion)]
+        struct Boss(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Minion(u32);
+
+        fn replace_minion(world: &mut World, _entity: Entity, _component_id: ComponentId) {
+            let old = world
+                .query::<(Entity, &Minion)>()
+                .find(|(_, minion)| minion.0 == 2)
+                .map(|(entity, _)| entity);
+            if let Some(old) = old {
+                world.despawn(old);
+            }
+            world.spawn(Minion(100));
+        }
+
+        let mut world = World::new();
+        world.spawn(Boss(0));
+        world.spawn(Boss(1));
+        world.spawn(Minion(1));
+        world.spawn(Minion(2));
+
+        // the minion spawned by the hook wasn't checked by `keep`, so it must not be
+        // despawned with the others.
+        world.retain::<()>(|entity| entity.get::<Boss>() == Some(&Boss(0)));
+        assert_eq!(world.query::<&Boss>().collect::<Vec<_>>(), [&Boss(0)]);
+        assert_eq!(world.query::<&Minion>().collect::<Vec<_>>(), [&Minion(100)]);
+    }
+
+    #[test]
+    fn clear_entities_invalidates_handles() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let old = world.spawn(A(1)).id();
+        world.spawn(A(2));
+
+        world.clear_entities();
+        assert!(world.get_entity(old).is_none());
+        assert_eq!(world.query::<&A>().count(), 0);
+
+        let new = world.spawn(A(3)).id();
+        assert_ne!(old, new);
+        assert!(world.get_entity(old).is_none());
+        assert_eq!(world.get_entity(new).unwrap().get::<A>().unwrap(), &A(3));
+        assert_eq!(world.query::<&A>().copied().collect::<Vec<_>>(), [A(3)]);
+    }
+
+    #[test]
+    fn reset() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let mut old = world.spawn(A(1));
+        old.insert(B(2));
+        let old = old.id();
+
+        world.reset();
+        assert!(world.get_entity(old).is_none());
+        assert_eq!(world.iter_entities().count(), 0);
+
+        let mut new = world.spawn(B(3));
+        new.insert(A(4));
+        let new = new.id();
+        assert!(world.get_entity(old).is_none());
+        let new = world.get_entity(new).unwrap();
+        assert_eq!(new.get::<A>().unwrap(), &A(4));
+        assert_eq!(new.get::<B>().unwrap(), &B(3));
+    }
+
+    #[test]
+    fn entity_errors() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+        world.despawn(entity);
+        assert!(matches!(
+            world.try_get_entity(entity),
+            Err(EntityError::Despawned(e)) if e == entity
+        ));
+
+        // the index is reused, but the old handle stays invalid
+        let reused = world.spawn(A(1)).id();
+        assert!(world.try_get_entity(reused).is_ok());
+        assert!(matches!(
+            world.try_get_entity(entity),
+            Err(EntityError::Despawned(_))
+        ));
+
+        let never = Entity::new(1000, EntityGeneration::NEW);
+        assert!(matches!(
+            world.try_get_entity(never),
+            Err(EntityError::NeverExisted(e)) if e == never
+        ));
+        assert!(matches!(
+            world.try_despawn(never),
+            Err(EntityError::NeverExisted(_))
+        ));
+    }
+
+    #[test]
+    fn missing_component_errors() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let mut entity = world.spawn(A(1));
+
+        match entity.try_get::<B>() {
+            Err(EntityError::MissingComponent { component, .. }) => {
+                assert!(component.ends_with("B"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        match entity.try_take::<B>() {
+            Err(QuasarError::Entity(EntityError::MissingComponents { components, .. })) => {
+                assert_eq!(components.len(), 1);
+                assert!(components[0].ends_with("B"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(entity.take::<B>().is_none());
+        assert_eq!(entity.try_take::<A>().unwrap(), A(1));
+    }
+
+    #[test]
+    fn get_many_entities_mut() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let a = world.spawn(A(1)).id();
+        let b = world.spawn(A(2)).id();
+
+        let [mut ea, mut eb] = world.get_many_entities_mut([a, b]).unwrap();
+        std::mem::swap(ea.get_mut::<A>().unwrap(), eb.get_mut::<A>().unwrap());
+        assert_eq!(world.get_entity(a).unwrap().get::<A>().unwrap(), &A(2));
+        assert_eq!(world.get_entity(b).unwrap().get::<A>().unwrap(), &A(1));
+
+        assert!(matches!(
+            world.get_many_entities_mut([a, b, a]),
+            Err(EntityError::Duplicate(e)) if e == a
+        ));
+
+        // an instance and its prefab would share the inherited component
+        let instance = world.spawn_instance(a).id();
+        assert!(world.get_many_entities_mut([instance, b]).is_ok());
+        assert!(matches!(
+            world.get_many_entities_mut([instance, a]),
+            Err(EntityError::Inherits { entity, prefab }) if entity == instance && prefab == a
+        ));
+
+        world.despawn(b);
+        assert!(matches!(
+            world.get_many_entities_mut([a, b]),
+            Err(EntityError::Despawned(e)) if e == b
+        ));
+    }
+
+    #[test]
+    fn unsafe_world_cell() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let a = world.spawn(A(1)).id();
+        let b = world.spawn(A(2)).id();
+        let id = world.id();
+
+        let cell = world.as_unsafe_world_cell();
+        assert_eq!(cell.id(), id);
+        unsafe {
+            // read one entity while mutating the other
+            let ea = cell.get_entity(a).unwrap();
+            let mut eb = cell.get_entity_mut(b).unwrap();
+            eb.get_mut::<A>().unwrap().0 += ea.get::<A>().unwrap().0;
+        }
+
+        assert_eq!(world.get_entity(b).unwrap().get::<A>().unwrap(), &A(3));
+    }
+
+    #[test]
+    fn component_entry() {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let mut entity = world.spawn(B(1));
+
+        assert_eq!(entity.entry::<A>().or_default().get(), &A(0));
+        entity
+            .entry::<A>()
+            .and_modify(|a| a.0 += 1)
+            .or_insert(A(10));
+        assert_eq!(entity.get::<A>(), Some(&A(1)));
+
+        let (old, occupied) = entity.entry::<B>().insert(B(2));
+        assert_eq!(old, Some(B(1)));
+        let (value, _vacant) = occupied.remove();
+        assert_eq!(value, B(2));
+        assert!(!entity.contains::<B>());
+
+        let (old, _) = entity.entry::<B>().insert(B(3));
+        assert_eq!(old, None);
+        *entity.entry::<B>().or_insert_with(|| B(4)).into_mut() = B(5);
+        assert_eq!(entity.get::<B>(), Some(&B(5)));
+    }
+
+    #[test]
+    fn component_entry_inherited() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let prefab = world.spawn_prefab(A(1)).id();
+        let instance = world.spawn_instance(prefab).id();
+
+        // the entity only inherits the component, so `or_insert` overrides it.
+        let mut entity = world.get_entity_world_mut(instance).unwrap();
+        entity.entry::<A>().or_insert(A(10)).get_mut().0 += 1;
+        assert!(entity.owns::<A>());
+        assert_eq!(entity.get::<A>(), Some(&A(11)));
+        assert_eq!(world.get_entity(prefab).unwrap().get::<A>(), Some(&A(1)));
+
+        // components that can't be cloned stay inherited when they're modified.
+        #[derive(Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        world.get_entity_world_mut(prefab).unwrap().insert(B(1));
+        let mut entity = world.get_entity_world_mut(instance).unwrap();
+        let entry = entity.entry::<B>().and_modify(|b| b.0 += 1);
+        let ComponentEntry::Inherited(inherited_entry) = entry
+        else {
+            panic!("expected an inherited entry");
+        };
+        assert_eq!(inherited_entry.get(), &B(1));
+        assert!(!entity.owns::<B>());
+        entity.entry::<B>().or_insert(B(10));
+        assert_eq!(entity.get::<B>(), Some(&B(10)));
+        assert_eq!(world.get_entity(prefab).unwrap().get::<B>(), Some(&B(1)));
+    }
+
+    #[test]
+    fn insert_if_new() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        let mut world = World::new();
+        let mut entity = world.spawn_empty();
+        entity.insert_if_new(A(1));
+        assert_eq!(entity.get::<A>(), Some(&A(1)));
+        entity.insert_if_new(A(2));
+        assert_eq!(entity.get::<A>(), Some(&A(1)));
+        entity.insert(A(3));
+        assert_eq!(entity.get::<A>(), Some(&A(3)));
+    }
+
+    #[test]
+    fn insert_replacing() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        impl Drop for A {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        let mut entity = world.spawn(B(1));
+
+        assert!(matches!(entity.insert_replacing(A(1)), Replaced::None));
+        let old = entity.insert_replacing(A(2)).all().unwrap();
+        assert_eq!(old.0, 1);
+        drop(old);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        assert_eq!(entity.get::<A>().unwrap().0, 2);
+
+        entity.remove::<B>();
+        assert!(matches!(entity.insert_replacing(B(2)), Replaced::None));
+        assert_eq!(entity.insert_replacing(B(3)).all(), Some(B(2)));
+        assert_eq!(entity.get::<A>().unwrap().0, 2);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        // if the entity has only some of the components, their old values are
+        // returned one by one.
+        entity.remove::<B>();
+        let Replaced::Partial(old) = entity.insert_replacing((A(3), B(4)))
+        else {
+            panic!("expected partially replaced components");
+        };
+        assert_eq!(old.len(), 1);
+        let (component_id, value) = old.into_iter().next().unwrap();
+        assert_eq!(
+            Some(component_id),
+            entity.world().components.get_component_id::<A>()
+        );
+        let value = value.downcast::<A>().unwrap();
+        assert_eq!(value.0, 2);
+        drop(value);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+        assert_eq!(entity.get::<A>().unwrap().0, 3);
+        assert_eq!(entity.get::<B>(), Some(&B(4)));
+
+        entity.despawn();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn tuple_bundles() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct C(u32);
+
+        let mut world = World::new();
+        // register in reverse, so that the component ids aren't in the order of the
+        // tuples.
+        world.register_component::<C>();
+        world.register_component::<B>();
+        world.register_component::<A>();
+
+        let mut entity = world.spawn((A(1), (B(2), C(3))));
+        assert_eq!(entity.get::<A>(), Some(&A(1)));
+        assert_eq!(entity.get::<B>(), Some(&B(2)));
+        assert_eq!(entity.get::<C>(), Some(&C(3)));
+
+        assert_eq!(entity.take::<(C, A)>(), Some((C(3), A(1))));
+        assert_eq!(entity.take::<(A, B)>(), None);
+        assert_eq!(entity.take::<(B, ())>(), Some((B(2), ())));
+        assert!(world.query::<&A>().next().is_none());
+    }
+
+    #[test]
+    fn duplicate_components_in_bundle() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct B(u32);
+
+        let mut world = World::new();
+        match world.try_spawn((A(1), B(2), A(3))) {
+            Err(QuasarError::Bundle(BundleError::DuplicateComponents { components, .. })) => {
+                assert_eq!(components.len(), 1);
+                assert!(components[0].ends_with("A"));
+            }
+            other => panic!("unexpected result: {:?}", other.map(|entity| entity.id())),
+        }
+        assert_eq!(world.iter_entities().count(), 0);
+    }
+
+    #[test]
+    fn component_hooks() {
+        static ADDED: AtomicUsize = AtomicUsize::new(0);
+        static REPLACED: AtomicUsize = AtomicUsize::new(0);
+        static REMOVED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(on_add = on_add, on_replace = on_replace, on_remove = on_remove)]
+        struct A(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Marker;
+
+        fn on_add(world: &mut World, entity: Entity, _component_id: ComponentId) {
+            ADDED.fetch_add(1, Ordering::Relaxed);
+            // hooks run after the operation, so they can modify the world.
+            world.get_entity_world_mut(entity).unwrap().insert(Marker);
+        }
+
+        fn on_replace(world: &mut World, entity: Entity, _component_id: ComponentId) {
+            REPLACED.fetch_add(1, Ordering::Relaxed);
+            assert!(world.get_entity(entity).unwrap().contains::<A>());
+        }
+
+        fn on_remove(world: &mut World, entity: Entity, _component_id: ComponentId) {
+            REMOVED.fetch_add(1, Ordering::Relaxed);
+            if let Some(mut entity) = world.get_entity_world_mut(entity) {
+                assert!(!entity.contains::<A>());
+                entity.remove::<Marker>();
+            }
+        }
+
+        let counts = || {
+            (
+                ADDED.load(Ordering::Relaxed),
+                REPLACED.load(Ordering::Relaxed),
+                REMOVED.load(Ordering::Relaxed),
+            )
+        };
+
+        let mut world = World::new();
+        let mut entity = world.spawn(A(1));
+        assert_eq!(counts(), (1, 0, 0));
+        assert!(entity.contains::<Marker>());
+
+        entity.insert(A(2));
+        assert_eq!(counts(), (1, 1, 0));
+        entity.insert_if_new(A(3));
+        assert_eq!(counts(), (1, 1, 0));
+
+        assert_eq!(entity.take::<A>(), Some(A(2)));
+        assert_eq!(counts(), (1, 1, 1));
+        assert!(!entity.contains::<Marker>());
+
+        entity.insert(A(4));
+        assert_eq!(counts(), (2, 1, 1));
+        entity.despawn();
+        assert_eq!(counts(), (2, 1, 2));
+
+        world.spawn_batch([A(5), A(6)]).for_each(drop);
+        assert_eq!(counts(), (4, 1, 2));
+        assert_eq!(world.query::<&Marker>().count(), 2);
+        world.despawn_where::<With<A>>();
+        assert_eq!(counts(), (4, 1, 4));
+    }
+
+    #[test]
+    fn hooks_despawning_the_entity() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(on_add = on_add)]
+        struct Doomed;
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct A(u32);
+
+        fn on_add(world: &mut World, entity: Entity, _component_id: ComponentId) {
+            world.despawn(entity);
+        }
+
+        let mut world = World::new();
+        let mut entity = world.spawn(A(1));
+        assert!(!entity.is_despawned());
+        entity.insert(Doomed);
+        assert!(entity.is_despawned());
+
+        let id = entity.id();
+        assert!(matches!(
+            entity.try_insert(A(2)),
+            Err(QuasarError::Entity(EntityError::Despawned(e))) if e == id
+        ));
+        assert!(matches!(
+            entity.try_take::<A>(),
+            Err(QuasarError::Entity(EntityError::Despawned(e))) if e == id
+        ));
     }
 
-    fn get_bundle_edge<'a>(
-        &self,
-        archetypes: &'a mut Archetypes,
-        archetype_id: ArchetypeId,
-        bundle_info: &BundleInfo,
-        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&'a mut Archetype, &'a mut Archetype)> {
-        archetypes.remove_bundle(archetype_id, bundle_info, create_archetype)
-    }
+    #[test]
+    #[should_panic(expected = "was despawned by a hook")]
+    fn using_an_entity_despawned_by_a_hook() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(on_add = on_add)]
+        struct Doomed;
 
-    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
-        MoveRowDropUnmatched
-    }
+        fn on_add(world: &mut World, entity: Entity, _component_id: ComponentId) {
+            world.despawn(entity);
+        }
 
-    fn insert(
-        self,
-        _bundle_info: &BundleInfo,
-        _insert_into_table: &mut InsertIntoTable,
-        _from_archetype: &Archetype,
-    ) {
+        let mut world = World::new();
+        let mut entity = world.spawn(());
+        entity.insert(Doomed);
+        entity.contains::<Doomed>();
     }
 
-    fn take(
-        &self,
-        _bundle_info: &BundleInfo,
-        _table: &mut Table,
-        _table_row: TableRow,
-    ) -> Self::Output {
-        ()
-    }
-}
+    #[test]
+    fn component_metadata() {
+        fn hook(_world: &mut World, _entity: Entity, _component_id: ComponentId) {}
 
-struct TakeOp<B> {
-    _bundle: PhantomData<B>,
-}
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        struct B(u32);
 
-unsafe impl<B: Bundle> InsertRemoveTakeOp for TakeOp<B> {
-    type Output = B;
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct C(u32);
 
-    fn get_bundle_info<'a>(
-        &self,
-        bundles: &'a mut Bundles,
-        components: &mut Components,
-    ) -> &'a BundleInfo {
-        bundles.get_mut_or_insert_static::<B>(components)
-    }
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone, immutable, on_remove = hook, require(B, C = || C(1)))]
+        struct A;
 
-    fn get_bundle_edge<'a>(
-        &self,
-        archetypes: &'a mut Archetypes,
-        archetype_id: ArchetypeId,
-        bundle_info: &BundleInfo,
-        create_archetype: impl FnOnce(ArchetypeId, &[ComponentId]) -> Archetype,
-    ) -> Option<(&'a mut Archetype, &'a mut Archetype)> {
-        archetypes.remove_bundle(archetype_id, bundle_info, create_archetype)
+        let mut world = World::new();
+        let descriptor = world.components.register::<A>().descriptor();
+        assert!(descriptor.clone_fn().is_some());
+        assert!(!descriptor.is_mutable());
+        assert!(descriptor.hooks().on_add.is_none());
+        assert!(descriptor.hooks().on_remove.is_some());
+
+        let required = descriptor
+            .required()
+            .iter()
+            .map(|required| required.type_id())
+            .collect::<Vec<_>>();
+        assert_eq!(required, [TypeId::of::<B>(), TypeId::of::<C>()]);
+
+        let descriptor = world.components.register::<B>().descriptor();
+        assert!(descriptor.is_mutable());
+        assert!(descriptor.hooks().is_empty());
+        assert!(descriptor.required().is_empty());
     }
 
-    fn handle_unmatched(&self) -> impl MoveRowHandleUnmatched {
-        MoveRowForgetUnmatched
-    }
+    #[test]
+    fn required_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(require(Transform, Visibility = || Visibility(true)))]
+        struct Sprite;
 
-    fn insert(
-        self,
-        _bundle_info: &BundleInfo,
-        _insert_into_table: &mut InsertIntoTable,
-        _from_archetype: &Archetype,
-    ) {
-    }
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        #[quasar(require(GlobalTransform))]
+        struct Transform(u32);
 
-    fn take(
-        &self,
-        bundle_info: &BundleInfo,
-        table: &mut Table,
-        table_row: TableRow,
-    ) -> Self::Output {
-        B::from_components(TakeComponentsFromTable::new(bundle_info, table, table_row))
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        struct GlobalTransform(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Visibility(bool);
+
+        let mut world = World::new();
+        let entity = world.spawn(Sprite);
+        assert_eq!(entity.get::<Transform>(), Some(&Transform(0)));
+        assert_eq!(entity.get::<GlobalTransform>(), Some(&GlobalTransform(0)));
+        assert_eq!(entity.get::<Visibility>(), Some(&Visibility(true)));
+
+        // components in the bundle, or that the entity already has, are kept.
+        let mut entity = world.spawn((Transform(1), Visibility(false)));
+        entity.insert((Sprite, GlobalTransform(2)));
+        assert_eq!(entity.get::<Transform>(), Some(&Transform(1)));
+        assert_eq!(entity.get::<GlobalTransform>(), Some(&GlobalTransform(2)));
+        assert_eq!(entity.get::<Visibility>(), Some(&Visibility(false)));
+
+        // a required component can be removed, but inserting the component that
+        // requires it adds it again.
+        entity.remove::<Visibility>();
+        assert!(!entity.contains::<Visibility>());
+        entity.insert(Sprite);
+        assert_eq!(entity.get::<Visibility>(), Some(&Visibility(true)));
+
+        world.spawn_batch([Sprite, Sprite]).for_each(drop);
+        assert_eq!(
+            world
+                .query::<(&Sprite, &Transform, &GlobalTransform, &Visibility)>()
+                .count(),
+            4
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::sync::atomic::{
-        AtomicBool,
-        Ordering,
-    };
+    #[test]
+    fn required_components_cycle() {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        #[quasar(require(B))]
+        struct A(u32);
 
-    use quasar_ecs_derive::Component;
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
+        #[quasar(require(A))]
+        struct B(u32);
 
-    use crate::World;
+        let mut world = World::new();
+        let entity = world.spawn(A(1));
+        assert_eq!(entity.get::<A>(), Some(&A(1)));
+        assert_eq!(entity.get::<B>(), Some(&B(0)));
+    }
 
     #[test]
-    fn spawn_component() {
-        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component)]
-        struct MyComponent {
-            position: u32,
-            velocity: u32,
+    fn immutable_components() {
+        static REPLACED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(immutable, on_replace = on_replace)]
+        struct NetworkId(u64);
+
+        fn on_replace(_world: &mut World, _entity: Entity, _component_id: ComponentId) {
+            REPLACED.fetch_add(1, Ordering::Relaxed);
         }
 
         let mut world = World::new();
-
-        let component = MyComponent {
-            position: 42,
-            velocity: 1312,
+        let entity = world.spawn(NetworkId(1)).id();
+        assert!(!world
+            .components
+            .register::<NetworkId>()
+            .descriptor()
+            .is_mutable());
+
+        // immutable components can only be changed by replacing them.
+        let mut entity = world.get_entity_world_mut(entity).unwrap();
+        entity.insert(NetworkId(2));
+        assert_eq!(entity.get::<NetworkId>(), Some(&NetworkId(2)));
+        assert_eq!(REPLACED.load(Ordering::Relaxed), 1);
+
+        let ComponentEntry::Occupied(mut occupied_entry) = entity.entry::<NetworkId>()
+        else {
+            panic!("expected an occupied entry");
         };
-        let entity = world.spawn(component);
+        assert_eq!(occupied_entry.insert(NetworkId(3)), NetworkId(2));
+        assert_eq!(occupied_entry.get(), &NetworkId(3));
+        assert_eq!(REPLACED.load(Ordering::Relaxed), 2);
+    }
 
-        // test if we can access the component from the returned `EntityWorldMut`
-        let component2 = entity.get::<MyComponent>().unwrap();
-        assert_eq!(component, *component2);
+    #[test]
+    fn component_indexes() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+        #[quasar(immutable)]
+        struct NetworkId(u64);
 
-        // test if we can access the entity and component from the world
-        let entity = entity.id();
-        let entity = world.get_entity(entity).unwrap();
-        let component2 = entity.get::<MyComponent>().unwrap();
-        assert_eq!(component, *component2);
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Component)]
+        #[quasar(immutable)]
+        struct Level(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Marker;
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(require(NetworkId = || NetworkId(100)))]
+        struct Sprite;
+
+        let mut world = World::new();
+        let a = world.spawn(NetworkId(1)).id();
+        world.add_hash_index::<NetworkId>();
+        world.add_ordered_index::<Level>();
+
+        // entities that existed before the index was created are indexed too.
+        assert_eq!(world.lookup(&NetworkId(1)), [a]);
+        assert!(world.lookup(&NetworkId(2)).is_empty());
+
+        let b = world.spawn((NetworkId(2), Level(5))).id();
+        let c = world.spawn(Level(10)).id();
+        assert_eq!(world.lookup(&NetworkId(2)), [b]);
+        assert_eq!(world.lookup(&Level(10)), [c]);
+
+        // replacing
+        world.get_entity_world_mut(a).unwrap().insert(NetworkId(3));
+        assert!(world.lookup(&NetworkId(1)).is_empty());
+        assert_eq!(world.lookup(&NetworkId(3)), [a]);
+        world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .insert_if_new(NetworkId(4));
+        assert_eq!(world.lookup(&NetworkId(3)), [a]);
+        assert!(world.lookup(&NetworkId(4)).is_empty());
+
+        // moving the entity to another archetype
+        world.get_entity_world_mut(b).unwrap().insert(Marker);
+        assert_eq!(world.lookup(&NetworkId(2)), [b]);
+
+        // ranges
+        world.get_entity_world_mut(a).unwrap().insert(Level(7));
+        assert_eq!(
+            world.lookup_range(Level(5)..=Level(7)).collect::<Vec<_>>(),
+            [b, a]
+        );
+        assert_eq!(world.lookup_range(Level(6)..).collect::<Vec<_>>(), [a, c]);
+
+        // removing
+        world.get_entity_world_mut(a).unwrap().remove::<NetworkId>();
+        assert!(world.lookup(&NetworkId(3)).is_empty());
+        assert_eq!(world.lookup(&Level(7)), [a]);
+
+        // despawning
+        world.despawn(b);
+        assert!(world.lookup(&NetworkId(2)).is_empty());
+        world.despawn_where::<With<Level>>();
+        assert_eq!(world.lookup_range::<Level>(..).count(), 0);
+
+        // batches
+        let entities = world
+            .spawn_batch((0..3).map(|i| (NetworkId(i), Level(i as u32))))
+            .collect::<Vec<_>>();
+        assert_eq!(world.lookup(&NetworkId(1)), [entities[1]]);
+        world.insert_batch(entities.iter().map(|entity| (*entity, NetworkId(10))));
+        assert_eq!(world.lookup(&NetworkId(10)).len(), 3);
+        assert!(world.lookup(&NetworkId(1)).is_empty());
+
+        // required components are indexed only if they were inserted
+        let d = world.spawn(Sprite).id();
+        assert_eq!(world.lookup(&NetworkId(100)), [d]);
+        world
+            .get_entity_world_mut(entities[0])
+            .unwrap()
+            .insert(Sprite);
+        assert_eq!(world.lookup(&NetworkId(10)).len(), 3);
+        world
+            .get_entity_world_mut(entities[0])
+            .unwrap()
+            .remove::<NetworkId>();
+        assert_eq!(world.lookup(&NetworkId(10)).len(), 2);
+
+        world.clear_entities();
+        assert!(world.lookup(&NetworkId(10)).is_empty());
     }
 
     #[test]
-    fn spawn_empty() {
+    fn unique_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+        #[quasar(immutable)]
+        struct Name(&'static str);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Component)]
+        #[quasar(immutable)]
+        struct Slot(u32);
+
         let mut world = World::new();
-        let entity = world.spawn_empty().id();
-        world.get_entity(entity).unwrap();
+        world.add_hash_index::<Name>();
+        world.add_ordered_index::<Slot>();
+
+        // components with duplicate values can't be made unique.
+        let a = world.spawn((Name("a"), Slot(1))).id();
+        let b = world.spawn(Slot(1)).id();
+        assert!(matches!(
+            world.set_unique::<Slot>(UniquePolicy::Replace),
+            Err(QuasarError::NotUnique { entity, .. }) if entity == b
+        ));
+        world.get_entity_world_mut(b).unwrap().insert(Slot(2));
+        world.set_unique::<Slot>(UniquePolicy::Replace).unwrap();
+        world.set_unique::<Name>(UniquePolicy::Error).unwrap();
+
+        // error
+        let entities = world.iter_entities().count();
+        assert!(matches!(
+            world.try_spawn(Name("a")),
+            Err(QuasarError::NotUnique { entity, .. }) if entity == a
+        ));
+        assert_eq!(world.iter_entities().count(), entities);
+        assert!(matches!(
+            world
+                .get_entity_world_mut(b)
+                .unwrap()
+                .try_insert((Name("a"), Slot(3))),
+            Err(QuasarError::NotUnique { entity, .. }) if entity == a
+        ));
+        assert_eq!(world.get_entity(b).unwrap().get::<Slot>(), Some(&Slot(2)));
+        assert!(world.try_clone_entity(a).is_err());
+        assert_eq!(world.iter_entities().count(), entities);
+
+        // reinserting the same value, or keeping the old one, is fine.
+        world.get_entity_world_mut(a).unwrap().insert(Name("a"));
+        world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .insert_if_new(Name("b"));
+        world.get_entity_world_mut(b).unwrap().insert(Name("b"));
+        world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .insert_if_new(Name("b"));
+        assert_eq!(world.lookup(&Name("a")), [a]);
+        assert_eq!(world.lookup(&Name("b")), [b]);
+
+        // replace
+        let c = world.spawn(Slot(1)).id();
+        assert_eq!(world.lookup(&Slot(1)), [c]);
+        assert!(world.get_entity(a).unwrap().get::<Slot>().is_none());
+        assert_eq!(world.get_entity(a).unwrap().get::<Name>(), Some(&Name("a")));
+        world.insert_batch([(a, Slot(2)), (c, Slot(3))]);
+        assert_eq!(world.lookup(&Slot(2)), [a]);
+        assert_eq!(world.lookup(&Slot(3)), [c]);
+        assert!(world.get_entity(b).unwrap().get::<Slot>().is_none());
+
+        let spawned = world.spawn_batch([Slot(2), Slot(4)]).collect::<Vec<_>>();
+        assert_eq!(world.lookup(&Slot(2)), [spawned[0]]);
+        assert!(world.get_entity(a).unwrap().get::<Slot>().is_none());
+
+        // required components are checked too.
+        #[derive(Component)]
+        #[quasar(require(Name = || Name("player")))]
+        struct Player;
+
+        let player = world.spawn(Player).id();
+        assert_eq!(world.lookup(&Name("player")), [player]);
+        let entities = world.iter_entities().count();
+        assert!(matches!(
+            world.try_spawn(Player),
+            Err(QuasarError::NotUnique { entity, .. }) if entity == player
+        ));
+        assert!(matches!(
+            world.get_entity_world_mut(c).unwrap().try_insert(Player),
+            Err(QuasarError::NotUnique { entity, .. }) if entity == player
+        ));
+        assert_eq!(world.iter_entities().count(), entities);
+        // the entity already has the required component, so it isn't inserted.
+        world.get_entity_world_mut(a).unwrap().insert(Player);
+        assert_eq!(world.lookup(&Name("a")), [a]);
+
+        // also when spawning in batches.
+        #[derive(Component)]
+        #[quasar(require(Slot = || Slot(4)))]
+        struct Seat;
+
+        let seat = world.spawn_batch([Seat]).next().unwrap();
+        assert_eq!(world.lookup(&Slot(4)), [seat]);
+        assert!(world
+            .get_entity(spawned[1])
+            .unwrap()
+            .get::<Slot>()
+            .is_none());
     }
 
     #[test]
-    fn remove_component() {
-        #[derive(Component)]
-        struct MyComponent;
+    fn entries_of_inherited_unique_components() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+        #[quasar(immutable, clone)]
+        struct Name(&'static str);
 
-        let mut world = World::new();
-        let mut entity = world.spawn(MyComponent);
-        entity.remove::<MyComponent>();
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Health(u32);
 
-        assert!(entity.get::<MyComponent>().is_none());
+        let mut world = World::new();
+        world.add_hash_index::<Name>();
+        world.set_unique::<Name>(UniquePolicy::Error).unwrap();
+        let mut prefab = world.spawn_prefab(Name("x"));
+        prefab.insert(Health(10));
+        let prefab = prefab.id();
+
+        // inherited components aren't cloned just by looking at their entry.
+        let mut instance = world.spawn_instance(prefab);
+        let ComponentEntry::Inherited(inherited_entry) = instance.entry::<Name>()
+        else {
+            panic!("expected an inherited entry");
+        };
+        assert_eq!(inherited_entry.get(), &Name("x"));
+        assert!(!instance.owns::<Name>());
+        instance.entry::<Name>().or_insert(Name("y"));
+        assert_eq!(instance.get::<Name>(), Some(&Name("y")));
+        let instance = instance.id();
+        assert_eq!(world.lookup(&Name("x")), [prefab]);
+        assert_eq!(world.lookup(&Name("y")), [instance]);
+
+        // inserting over an inherited component doesn't replace it.
+        let mut other = world.spawn_instance(prefab);
+        let (old_value, _) = other.entry::<Name>().insert(Name("z"));
+        assert_eq!(old_value, None);
+        let (old_value, _) = other.entry::<Health>().insert(Health(20));
+        assert_eq!(old_value, None);
+
+        // they're only cloned when they're modified.
+        let mut other = world.spawn_instance(prefab);
+        other.entry::<Health>().and_modify(|health| health.0 += 1);
+        assert_eq!(other.get::<Health>(), Some(&Health(11)));
+        assert!(other.owns::<Health>());
+        assert!(!other.owns::<Name>());
+        let prefab = world.get_entity(prefab).unwrap();
+        assert_eq!(prefab.get::<Name>(), Some(&Name("x")));
+        assert_eq!(prefab.get::<Health>(), Some(&Health(10)));
     }
 
     #[test]
-    fn it_doesnt_drop_inserted_components() {
-        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+    fn shared_components() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Material(&'static str);
 
-        #[derive(Component)]
-        struct MyComponent;
+        impl SharedComponent for Material {}
 
-        impl Drop for MyComponent {
-            fn drop(&mut self) {
-                WAS_DROPPED.store(true, Ordering::Relaxed);
-            }
-        }
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Position(u32);
 
         let mut world = World::new();
-        let _ = world.spawn(MyComponent);
-
-        assert!(!WAS_DROPPED.load(Ordering::Relaxed));
+        let a = world
+            .spawn(Position(0))
+            .insert_shared(Material("stone"))
+            .id();
+        let b = world
+            .spawn(Position(1))
+            .insert_shared(Material("wood"))
+            .id();
+        let c = world
+            .spawn(Position(2))
+            .insert_shared(Material("stone"))
+            .id();
+
+        // equal values are only stored once
+        assert_eq!(
+            world.shared_values::<Material>().collect::<Vec<_>>(),
+            [&Material("stone"), &Material("wood")]
+        );
+        assert_eq!(
+            world.get_entity_world_mut(c).unwrap().get_shared(),
+            Some(&Material("stone"))
+        );
+
+        // entities are grouped by value
+        assert_eq!(
+            world
+                .query_shared::<Entity, _>(&Material("stone"))
+                .collect::<Vec<_>>(),
+            [a, c]
+        );
+        assert_eq!(
+            world.query_shared::<Entity, _>(&Material("glass")).count(),
+            0
+        );
+        let mut materials = world
+            .query::<(Entity, Shared<Material>)>()
+            .collect::<Vec<_>>();
+        materials.sort_by_key(|(entity, _)| *entity);
+        assert_eq!(
+            materials,
+            [
+                (a, &Material("stone")),
+                (b, &Material("wood")),
+                (c, &Material("stone"))
+            ]
+        );
+
+        // replacing the value moves the entity to the other group
+        world
+            .get_entity_world_mut(c)
+            .unwrap()
+            .insert_shared(Material("wood"));
+        assert_eq!(
+            world
+                .query_shared::<Entity, _>(&Material("wood"))
+                .collect::<Vec<_>>(),
+            [b, c]
+        );
+        for position in world.query_shared_mut::<&mut Position, _>(&Material("wood")) {
+            position.0 += 10;
+        }
+        assert_eq!(
+            world.get_entity(c).unwrap().get::<Position>(),
+            Some(&Position(12))
+        );
+        assert_eq!(
+            world.get_entity(a).unwrap().get::<Position>(),
+            Some(&Position(0))
+        );
+
+        // clones share the value
+        let d = world.clone_entity(b);
+        assert_eq!(
+            world.get_entity_world_mut(d).unwrap().get_shared(),
+            Some(&Material("wood"))
+        );
+
+        // removing
+        world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .remove_shared::<Material>();
+        assert!(world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .get_shared::<Material>()
+            .is_none());
+        assert_eq!(
+            world
+                .query_filtered::<Entity, With<Shared<Material>>>()
+                .count(),
+            3
+        );
+        assert_eq!(world.shared_values::<Material>().count(), 2);
     }
 
     #[test]
-    fn it_doesnt_drop_taken_components() {
-        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+    fn shared_values_are_reused() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Mesh(u32);
 
-        #[derive(Component)]
-        struct MyComponent;
+        impl SharedComponent for Mesh {}
 
-        impl Drop for MyComponent {
-            fn drop(&mut self) {
-                WAS_DROPPED.store(true, Ordering::Relaxed);
-            }
+        #[derive(Clone, Debug, Component)]
+        #[quasar(clone)]
+        struct Position;
+
+        #[derive(Clone, Debug, Component)]
+        #[quasar(clone)]
+        struct Velocity;
+
+        let mut world = World::new();
+        let a = world.spawn(Position).insert_shared(Mesh(0)).id();
+        let b = world
+            .spawn(Position)
+            .insert_shared(Mesh(0))
+            .insert(Velocity)
+            .id();
+
+        // replacing the value moves the entity directly to the archetype with the
+        // other value, without passing through one without a value
+        let archetypes = world.archetypes.iter().count();
+        world
+            .get_entity_world_mut(b)
+            .unwrap()
+            .insert_shared(Mesh(1));
+        assert_eq!(world.archetypes.iter().count(), archetypes + 1);
+
+        // the components, archetypes and tables of values that no entity has anymore
+        // are reused
+        for i in 2..100 {
+            world
+                .get_entity_world_mut(b)
+                .unwrap()
+                .insert_shared(Mesh(i));
+            assert_eq!(
+                world.get_entity_world_mut(b).unwrap().get_shared(),
+                Some(&Mesh(i))
+            );
         }
+        assert!(world.archetypes.iter().count() < archetypes + 20);
+        assert!(world.shared_values::<Mesh>().count() < 20);
+        assert_eq!(
+            world
+                .query_shared::<Entity, _>(&Mesh(0))
+                .collect::<Vec<_>>(),
+            [a]
+        );
+        assert_eq!(
+            world
+                .query_shared::<Entity, _>(&Mesh(99))
+                .collect::<Vec<_>>(),
+            [b]
+        );
+        assert_eq!(world.query_shared::<Entity, _>(&Mesh(50)).count(), 0);
+
+        // cloning replaces the value too
+        world
+            .get_entity_world_mut(b)
+            .unwrap()
+            .clone_components_to(a);
+        assert_eq!(
+            world.get_entity_world_mut(a).unwrap().get_shared(),
+            Some(&Mesh(99))
+        );
+        assert_eq!(world.query_shared::<Entity, _>(&Mesh(0)).count(), 0);
+
+        // resetting forgets all values
+        world.reset();
+        assert_eq!(world.shared_values::<Mesh>().count(), 0);
+        let c = world.spawn_empty().insert_shared(Mesh(0)).id();
+        assert_eq!(
+            world
+                .query_shared::<Entity, _>(&Mesh(0))
+                .collect::<Vec<_>>(),
+            [c]
+        );
+    }
+
+    #[test]
+    fn component_enable_bits() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Position(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Frozen;
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Tag;
 
         let mut world = World::new();
-        let mut entity = world.spawn(MyComponent);
-        let _component = entity.take::<MyComponent>().unwrap();
+        let a = world.spawn((Position(0), Frozen)).id();
+        let b = world.spawn((Position(1), Frozen)).id();
+        let c = world.spawn(Position(2)).id();
+
+        world.disable_component::<Position>(a);
+        world
+            .get_entity_world_mut(b)
+            .unwrap()
+            .disable_component::<Frozen>();
+        let location = world.entities.get_location(a).unwrap();
+        assert_eq!(
+            world.entities.get_location(b).unwrap().archetype_id,
+            location.archetype_id
+        );
+
+        let mut positions = world.query::<(Entity, &Position)>().collect::<Vec<_>>();
+        positions.sort_by_key(|(entity, _)| *entity);
+        assert_eq!(positions, [(b, &Position(1)), (c, &Position(2))]);
+        let mut optional = world
+            .query::<(Entity, Option<&Position>)>()
+            .collect::<Vec<_>>();
+        optional.sort_by_key(|(entity, _)| *entity);
+        assert_eq!(
+            optional,
+            [(a, None), (b, Some(&Position(1))), (c, Some(&Position(2)))]
+        );
+        assert_eq!(
+            world
+                .query_filtered::<Entity, With<Frozen>>()
+                .collect::<Vec<_>>(),
+            [a]
+        );
+        let mut unfrozen = world
+            .query_filtered::<Entity, Without<Frozen>>()
+            .collect::<Vec<_>>();
+        unfrozen.sort();
+        assert_eq!(unfrozen, [b, c]);
+
+        // archetypes with the component are only checked row by row while it's
+        // disabled in any of them.
+        let frozen = world.components().get_component_id::<Frozen>().unwrap();
+        let table = world.tables().get(location.table_id);
+        assert!(table.has_disabled(frozen));
+        world.enable_component::<Frozen>(b);
+        let table = world.tables().get(location.table_id);
+        assert!(!table.has_disabled(frozen));
+        let state = <Without<Frozen> as QueryFilter>::init_state(&world);
+        assert!(!Without::<Frozen>::matches_archetype(
+            &state,
+            world.archetypes.get(location.archetype_id),
+            table
+        ));
+        assert_eq!(
+            world
+                .query_filtered::<Entity, Without<Frozen>>()
+                .collect::<Vec<_>>(),
+            [c]
+        );
+        world.disable_component::<Frozen>(b);
+        for position in world.query_mut::<&mut Position>() {
+            position.0 += 10;
+        }
 
-        assert!(!WAS_DROPPED.load(Ordering::Relaxed));
+        // the data stays in place
+        let entity = world.get_entity(a).unwrap();
+        assert_eq!(entity.get::<Position>(), Some(&Position(0)));
+        assert!(!entity.is_component_enabled::<Position>());
+        assert!(entity.is_component_enabled::<Frozen>());
+        assert_eq!(
+            world.get_entity(b).unwrap().get::<Position>(),
+            Some(&Position(11))
+        );
+
+        // the bit moves along with the entity
+        world.get_entity_world_mut(a).unwrap().insert(Tag);
+        assert!(!world
+            .get_entity(a)
+            .unwrap()
+            .is_component_enabled::<Position>());
+        let position = world.components().get_component_id::<Position>().unwrap();
+        assert!(!world.tables().get(location.table_id).has_disabled(position));
+        assert_eq!(world.query::<&Position>().count(), 2);
+
+        world
+            .get_entity_world_mut(a)
+            .unwrap()
+            .enable_component::<Position>();
+        assert_eq!(
+            world
+                .query_filtered::<&Position, With<Tag>>()
+                .collect::<Vec<_>>(),
+            [&Position(0)]
+        );
+
+        world.despawn_where::<Without<Frozen>>();
+        assert!(world.get_entity(a).is_some());
+        assert!(world.get_entity(b).is_none());
+        assert!(world.get_entity(c).is_none());
     }
 
     #[test]
-    fn it_does_drop_components_when_world_is_dropped() {
-        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+    fn disabled_entities() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Bullet(u32);
 
-        #[derive(Component)]
-        struct MyComponent;
+        let mut world = World::new();
+        let a = world.spawn(Bullet(0)).id();
+        let b = world.spawn(Bullet(1)).disable().id();
+        assert!(world.get_entity_world_mut(b).unwrap().is_disabled());
+        assert!(!world.get_entity_world_mut(a).unwrap().is_disabled());
+
+        assert_eq!(world.query::<&Bullet>().collect::<Vec<_>>(), [&Bullet(0)]);
+        let mut all = world
+            .query_filtered::<Entity, Allow<Disabled>>()
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, [a, b]);
+        assert_eq!(
+            world
+                .query_filtered::<Entity, With<Disabled>>()
+                .collect::<Vec<_>>(),
+            [b]
+        );
+
+        // the entity keeps its components and identity
+        assert_eq!(
+            world.get_entity(b).unwrap().get::<Bullet>(),
+            Some(&Bullet(1))
+        );
+        world.despawn_where::<With<Bullet>>();
+        assert!(world.get_entity(a).is_none());
+        assert!(world.get_entity(b).is_some());
+
+        // clones are disabled too
+        let c = world.clone_entity(b);
+        assert!(world.get_entity_world_mut(c).unwrap().is_disabled());
+
+        world.get_entity_world_mut(b).unwrap().enable();
+        assert_eq!(world.query::<Entity>().collect::<Vec<_>>(), [b]);
+    }
 
-        impl Drop for MyComponent {
-            fn drop(&mut self) {
-                WAS_DROPPED.store(true, Ordering::Relaxed);
+    #[test]
+    fn chunked_tables() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Position(u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        struct Velocity(u32);
+
+        let mut world = World::new();
+        // 8 rows of `Position` and `Velocity` per chunk
+        world.set_table_layout(TableLayout::Chunked { chunk_size: 64 });
+        let entities = (0..20)
+            .map(|i| world.spawn((Position(i), Velocity(i))).id())
+            .collect::<Vec<_>>();
+
+        let chunk_lens = world
+            .chunks::<Position>()
+            .map(|(entities, positions)| {
+                assert_eq!(entities.len(), positions.len());
+                positions.len()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(chunk_lens, [8, 8, 4]);
+
+        // removing a row from an earlier chunk moves the last row into it
+        world.despawn(entities[3]);
+        world.remove::<Velocity>(entities[10]);
+        let chunk_lens = world
+            .chunks::<Velocity>()
+            .map(|(_, velocities)| velocities.len())
+            .collect::<Vec<_>>();
+        assert_eq!(chunk_lens, [8, 8, 2]);
+        for (chunk_entities, velocities) in world.chunks_mut::<Velocity>() {
+            for (entity, velocity) in chunk_entities.iter().zip(velocities) {
+                let index = entities.iter().position(|e| e == entity).unwrap() as u32;
+                assert_eq!(velocity.0, index);
+                velocity.0 += 100;
+            }
+        }
+        for (i, entity) in entities.iter().enumerate() {
+            let i = i as u32;
+            let Some(entity) = world.get_entity(*entity)
+            else {
+                assert_eq!(i, 3);
+                continue;
+            };
+            assert_eq!(entity.get::<Position>(), Some(&Position(i)));
+            if i != 10 {
+                assert_eq!(entity.get::<Velocity>(), Some(&Velocity(i + 100)));
             }
         }
+        assert_eq!(world.chunks::<Position>().count(), 4);
+        assert_eq!(world.query::<&Position>().count(), 19);
 
+        // contiguous tables are a single chunk
         let mut world = World::new();
-        let _ = world.spawn(MyComponent);
-        drop(world);
-
-        assert!(WAS_DROPPED.load(Ordering::Relaxed));
+        for i in 0..20 {
+            world.spawn(Position(i));
+        }
+        assert_eq!(world.chunks::<Position>().count(), 1);
     }
 
     #[test]
-    fn it_does_drop_components_when_theyre_removed() {
-        static WAS_DROPPED: AtomicBool = AtomicBool::new(false);
+    fn stable_storage() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
 
-        #[derive(Component)]
-        struct MyComponent;
+        #[derive(Clone, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone, storage = "stable")]
+        struct Body(u32);
 
-        impl Drop for MyComponent {
+        impl Drop for Body {
             fn drop(&mut self) {
-                WAS_DROPPED.store(true, Ordering::Relaxed);
+                DROPS.fetch_add(1, Ordering::Relaxed);
             }
         }
 
-        let mut world = World::new();
-        let mut entity = world.spawn(MyComponent);
-        entity.remove::<MyComponent>();
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Tag(u32);
 
-        assert!(WAS_DROPPED.load(Ordering::Relaxed));
+        fn address(world: &World, entity: Entity) -> *const Body {
+            world.get_entity(entity).unwrap().get::<Body>().unwrap()
+        }
+
+        let mut world = World::new();
+        let entities = (0..10)
+            .map(|i| world.spawn(Body(i)).id())
+            .collect::<Vec<_>>();
+        let addresses = entities
+            .iter()
+            .map(|entity| address(&world, *entity))
+            .collect::<Vec<_>>();
+
+        // moving entities between tables, and removing rows, doesn't move the bodies
+        for entity in &entities[..5] {
+            world.get_entity_world_mut(*entity).unwrap().insert(Tag(0));
+        }
+        world.remove::<Tag>(entities[2]);
+        world.despawn(entities[0]);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        world
+            .get_entity_world_mut(entities[1])
+            .unwrap()
+            .insert(Body(11));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+        let old = world
+            .get_entity_world_mut(entities[3])
+            .unwrap()
+            .insert_replacing(Body(13))
+            .all()
+            .unwrap();
+        assert_eq!(old.0, 3);
+        drop(old);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+
+        for (i, entity) in entities.iter().enumerate().skip(1) {
+            assert_eq!(address(&world, *entity), addresses[i]);
+            let expected = match i {
+                1 => 11,
+                3 => 13,
+                _ => i as u32,
+            };
+            assert_eq!(unsafe { (*addresses[i]).0 }, expected);
+        }
+        let mut bodies = world
+            .query::<&Body>()
+            .map(|body| body.0)
+            .collect::<Vec<_>>();
+        bodies.sort();
+        assert_eq!(bodies, [2, 4, 5, 6, 7, 8, 9, 11, 13]);
+
+        // clones get their own box
+        let clone = world.clone_entity(entities[4]);
+        assert_ne!(address(&world, clone), addresses[4]);
+        assert_eq!(
+            world
+                .get_entity(clone)
+                .unwrap()
+                .get::<Body>()
+                .map(|body| body.0),
+            Some(4)
+        );
+
+        // removing the body drops it
+        world.remove::<Body>(entities[5]);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 4);
+        drop(world);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 13);
     }
 
     #[test]
-    fn take_component() {
+    fn custom_storage() {
         #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
-        struct MyComponent(u32);
+        #[quasar(clone)]
+        struct Position(i32);
 
-        let mut world = World::new();
-        let component = MyComponent(1312);
-        let mut entity = world.spawn(component);
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+        #[quasar(clone)]
+        struct Tag;
+
+        // the components are in `UnsafeCell`s, so that they can be mutated through
+        // the pointers from `get_ptr`.
+        #[derive(Default)]
+        struct MapStorage {
+            components: HashMap<Entity, (UnsafeCell<Position>, Cell<Tick>)>,
+        }
 
-        let component2 = entity.take::<MyComponent>().unwrap();
-        assert_eq!(component, component2);
-    }
+        unsafe impl ComponentStorage<Position> for MapStorage {
+            fn insert(
+                &mut self,
+                entity: Entity,
+                component: Position,
+                tick: Tick,
+            ) -> Option<Position> {
+                self.components
+                    .insert(entity, (UnsafeCell::new(component), Cell::new(tick)))
+                    .map(|(old, _)| old.into_inner())
+            }
 
-    #[test]
-    fn taking_a_component_twice_fails() {
-        #[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
-        struct MyComponent(u32);
+            fn remove(&mut self, entity: Entity) -> Option<Position> {
+                self.components
+                    .remove(&entity)
+                    .map(|(old, _)| old.into_inner())
+            }
 
-        let mut world = World::new();
-        let component = MyComponent(1312);
-        let mut entity = world.spawn(component);
+            fn get(&self, entity: Entity) -> Option<&Position> {
+                let (component, _) = self.components.get(&entity)?;
+                // SAFETY: The world doesn't mutate the component through a pointer while
+                // it's borrowed.
+                Some(unsafe { &*component.get() })
+            }
 
-        let component2 = entity.take::<MyComponent>().unwrap();
-        assert_eq!(component, component2);
+            fn get_mut(&mut self, entity: Entity, tick: Tick) -> Option<&mut Position> {
+                let (component, changed) = self.components.get_mut(&entity)?;
+                changed.set(tick);
+                Some(component.get_mut())
+            }
 
-        assert!(entity.take::<MyComponent>().is_none());
+            fn get_ptr(&self, entity: Entity, tick: Tick) -> Option<NonNull<Position>> {
+                let (component, changed) = self.components.get(&entity)?;
+                changed.set(tick);
+                NonNull::new(component.get())
+            }
+
+            fn iter(&self) -> Box<dyn Iterator<Item = (Entity, &Position)> + '_> {
+                Box::new(self.components.keys().map(|entity| {
+                    let component = self.get(*entity).unwrap();
+                    (*entity, component)
+                }))
+            }
+
+            fn changed_tick(&self, entity: Entity) -> Option<Tick> {
+                self.components.get(&entity).map(|(_, tick)| tick.get())
+            }
+        }
+
+        fn stored(world: &World) -> usize {
+            world
+                .component_storage::<Position>()
+                .unwrap()
+                .iter()
+                .count()
+        }
+
+        let mut world = World::new();
+        world.set_component_storage(MapStorage::default()).unwrap();
+
+        let a = world.spawn(Position(1)).id();
+        let b = world.spawn((Position(2), Tag)).id();
+        let c = world.spawn(Tag).id();
+        assert_eq!(stored(&world), 2);
+        assert_eq!(
+            world.get_entity(a).unwrap().get::<Position>(),
+            Some(&Position(1))
+        );
+
+        // queries fetch from the storage
+        let mut positions = world
+            .query::<&Position>()
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        positions.sort();
+        assert_eq!(positions, [1, 2]);
+        assert_eq!(world.query_filtered::<&Position, With<Tag>>().count(), 1);
+
+        // mutable access records the change tick
+        let tick = world.increment_change_tick();
+        for position in world.query_mut::<&mut Position>() {
+            position.0 += 10;
+        }
+        let storage = world.component_storage::<Position>().unwrap();
+        assert_eq!(storage.changed_tick(a), Some(tick));
+        assert_eq!(storage.get(b), Some(&Position(12)));
+
+        // moving entities between tables keeps the component in the storage
+        world.get_entity_world_mut(a).unwrap().insert(Tag);
+        world.remove::<Tag>(b);
+        world.get_entity_world_mut(c).unwrap().insert(Position(3));
+        assert_eq!(stored(&world), 3);
+        assert_eq!(
+            world.get_entity(a).unwrap().get::<Position>(),
+            Some(&Position(11))
+        );
+        assert_eq!(
+            world.get_entity(b).unwrap().get::<Position>(),
+            Some(&Position(12))
+        );
+
+        // removing, taking and despawning removes it from the storage
+        assert_eq!(world.take::<Position>(a), Some(Position(11)));
+        world.remove::<Position>(b);
+        world.despawn(c);
+        assert_eq!(stored(&world), 0);
+
+        // clones are inserted into the storage
+        let d = world.spawn(Position(4)).id();
+        let e = world.clone_entity(d);
+        assert_eq!(
+            world.get_entity(e).unwrap().get::<Position>(),
+            Some(&Position(4))
+        );
+        assert_eq!(stored(&world), 2);
+
+        // components in custom storages can't be disabled
+        world.disable_component::<Position>(d);
+        assert!(world
+            .get_entity(d)
+            .unwrap()
+            .is_component_enabled::<Position>());
+
+        world.clear_entities();
+        assert_eq!(stored(&world), 0);
+
+        // the storage can't be changed once an entity had the component
+        assert!(matches!(
+            world.set_component_storage(MapStorage::default()),
+            Err(QuasarError::StorageInUse { .. })
+        ));
+        world.reset();
+        world.set_component_storage(MapStorage::default()).unwrap();
     }
 }
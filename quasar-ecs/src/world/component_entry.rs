@@ -0,0 +1,273 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+};
+
+use super::{
+    get_component,
+    get_component_mut,
+    EntityWorldMut,
+    FoundComponent,
+};
+use crate::{
+    component::{
+        Component,
+        MutableComponent,
+    },
+    entity::EntityLocation,
+};
+
+/// A view into a component of an entity, which the entity may or may not have.
+///
+/// Returned by [`EntityWorldMut::entry`].
+pub enum ComponentEntry<'a, 'w, C> {
+    Occupied(OccupiedComponentEntry<'a, 'w, C>),
+    /// The entity inherits the component from a prefab.
+    Inherited(InheritedComponentEntry<'a, 'w, C>),
+    Vacant(VacantComponentEntry<'a, 'w, C>),
+}
+
+impl<'a, 'w, C: Component> ComponentEntry<'a, 'w, C> {
+    pub(super) fn new(entity: &'a mut EntityWorldMut<'w>) -> Self {
+        match entity.find_component::<C>() {
+            FoundComponent::Missing => {
+                Self::Vacant(VacantComponentEntry {
+                    entity,
+                    _component: PhantomData,
+                })
+            }
+            FoundComponent::Owned => {
+                Self::Occupied(OccupiedComponentEntry {
+                    entity,
+                    _component: PhantomData,
+                })
+            }
+            FoundComponent::Inherited(owner_location) => {
+                Self::Inherited(InheritedComponentEntry {
+                    entity,
+                    owner_location,
+                    _component: PhantomData,
+                })
+            }
+        }
+    }
+
+    /// Inserts the component, and returns the value it replaced, if any. An
+    /// inherited component isn't replaced, since the prefab keeps it.
+    pub fn insert(self, value: C) -> (Option<C>, OccupiedComponentEntry<'a, 'w, C>) {
+        match self {
+            Self::Occupied(mut occupied_entry) => {
+                let old_value = occupied_entry.insert(value);
+                (Some(old_value), occupied_entry)
+            }
+            Self::Inherited(inherited_entry) => (None, inherited_entry.insert(value)),
+            Self::Vacant(vacant_entry) => {
+                let occupied_entry = vacant_entry.insert(value);
+                (None, occupied_entry)
+            }
+        }
+    }
+
+    /// Inserts the component if the entity doesn't have it itself. An
+    /// inherited component is overridden by the inserted one.
+    pub fn or_insert_with<F: FnOnce() -> C>(self, default: F) -> OccupiedComponentEntry<'a, 'w, C> {
+        match self {
+            Self::Occupied(occupied_entry) => occupied_entry,
+            Self::Inherited(inherited_entry) => inherited_entry.insert(default()),
+            Self::Vacant(vacant_entry) => vacant_entry.insert(default()),
+        }
+    }
+
+    pub fn or_insert(self, value: C) -> OccupiedComponentEntry<'a, 'w, C> {
+        self.or_insert_with(move || value)
+    }
+}
+
+impl<'a, 'w, C: MutableComponent> ComponentEntry<'a, 'w, C> {
+    /// Modifies the component, if the entity has it. An inherited component
+    /// is first cloned into the entity, and is left as is if it can't be
+    /// cloned.
+    pub fn and_modify<F: FnOnce(&mut C)>(self, f: F) -> Self {
+        match self {
+            Self::Occupied(mut occupied_entry) => {
+                f(occupied_entry.get_mut());
+                Self::Occupied(occupied_entry)
+            }
+            Self::Inherited(inherited_entry) => {
+                match inherited_entry.into_owned() {
+                    Ok(mut occupied_entry) => {
+                        f(occupied_entry.get_mut());
+                        Self::Occupied(occupied_entry)
+                    }
+                    Err(inherited_entry) => Self::Inherited(inherited_entry),
+                }
+            }
+            Self::Vacant(vacant_entry) => Self::Vacant(vacant_entry),
+        }
+    }
+}
+
+impl<'a, 'w, C: Component + Default> ComponentEntry<'a, 'w, C> {
+    pub fn or_default(self) -> OccupiedComponentEntry<'a, 'w, C> {
+        self.or_insert_with(Default::default)
+    }
+}
+
+impl<C: Component + Debug> Debug for ComponentEntry<'_, '_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Occupied(arg0) => f.debug_tuple("Occupied").field(arg0).finish(),
+            Self::Inherited(arg0) => f.debug_tuple("Inherited").field(arg0).finish(),
+            Self::Vacant(arg0) => f.debug_tuple("Vacant").field(arg0).finish(),
+        }
+    }
+}
+
+/// A component that the entity has.
+pub struct OccupiedComponentEntry<'a, 'w, C> {
+    entity: &'a mut EntityWorldMut<'w>,
+    _component: PhantomData<C>,
+}
+
+impl<'a, 'w, C: Component> OccupiedComponentEntry<'a, 'w, C> {
+    pub fn get(&self) -> &C {
+        get_component(
+            self.entity.location(),
+            &self.entity.world.components,
+            &self.entity.world.entities,
+            &self.entity.world.archetypes,
+            &self.entity.world.tables,
+        )
+        .expect("occupied entry without component")
+    }
+
+    /// Replaces the component, and returns the old value.
+    ///
+    /// Like [`EntityWorldMut::insert`], this runs the component's
+    /// `on_replace` hook, and also works for immutable components.
+    pub fn insert(&mut self, value: C) -> C {
+        self.entity
+            .insert_replacing(value)
+            .all()
+            .expect("occupied entry without component")
+    }
+
+    /// Removes the component from the entity.
+    pub fn remove(self) -> (C, VacantComponentEntry<'a, 'w, C>) {
+        let value = self
+            .entity
+            .take::<C>()
+            .expect("occupied entry without component");
+        let vacant_entry = VacantComponentEntry {
+            entity: self.entity,
+            _component: PhantomData,
+        };
+        (value, vacant_entry)
+    }
+}
+
+impl<'a, 'w, C: MutableComponent> OccupiedComponentEntry<'a, 'w, C> {
+    pub fn get_mut(&mut self) -> &mut C {
+        get_component_mut(
+            self.entity.location(),
+            &self.entity.world.components,
+            &mut self.entity.world.tables,
+        )
+        .expect("occupied entry without component")
+    }
+
+    pub fn into_mut(self) -> &'a mut C {
+        get_component_mut(
+            self.entity.location(),
+            &self.entity.world.components,
+            &mut self.entity.world.tables,
+        )
+        .expect("occupied entry without component")
+    }
+}
+
+impl<C: Component + Debug> Debug for OccupiedComponentEntry<'_, '_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OccupiedComponentEntry")
+            .field("entity", &self.entity.entity)
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+/// A component that the entity inherits from a prefab. It's only cloned into
+/// the entity when it's modified, so that the prefab and its other instances
+/// are not affected.
+pub struct InheritedComponentEntry<'a, 'w, C> {
+    entity: &'a mut EntityWorldMut<'w>,
+    owner_location: EntityLocation,
+    _component: PhantomData<C>,
+}
+
+impl<'a, 'w, C: Component> InheritedComponentEntry<'a, 'w, C> {
+    /// Returns the inherited component.
+    pub fn get(&self) -> &C {
+        get_component(
+            self.entity.location(),
+            &self.entity.world.components,
+            &self.entity.world.entities,
+            &self.entity.world.archetypes,
+            &self.entity.world.tables,
+        )
+        .expect("inherited entry without component")
+    }
+
+    /// Inserts a component into the entity, which overrides the inherited
+    /// one.
+    pub fn insert(self, value: C) -> OccupiedComponentEntry<'a, 'w, C> {
+        self.entity.insert(value);
+        OccupiedComponentEntry {
+            entity: self.entity,
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'w, C: MutableComponent> InheritedComponentEntry<'a, 'w, C> {
+    /// Clones the inherited component into the entity, so that it can be
+    /// modified. Fails if the component can't be cloned.
+    pub fn into_owned(self) -> Result<OccupiedComponentEntry<'a, 'w, C>, Self> {
+        match self.entity.clone_inherited::<C>(self.owner_location) {
+            Some(value) => Ok(self.insert(value)),
+            None => Err(self),
+        }
+    }
+}
+
+impl<C: Component + Debug> Debug for InheritedComponentEntry<'_, '_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InheritedComponentEntry")
+            .field("entity", &self.entity.entity)
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+/// A component that the entity doesn't have.
+pub struct VacantComponentEntry<'a, 'w, C> {
+    entity: &'a mut EntityWorldMut<'w>,
+    _component: PhantomData<C>,
+}
+
+impl<'a, 'w, C: Component> VacantComponentEntry<'a, 'w, C> {
+    pub fn insert(self, value: C) -> OccupiedComponentEntry<'a, 'w, C> {
+        self.entity.insert(value);
+        OccupiedComponentEntry {
+            entity: self.entity,
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<C> Debug for VacantComponentEntry<'_, '_, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VacantComponentEntry")
+            .field("entity", &self.entity.entity)
+            .finish()
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+};
+
+use super::{
+    EntityMut,
+    EntityRef,
+    World,
+    WorldId,
+};
+use crate::{
+    archetype::Archetypes,
+    component::Components,
+    entity::{
+        Entities,
+        Entity,
+    },
+    error::EntityError,
+    resources::Resources,
+    storage::table::Tables,
+};
+
+/// A handle to a [`World`] through which its parts can be borrowed
+/// independently.
+///
+/// This is what safe abstractions that need to borrow different parts of the
+/// world at the same time (e.g. queries, or access to several entities) are
+/// built on. The cell itself doesn't track any borrows. Instead, its methods
+/// are unsafe, and the caller promises that the accesses made through it don't
+/// conflict, i.e. that nothing is borrowed mutably while it's also borrowed
+/// otherwise.
+///
+/// A cell created with [`World::as_unsafe_world_cell_readonly`] must only be
+/// used for shared access.
+#[derive(Clone, Copy)]
+pub struct UnsafeWorldCell<'w> {
+    world: *mut World,
+    _marker: PhantomData<(&'w World, &'w UnsafeCell<World>)>,
+}
+
+impl<'w> UnsafeWorldCell<'w> {
+    pub(super) fn new_mutable(world: &'w mut World) -> Self {
+        Self {
+            world,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(super) fn new_readonly(world: &'w World) -> Self {
+        Self {
+            world: std::ptr::from_ref(world).cast_mut(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(self) -> WorldId {
+        unsafe {
+            // SAFETY: The id never changes, so reading it can't conflict with any other
+            // access.
+            (*self.world).id
+        }
+    }
+
+    /// Returns the whole world.
+    ///
+    /// # Safety
+    ///
+    /// Nothing in the world may be borrowed mutably while the returned
+    /// reference is alive.
+    pub unsafe fn world(self) -> &'w World {
+        &*self.world
+    }
+
+    /// Returns the whole world mutably.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else in the world may be borrowed while the returned reference
+    /// is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn world_mut(self) -> &'w mut World {
+        &mut *self.world
+    }
+
+    /// # Safety
+    ///
+    /// The entities may not be borrowed mutably while the returned reference
+    /// is alive.
+    pub unsafe fn entities(self) -> &'w Entities {
+        &(*self.world).entities
+    }
+
+    /// # Safety
+    ///
+    /// The entities may not be borrowed otherwise while the returned reference
+    /// is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn entities_mut(self) -> &'w mut Entities {
+        &mut (*self.world).entities
+    }
+
+    /// # Safety
+    ///
+    /// The components may not be borrowed mutably while the returned reference
+    /// is alive.
+    pub unsafe fn components(self) -> &'w Components {
+        &(*self.world).components
+    }
+
+    /// # Safety
+    ///
+    /// The components may not be borrowed otherwise while the returned
+    /// reference is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn components_mut(self) -> &'w mut Components {
+        &mut (*self.world).components
+    }
+
+    /// # Safety
+    ///
+    /// The archetypes may not be borrowed mutably while the returned reference
+    /// is alive.
+    pub unsafe fn archetypes(self) -> &'w Archetypes {
+        &(*self.world).archetypes
+    }
+
+    /// # Safety
+    ///
+    /// The archetypes may not be borrowed otherwise while the returned
+    /// reference is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn archetypes_mut(self) -> &'w mut Archetypes {
+        &mut (*self.world).archetypes
+    }
+
+    /// # Safety
+    ///
+    /// The tables may not be borrowed mutably while the returned reference is
+    /// alive. Components that are accessed through the returned reference may
+    /// not be borrowed mutably either.
+    pub unsafe fn tables(self) -> &'w Tables {
+        &(*self.world).tables
+    }
+
+    /// # Safety
+    ///
+    /// The tables may not be borrowed otherwise while the returned reference
+    /// is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn tables_mut(self) -> &'w mut Tables {
+        &mut (*self.world).tables
+    }
+
+    /// # Safety
+    ///
+    /// The resources may not be borrowed mutably while the returned reference
+    /// is alive.
+    pub unsafe fn resources(self) -> &'w Resources {
+        &(*self.world).resources
+    }
+
+    /// # Safety
+    ///
+    /// The resources may not be borrowed otherwise while the returned
+    /// reference is alive, and the cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn resources_mut(self) -> &'w mut Resources {
+        &mut (*self.world).resources
+    }
+
+    /// Returns read access to an entity.
+    ///
+    /// # Safety
+    ///
+    /// The entities, components, archetypes and tables may not be borrowed
+    /// mutably while the returned [`EntityRef`] is alive, except for
+    /// components of other entities.
+    pub unsafe fn get_entity(self, entity: Entity) -> Result<EntityRef<'w>, EntityError> {
+        let entity_location = self.entities().check(entity)?;
+        Ok(EntityRef {
+            components: self.components(),
+            entities: self.entities(),
+            archetypes: self.archetypes(),
+            tables: self.tables(),
+            entity,
+            entity_location,
+        })
+    }
+
+    /// Returns mutable access to the components of an entity.
+    ///
+    /// # Safety
+    ///
+    /// The entities, components, archetypes and tables may not be borrowed
+    /// mutably while the returned [`EntityMut`] is alive, and the components of
+    /// this entity may not be borrowed at all, except through the
+    /// [`EntityMut`]. The cell must have been created with
+    /// [`World::as_unsafe_world_cell`].
+    pub unsafe fn get_entity_mut(self, entity: Entity) -> Result<EntityMut<'w>, EntityError> {
+        let entity_location = self.entities().check(entity)?;
+        Ok(EntityMut {
+            components: self.components(),
+            entities: self.entities(),
+            archetypes: self.archetypes(),
+            tables: self.tables(),
+            entity,
+            entity_location,
+        })
+    }
+}
+
+impl std::fmt::Debug for UnsafeWorldCell<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UnsafeWorldCell").field(&self.id()).finish()
+    }
+}
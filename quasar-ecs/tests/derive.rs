@@ -0,0 +1,13 @@
+#[test]
+fn derive_bundle() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/bundle/pass_*.rs");
+    cases.compile_fail("tests/ui/bundle/fail_*.rs");
+}
+
+#[test]
+fn derive_component() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/component/pass_*.rs");
+    cases.compile_fail("tests/ui/component/fail_*.rs");
+}
@@ -0,0 +1,9 @@
+use quasar_ecs::Bundle;
+
+#[derive(Bundle)]
+enum NotABundle {
+    A,
+    B,
+}
+
+fn main() {}
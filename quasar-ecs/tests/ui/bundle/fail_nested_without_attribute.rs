@@ -0,0 +1,19 @@
+use quasar_ecs::{
+    Bundle,
+    Component,
+};
+
+#[derive(Component)]
+struct Position(f32, f32);
+
+#[derive(Bundle)]
+struct Inner {
+    position: Position,
+}
+
+#[derive(Bundle)]
+struct Outer {
+    inner: Inner,
+}
+
+fn main() {}
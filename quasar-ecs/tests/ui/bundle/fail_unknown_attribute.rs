@@ -0,0 +1,15 @@
+use quasar_ecs::{
+    Bundle,
+    Component,
+};
+
+#[derive(Component)]
+struct Position(f32, f32);
+
+#[derive(Bundle)]
+struct Movement {
+    #[quasar(component)]
+    position: Position,
+}
+
+fn main() {}
@@ -0,0 +1,42 @@
+use quasar_ecs::{
+    Bundle,
+    Component,
+    World,
+};
+
+#[derive(Debug, PartialEq, Component)]
+struct Position(f32, f32);
+
+#[derive(Debug, PartialEq, Component)]
+struct Health(u32);
+
+#[derive(Debug, PartialEq, Bundle)]
+struct WithPosition<B> {
+    position: Position,
+    #[quasar(bundle)]
+    rest: B,
+}
+
+#[derive(Debug, PartialEq, Bundle)]
+struct Unit {
+    #[quasar(bundle)]
+    base: WithPosition<(Health,)>,
+}
+
+fn main() {
+    let mut world = World::new();
+    let mut entity = world.spawn(Unit {
+        base: WithPosition {
+            position: Position(1.0, 2.0),
+            rest: (Health(10),),
+        },
+    });
+    assert_eq!(entity.get::<Health>(), Some(&Health(10)));
+    assert_eq!(
+        entity.take::<WithPosition<Health>>(),
+        Some(WithPosition {
+            position: Position(1.0, 2.0),
+            rest: Health(10),
+        })
+    );
+}
@@ -0,0 +1,33 @@
+use quasar_ecs::{
+    Bundle,
+    Component,
+    World,
+};
+
+#[derive(Debug, PartialEq, Component)]
+struct Position(f32, f32);
+
+#[derive(Debug, PartialEq, Component)]
+struct Velocity(f32, f32);
+
+#[derive(Debug, PartialEq, Bundle)]
+struct Movement {
+    position: Position,
+    velocity: Velocity,
+}
+
+fn main() {
+    let mut world = World::new();
+    let mut entity = world.spawn(Movement {
+        position: Position(1.0, 2.0),
+        velocity: Velocity(3.0, 4.0),
+    });
+    assert_eq!(entity.get::<Position>(), Some(&Position(1.0, 2.0)));
+    assert_eq!(
+        entity.take::<Movement>(),
+        Some(Movement {
+            position: Position(1.0, 2.0),
+            velocity: Velocity(3.0, 4.0),
+        })
+    );
+}
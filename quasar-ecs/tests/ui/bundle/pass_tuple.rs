@@ -0,0 +1,28 @@
+use quasar_ecs::{
+    Bundle,
+    Component,
+    World,
+};
+
+#[derive(Debug, PartialEq, Component)]
+struct Position(f32, f32);
+
+#[derive(Debug, PartialEq, Component)]
+struct Velocity(f32, f32);
+
+#[derive(Debug, PartialEq, Bundle)]
+struct Movement(Velocity, Position);
+
+#[derive(Debug, PartialEq, Bundle)]
+struct Empty;
+
+fn main() {
+    let mut world = World::new();
+    let mut entity = world.spawn(Movement(Velocity(3.0, 4.0), Position(1.0, 2.0)));
+    entity.insert(Empty);
+    assert_eq!(
+        entity.take::<Movement>(),
+        Some(Movement(Velocity(3.0, 4.0), Position(1.0, 2.0)))
+    );
+    assert_eq!(entity.take::<Empty>(), Some(Empty));
+}
@@ -0,0 +1,12 @@
+use quasar_ecs::{
+    Component,
+    World,
+};
+
+fn on_add(_world: &mut World) {}
+
+#[derive(Component)]
+#[quasar(on_add = on_add)]
+struct Sprite;
+
+fn main() {}
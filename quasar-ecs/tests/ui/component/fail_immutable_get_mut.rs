@@ -0,0 +1,14 @@
+use quasar_ecs::{
+    Component,
+    World,
+};
+
+#[derive(Component)]
+#[quasar(immutable)]
+struct NetworkId(u64);
+
+fn main() {
+    let mut world = World::new();
+    let mut entity = world.spawn(NetworkId(1));
+    entity.get_mut::<NetworkId>().unwrap().0 = 2;
+}
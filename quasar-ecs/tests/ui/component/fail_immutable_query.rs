@@ -0,0 +1,16 @@
+use quasar_ecs::{
+    Component,
+    World,
+};
+
+#[derive(Component)]
+#[quasar(immutable)]
+struct NetworkId(u64);
+
+fn main() {
+    let mut world = World::new();
+    world.spawn(NetworkId(1));
+    for network_id in world.query_mut::<&mut NetworkId>() {
+        network_id.0 = 2;
+    }
+}
@@ -0,0 +1,12 @@
+use quasar_ecs::{
+    Component,
+    World,
+};
+
+#[derive(Clone, PartialEq, Eq, Hash, Component)]
+struct NetworkId(u64);
+
+fn main() {
+    let mut world = World::new();
+    world.add_hash_index::<NetworkId>();
+}
@@ -0,0 +1,7 @@
+use quasar_ecs::Component;
+
+#[derive(Default, Component)]
+#[quasar(require(Sprite))]
+struct Sprite;
+
+fn main() {}
@@ -0,0 +1,10 @@
+use quasar_ecs::Component;
+
+#[derive(Default, Component)]
+struct Transform;
+
+#[derive(Component)]
+#[quasar(require(Transform), require(Transform = || Transform))]
+struct Sprite;
+
+fn main() {}
@@ -0,0 +1,10 @@
+use quasar_ecs::Component;
+
+#[derive(Component)]
+struct Transform;
+
+#[derive(Component)]
+#[quasar(require(Transform))]
+struct Sprite;
+
+fn main() {}
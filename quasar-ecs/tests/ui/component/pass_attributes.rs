@@ -0,0 +1,32 @@
+use quasar_ecs::{
+    Component,
+    ComponentId,
+    Entity,
+    World,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Component)]
+struct Transform(f32, f32);
+
+#[derive(Clone, Debug, PartialEq, Component)]
+struct Visibility(bool);
+
+fn default_visibility() -> Visibility {
+    Visibility(true)
+}
+
+fn on_add(_world: &mut World, _entity: Entity, _component_id: ComponentId) {}
+
+fn on_remove(_world: &mut World, _entity: Entity, _component_id: ComponentId) {}
+
+#[derive(Clone, Debug, PartialEq, Component)]
+#[quasar(clone, immutable, storage = "table")]
+#[quasar(on_add = on_add, on_remove = on_remove)]
+#[quasar(require(Transform, Visibility = default_visibility))]
+struct Sprite(u32);
+
+fn main() {
+    let mut world = World::new();
+    let entity = world.spawn(Sprite(1));
+    assert_eq!(entity.get::<Sprite>(), Some(&Sprite(1)));
+}